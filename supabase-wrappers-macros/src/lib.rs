@@ -6,11 +6,18 @@ use syn::{parse_macro_input, punctuated::Punctuated, ItemStruct, Lit, MetaNameVa
 
 /// Create necessary handler, validator and meta functions for foreign data wrapper
 ///
-/// This macro will create three functions which can be used in Postgres.
+/// This macro will create below functions which can be used in Postgres.
 ///
 /// 1. `<snake_case_fdw_name>_fdw_handler()` - foreign data wrapper handler function
 /// 2. `<snake_case_fdw_name>_fdw_validator()` - foreign data wrapper validator function
 /// 3. `<snake_case_fdw_name>_fdw_meta()` - function to return a table contains fdw metadata
+/// 4. `<snake_case_fdw_name>_fdw_get_connections()` - function to return a table contains
+///    foreign server and user names of the connections cached in current session
+/// 5. `<snake_case_fdw_name>_fdw_disconnect(server_name)` - function to drop the cached
+///    connections to the foreign server
+/// 6. `<snake_case_fdw_name>_fdw_disconnect_all()` - function to drop all the cached connections
+/// 7. `<snake_case_fdw_name>_fdw_options()` - function to return a table contains the options
+///    accepted by the foreign data wrapper
 ///
 /// # Example
 ///
@@ -61,6 +68,10 @@ pub fn wrappers_fdw(attr: TokenStream, item: TokenStream) -> TokenStream {
     let fn_ident = format_ident!("{}_handler", ident_snake);
     let fn_validator_ident = format_ident!("{}_validator", ident_snake);
     let fn_meta_ident = format_ident!("{}_meta", ident_snake);
+    let fn_get_connections_ident = format_ident!("{}_get_connections", ident_snake);
+    let fn_disconnect_ident = format_ident!("{}_disconnect", ident_snake);
+    let fn_disconnect_all_ident = format_ident!("{}_disconnect_all", ident_snake);
+    let fn_options_ident = format_ident!("{}_options", ident_snake);
 
     let quoted = quote! {
         #item_tokens
@@ -78,7 +89,9 @@ pub fn wrappers_fdw(attr: TokenStream, item: TokenStream) -> TokenStream {
 
             #[pg_extern(create_or_replace)]
             fn #fn_validator_ident(options: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) {
-                #ident::validator(options, catalog)
+                if let Err(err) = #ident::validator(options, catalog) {
+                    err.report();
+                }
             }
 
             #[pg_extern(create_or_replace)]
@@ -99,6 +112,54 @@ pub fn wrappers_fdw(attr: TokenStream, item: TokenStream) -> TokenStream {
                     meta.get("website").map(|s| s.to_owned()),
                 )].into_iter())
             }
+
+            #[pg_extern(create_or_replace)]
+            fn #fn_get_connections_ident() -> TableIterator<'static, (
+                name!(server_name, String),
+                name!(user_name, String)
+            )> {
+                TableIterator::new(
+                    supabase_wrappers::connection::get_connections::<#ident>().into_iter()
+                )
+            }
+
+            #[pg_extern(create_or_replace)]
+            fn #fn_disconnect_ident(server_name: &str) -> bool {
+                supabase_wrappers::connection::disconnect::<#ident>(Some(server_name))
+            }
+
+            #[pg_extern(create_or_replace)]
+            fn #fn_disconnect_all_ident() -> bool {
+                supabase_wrappers::connection::disconnect::<#ident>(None)
+            }
+
+            #[pg_extern(create_or_replace)]
+            fn #fn_options_ident() -> TableIterator<'static, (
+                name!(name, String),
+                name!(catalogs, String),
+                name!(value_type, String),
+                name!(required, bool),
+                name!(default_value, Option<String>),
+                name!(secret, bool),
+                name!(description, String)
+            )> {
+                let options = supabase_wrappers::options::all_options(#ident::options())
+                    .map(|def| (
+                        def.name.to_owned(),
+                        def.catalogs
+                            .iter()
+                            .map(|catalog| catalog.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        def.kind.to_string(),
+                        def.required,
+                        def.default.map(|s| s.to_owned()),
+                        def.secret,
+                        def.description.to_owned(),
+                    ))
+                    .collect::<Vec<_>>();
+                TableIterator::new(options.into_iter())
+            }
         }
 
     };
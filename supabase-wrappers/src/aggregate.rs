@@ -0,0 +1,214 @@
+use crate::interface::{Aggregate, AggregateFunc, Cell, Column, HavingQual, Value};
+use crate::qual::{get_operator, unnest_clause};
+use pgx::{is_a, pg_sys, FromDatum, PgList};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+// extract aggregate function from an Aggref node, only built-in aggregate
+// function with no argument or a single column argument is supported
+unsafe fn extract_aggregate_func(
+    aggref: *mut pg_sys::Aggref,
+    baserel_id: pg_sys::Oid,
+) -> Option<AggregateFunc> {
+    // AGGKIND_NORMAL is 'n', ordered-set and hypothetical aggregates are not supported
+    if (*aggref).aggkind != b'n' as c_char
+        || (*aggref).agglevelsup != 0
+        || (*aggref).aggsplit != pg_sys::AggSplit_AGGSPLIT_SIMPLE
+        || (*aggref).aggvariadic
+        || !(*aggref).aggorder.is_null()
+        || !(*aggref).aggfilter.is_null()
+        || (*aggref).aggfnoid >= pg_sys::FirstNormalObjectId
+    {
+        return None;
+    }
+
+    let name = pg_sys::get_func_name((*aggref).aggfnoid);
+    if name.is_null() {
+        return None;
+    }
+
+    let column = if (*aggref).aggstar {
+        None
+    } else {
+        let args = PgList::<pg_sys::TargetEntry>::from_pg((*aggref).args);
+        if args.len() != 1 {
+            return None;
+        }
+        let var = unnest_clause((*args.head().unwrap()).expr as _) as *mut pg_sys::Var;
+        if !is_a(var as _, pg_sys::NodeTag_T_Var) || (*var).varattno < 1 {
+            return None;
+        }
+        let field = pg_sys::get_attname(baserel_id, (*var).varattno, false);
+        Some(CStr::from_ptr(field).to_str().unwrap().to_string())
+    };
+
+    Some(AggregateFunc {
+        name: CStr::from_ptr(name).to_str().unwrap().to_string(),
+        column,
+        distinct: !(*aggref).aggdistinct.is_null(),
+        type_oid: (*aggref).aggtype,
+    })
+}
+
+// extract HAVING condition, only binary operator between an aggregate
+// function and a constant is supported
+unsafe fn extract_having_qual(
+    expr: *mut pg_sys::Node,
+    baserel_id: pg_sys::Oid,
+) -> Option<HavingQual> {
+    if !is_a(expr, pg_sys::NodeTag_T_OpExpr) {
+        return None;
+    }
+
+    let expr = expr as *mut pg_sys::OpExpr;
+    let args: PgList<pg_sys::Node> = PgList::from_pg((*expr).args);
+
+    // only deal with binary operator
+    if args.len() != 2 {
+        return None;
+    }
+
+    // get operator
+    let mut opr = get_operator((*expr).opno);
+    if opr.is_null() {
+        return None;
+    }
+
+    let mut left = unnest_clause(args.head().unwrap());
+    let mut right = unnest_clause(args.tail().unwrap());
+
+    // swap operands if needed
+    if is_a(right, pg_sys::NodeTag_T_Aggref) && is_a(left, pg_sys::NodeTag_T_Const) {
+        if (*opr).oprcom == 0 {
+            return None;
+        }
+        opr = get_operator((*opr).oprcom);
+        if opr.is_null() {
+            return None;
+        }
+        std::mem::swap(&mut left, &mut right);
+    }
+
+    if !is_a(left, pg_sys::NodeTag_T_Aggref) || !is_a(right, pg_sys::NodeTag_T_Const) {
+        return None;
+    }
+
+    let func = extract_aggregate_func(left as _, baserel_id)?;
+    let right = right as *mut pg_sys::Const;
+    let value = Cell::from_polymorphic_datum(
+        (*right).constvalue,
+        (*right).constisnull,
+        (*right).consttype,
+    )?;
+
+    Some(HavingQual {
+        func,
+        operator: pgx::name_data_to_str(&(*opr).oprname).to_string(),
+        value: Value::Cell(value),
+    })
+}
+
+// extract aggregation on a base relation, its quals and options are not
+// filled in. Also return the target list of the aggregate scan, which is the
+// grouping columns followed by the aggregate functions.
+pub(crate) unsafe fn extract_aggregate(
+    root: *mut pg_sys::PlannerInfo,
+    baserel: *mut pg_sys::RelOptInfo,
+    extra: *mut pg_sys::GroupPathExtraData,
+) -> Option<(Aggregate, *mut pg_sys::List)> {
+    let parse = (*root).parse;
+
+    // grouping sets are not supported
+    if !(*parse).groupingSets.is_null() {
+        return None;
+    }
+
+    let rte = pg_sys::planner_rt_fetch((*baserel).relid, root);
+    let baserel_id = (*rte).relid;
+
+    let mut aggregate = Aggregate::default();
+    let mut group_nodes = Vec::new();
+    let mut agg_nodes = Vec::new();
+    let mut other_vars = Vec::new();
+
+    let target = (*root).upper_targets[pg_sys::UpperRelationKind_UPPERREL_GROUP_AGG as usize];
+    let exprs = PgList::<pg_sys::Node>::from_pg((*target).exprs);
+    for (i, expr) in exprs.iter_ptr().enumerate() {
+        let sgref = if (*target).sortgrouprefs.is_null() {
+            0
+        } else {
+            *(*target).sortgrouprefs.add(i)
+        };
+
+        if sgref > 0
+            && !pg_sys::get_sortgroupref_clause_noerr(sgref, (*parse).groupClause).is_null()
+        {
+            // grouping expression, only plain column is supported
+            let var = expr as *mut pg_sys::Var;
+            if !is_a(expr, pg_sys::NodeTag_T_Var) || (*var).varattno < 1 {
+                return None;
+            }
+            let field = pg_sys::get_attname(baserel_id, (*var).varattno, false);
+            aggregate.group_by.push(Column {
+                name: CStr::from_ptr(field).to_str().unwrap().to_owned(),
+                num: (*var).varattno as usize,
+                type_oid: (*var).vartype,
+                typmod: (*var).vartypmod,
+            });
+            group_nodes.push(expr);
+        } else {
+            // other expressions are computed locally from the grouping
+            // columns and aggregate functions
+            let nodes = pg_sys::pull_var_clause(
+                expr,
+                (pg_sys::PVC_INCLUDE_AGGREGATES | pg_sys::PVC_INCLUDE_PLACEHOLDERS) as c_int,
+            );
+            let nodes = PgList::<pg_sys::Node>::from_pg(nodes);
+            for node in nodes.iter_ptr() {
+                if is_a(node, pg_sys::NodeTag_T_Var) {
+                    other_vars.push(node as *mut pg_sys::Var);
+                } else if is_a(node, pg_sys::NodeTag_T_Aggref) {
+                    let func = extract_aggregate_func(node as _, baserel_id)?;
+                    aggregate.funcs.push(func);
+                    agg_nodes.push(node);
+                } else {
+                    return None;
+                }
+            }
+        }
+    }
+
+    // columns used outside of aggregate functions must be grouping columns
+    if !other_vars.iter().all(|var| {
+        aggregate
+            .group_by
+            .iter()
+            .any(|col| col.num == (**var).varattno as usize)
+    }) {
+        return None;
+    }
+
+    // extract HAVING clause, which is an implicit-AND list
+    if !extra.is_null() && !(*extra).havingQual.is_null() {
+        let conds = PgList::<pg_sys::Node>::from_pg((*extra).havingQual as _);
+        for cond in conds.iter_ptr() {
+            aggregate
+                .having
+                .push(extract_having_qual(cond, baserel_id)?);
+        }
+    }
+
+    let mut tlist = PgList::<pg_sys::TargetEntry>::new();
+    for node in group_nodes.into_iter().chain(agg_nodes) {
+        let tle = pg_sys::makeTargetEntry(
+            pg_sys::copyObjectImpl(node as _) as _,
+            (tlist.len() + 1) as pg_sys::AttrNumber,
+            ptr::null_mut(),
+            false,
+        );
+        tlist.push(tle);
+    }
+
+    Some((aggregate, tlist.into_pg()))
+}
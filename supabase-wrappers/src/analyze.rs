@@ -0,0 +1,105 @@
+use pgx::{debug2, memcxt::PgMemoryContexts, pg_sys::Datum, prelude::*, tupdesc::PgTupleDesc};
+use std::os::raw::c_int;
+
+use crate::instance;
+use crate::interface::{cell_into_datum, Column};
+use crate::prelude::ForeignDataWrapper;
+use crate::utils::{self, report_error, ReportUnwrap};
+
+#[pg_guard]
+pub(super) extern "C" fn analyze_foreign_table<W: ForeignDataWrapper>(
+    relation: pg_sys::Relation,
+    func: *mut pg_sys::AcquireSampleRowsFunc,
+    totalpages: *mut pg_sys::BlockNumber,
+) -> bool {
+    debug2!("---> analyze_foreign_table");
+    unsafe {
+        let ftable = pg_sys::GetForeignTable((*relation).rd_id);
+        let opts = utils::options_to_hashmap((*ftable).options);
+        if !W::can_analyze(&opts) {
+            return false;
+        }
+
+        *func = Some(acquire_sample_rows::<W>);
+
+        // foreign table has no local pages, but a non-zero page count tells
+        // the planner the table has been analyzed
+        *totalpages = 1;
+
+        true
+    }
+}
+
+#[pg_guard]
+extern "C" fn acquire_sample_rows<W: ForeignDataWrapper>(
+    relation: pg_sys::Relation,
+    _elevel: c_int,
+    rows: *mut pg_sys::HeapTuple,
+    targrows: c_int,
+    totalrows: *mut f64,
+    totaldeadrows: *mut f64,
+) -> c_int {
+    debug2!("---> acquire_sample_rows");
+    unsafe {
+        let relid = (*relation).rd_id;
+        let ftable = pg_sys::GetForeignTable(relid);
+        let opts = utils::options_to_hashmap((*ftable).options);
+        let mut instance = instance::create_fdw_instance::<W>(relid);
+
+        let tup_desc = PgTupleDesc::from_pg_copy((*relation).rd_att);
+        let columns: Vec<Column> = tup_desc
+            .iter()
+            .filter(|a| !a.attisdropped)
+            .map(|a| Column {
+                name: pgx::name_data_to_str(&a.attname).to_owned(),
+                num: a.attnum as usize,
+                type_oid: a.atttypid,
+                typmod: a.atttypmod,
+            })
+            .collect();
+
+        let (total, sample) = instance
+            .analyze(&columns, targrows as usize, &opts)
+            .report_unwrap();
+
+        // sample rows are formed in the caller's memory context, the cell
+        // datums are only needed until then
+        let natts = (*(*relation).rd_att).natts as usize;
+        let mut tmp_ctx = PgMemoryContexts::new("Wrappers analyze data");
+        let mut numrows = 0;
+        for row in sample.into_iter().take(targrows as usize) {
+            let mut values = vec![Datum::from(0); natts];
+            let mut nulls = vec![true; natts];
+            let mut old_ctx = tmp_ctx.set_as_current();
+            for (col_name, cell) in row.cols.iter().zip(row.cells) {
+                let col = match columns.iter().find(|col| &col.name == col_name) {
+                    Some(col) => col,
+                    None => {
+                        report_error(
+                            PgSqlErrorCode::ERRCODE_FDW_COLUMN_NAME_NOT_FOUND,
+                            &format!("column '{}' not found", col_name),
+                        );
+                        continue;
+                    }
+                };
+                if let Some(cell) = cell {
+                    values[col.num - 1] = cell_into_datum(cell, col).report_unwrap();
+                    nulls[col.num - 1] = false;
+                }
+            }
+            old_ctx.set_as_current();
+            *rows.add(numrows) = pg_sys::heap_form_tuple(
+                (*relation).rd_att,
+                values.as_mut_ptr(),
+                nulls.as_mut_ptr(),
+            );
+            numrows += 1;
+            tmp_ctx.reset();
+        }
+
+        *totalrows = (total as f64).max(numrows as f64);
+        *totaldeadrows = 0.0;
+
+        numrows as c_int
+    }
+}
@@ -0,0 +1,220 @@
+//! Per-backend cache of FDW connections
+//!
+//! Creating a connection to the foreign source, such as a database client or
+//! an OAuth token exchange, is often the most expensive part of a query. A FDW
+//! can keep it in this cache by creating it with [`cached_connection`] in its
+//! `new()` function, so the following queries in the same backend reuse it.
+//!
+//! For example,
+//!
+//! ```rust,no_run
+//! fn new(options: &HashMap<String, String>) -> FdwResult<Self> {
+//!     let conn = cached_connection(|| {
+//!         let conn_str = require_option("conn_string", options)?;
+//!         MyClient::connect(&conn_str)
+//!     })?;
+//!     Ok(Self { conn })
+//! }
+//! ```
+//!
+//! Cached connections are keyed by the FDW, the foreign server and the current
+//! user, and are dropped when the foreign server or any user mapping is
+//! altered. They can also be listed and dropped by the SQL functions created
+//! by [`wrappers_fdw`](crate::wrappers_fdw) macro,
+//!
+//! ```sql
+//! select * from hello_world_fdw_get_connections();
+//! select hello_world_fdw_disconnect('my_helloworld_server');
+//! select hello_world_fdw_disconnect_all();
+//! ```
+use pgx::{debug2, prelude::*, IntoDatum};
+use std::any::{type_name, Any, TypeId};
+use std::cell::{Cell, RefCell};
+use std::ffi::CStr;
+use std::os::raw::c_int;
+use std::rc::Rc;
+
+use crate::interface::{FdwResult, ForeignDataWrapper};
+use crate::polyfill;
+
+// the FDW instance being created, its connections are cached under this key
+#[derive(Clone, Copy, PartialEq)]
+struct ConnectionKey {
+    fdw: &'static str,
+    server_oid: pg_sys::Oid,
+    user_oid: pg_sys::Oid,
+}
+
+struct ConnectionEntry {
+    key: ConnectionKey,
+
+    // type of the connection, a FDW can cache more than one connection
+    type_id: TypeId,
+
+    // syscache hash value of the foreign server, used for invalidation
+    server_hash: u32,
+
+    conn: Rc<dyn Any>,
+}
+
+thread_local! {
+    // true if the syscache callbacks are registered in this backend
+    static CALLBACKS_REGISTERED: Cell<bool> = const { Cell::new(false) };
+
+    // key of the FDW instance being created
+    static CURRENT_KEY: Cell<Option<ConnectionKey>> = const { Cell::new(None) };
+
+    // connections cached in this backend
+    static CONNECTIONS: RefCell<Vec<ConnectionEntry>> = const { RefCell::new(Vec::new()) };
+}
+
+// call the function with connections cached under the specified foreign
+// server and current user
+pub(crate) unsafe fn with_server<W: ForeignDataWrapper, R>(
+    server_oid: pg_sys::Oid,
+    f: impl FnOnce() -> R,
+) -> R {
+    let key = ConnectionKey {
+        fdw: type_name::<W>(),
+        server_oid,
+        user_oid: pg_sys::GetUserId(),
+    };
+    let _guard = KeyGuard(CURRENT_KEY.with(|k| k.replace(Some(key))));
+    f()
+}
+
+// restore the previous key even if the FDW instance creation failed
+struct KeyGuard(Option<ConnectionKey>);
+
+impl Drop for KeyGuard {
+    fn drop(&mut self) {
+        CURRENT_KEY.with(|k| k.set(self.0));
+    }
+}
+
+/// Get a connection from the cache, or create it by `create` if it is not
+/// cached yet
+///
+/// It should be called in `new()` function of the FDW, otherwise the
+/// connection is created without caching.
+pub fn cached_connection<C: 'static>(create: impl FnOnce() -> FdwResult<C>) -> FdwResult<Rc<C>> {
+    let key = match CURRENT_KEY.with(|k| k.get()) {
+        Some(key) => key,
+        None => return create().map(Rc::new),
+    };
+    let type_id = TypeId::of::<C>();
+
+    let cached = CONNECTIONS.with(|conns| {
+        conns
+            .borrow()
+            .iter()
+            .find(|entry| entry.key == key && entry.type_id == type_id)
+            .map(|entry| entry.conn.clone())
+    });
+    if let Some(conn) = cached {
+        return Ok(conn.downcast::<C>().unwrap());
+    }
+
+    let conn = Rc::new(create()?);
+    unsafe {
+        if !CALLBACKS_REGISTERED.with(|r| r.replace(true)) {
+            polyfill::cache_register_syscache_callback(
+                pg_sys::SysCacheIdentifier_FOREIGNSERVEROID as _,
+                Some(inval_callback),
+                0.into(),
+            );
+            polyfill::cache_register_syscache_callback(
+                pg_sys::SysCacheIdentifier_USERMAPPINGOID as _,
+                Some(inval_callback),
+                0.into(),
+            );
+        }
+
+        let server_hash = pg_sys::GetSysCacheHashValue(
+            pg_sys::SysCacheIdentifier_FOREIGNSERVEROID as _,
+            key.server_oid.into_datum().unwrap(),
+            0.into(),
+            0.into(),
+            0.into(),
+        );
+        CONNECTIONS.with(|conns| {
+            conns.borrow_mut().push(ConnectionEntry {
+                key,
+                type_id,
+                server_hash,
+                conn: conn.clone(),
+            })
+        });
+    }
+
+    Ok(conn)
+}
+
+// drop the cached connections when the foreign server or user mapping is
+// changed, FDW instances still using them are not affected
+#[pg_guard]
+extern "C" fn inval_callback(_arg: pg_sys::Datum, cache_id: c_int, hash_value: u32) {
+    debug2!("---> connection inval_callback");
+    CONNECTIONS.with(|conns| {
+        conns.borrow_mut().retain(|entry| {
+            // the user mapping hash value cannot tell which server it is for,
+            // so any change to user mappings drops all the connections
+            cache_id == pg_sys::SysCacheIdentifier_FOREIGNSERVEROID as c_int
+                && hash_value != 0
+                && entry.server_hash != hash_value
+        })
+    });
+}
+
+/// Get the foreign server and user names of connections cached by the FDW
+///
+/// Not to be used directly, use the SQL function created by
+/// [`wrappers_fdw`](crate::wrappers_fdw) macro instead.
+pub fn get_connections<W: ForeignDataWrapper>() -> Vec<(String, String)> {
+    let keys = CONNECTIONS.with(|conns| {
+        let mut keys = Vec::new();
+        for entry in conns.borrow().iter() {
+            if entry.key.fdw == type_name::<W>() && !keys.contains(&entry.key) {
+                keys.push(entry.key);
+            }
+        }
+        keys
+    });
+    keys.iter()
+        .map(|key| unsafe {
+            let server = pg_sys::GetForeignServer(key.server_oid);
+            let user = pg_sys::GetUserNameFromId(key.user_oid, true);
+            (
+                CStr::from_ptr((*server).servername)
+                    .to_string_lossy()
+                    .into_owned(),
+                if user.is_null() {
+                    String::new()
+                } else {
+                    CStr::from_ptr(user).to_string_lossy().into_owned()
+                },
+            )
+        })
+        .collect()
+}
+
+/// Drop connections cached by the FDW for the foreign server, or all of them
+/// if the server is not specified, return true if any connection is dropped
+///
+/// Not to be used directly, use the SQL function created by
+/// [`wrappers_fdw`](crate::wrappers_fdw) macro instead.
+pub fn disconnect<W: ForeignDataWrapper>(server_name: Option<&str>) -> bool {
+    let server_oid = server_name.map(|name| unsafe {
+        let name = pgx::memcxt::PgMemoryContexts::CurrentMemoryContext.pstrdup(name);
+        (*pg_sys::GetForeignServerByName(name, false)).serverid
+    });
+    CONNECTIONS.with(|conns| {
+        let mut conns = conns.borrow_mut();
+        let len = conns.len();
+        conns.retain(|entry| {
+            entry.key.fdw != type_name::<W>()
+                || server_oid.is_some_and(|oid| entry.key.server_oid != oid)
+        });
+        conns.len() < len
+    })
+}
@@ -0,0 +1,685 @@
+//! SQL deparser for the FDWs which send queries to a remote SQL engine
+//!
+//! [`Deparser`] renders conditions, sorts and full statements in the
+//! [`Dialect`] of the remote engine. Identifiers are always quoted and
+//! literals are escaped according to their types, so values in the query
+//! cannot change the meaning of the SQL sent to the remote engine.
+//!
+//! ```rust,no_run
+//! # use supabase_wrappers::prelude::*;
+//! # fn deparse(quals: &[Qual], columns: &[Column]) -> FdwResult<String> {
+//! const DEPARSER: Deparser = Deparser::new(Dialect::ClickHouse);
+//!
+//! // select `id`, `name` from `people` where `name` = 'O\'Brien'
+//! DEPARSER.select("people", columns, quals, &[], &[], &None)
+//! # }
+//! ```
+
+use crate::interface::{
+    date_time_text, Aggregate, AggregateFunc, Cell, Column, FdwError, FdwResult, HavingQual, Join,
+    JoinQual, JoinRel, Limit, Qual, QualExpr, Row, Sort, Value,
+};
+use pgx::{pg_sys, PgSqlErrorCode};
+
+/// SQL dialect of a remote engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    /// ANSI SQL, which is also understood by Postgres
+    Ansi,
+
+    /// Google BigQuery standard SQL
+    BigQuery,
+
+    /// ClickHouse SQL
+    ClickHouse,
+
+    /// MySQL with the default SQL mode
+    MySql,
+}
+
+/// Operators which give the same result on the remote engine as on Postgres,
+/// see [`Deparser::is_remote_qual`]
+#[derive(Debug, Clone, Copy)]
+pub struct OperatorPolicy {
+    /// operators trusted for boolean and numeric values
+    pub ops: &'static [&'static str],
+
+    /// operators trusted for string values, ordering of strings depends on
+    /// collation so it is usually equality only
+    pub string_ops: &'static [&'static str],
+}
+
+impl Dialect {
+    // operators trusted by default, remote engines don't have the collations
+    // of Postgres
+    const fn operator_policy(self) -> OperatorPolicy {
+        OperatorPolicy {
+            ops: &["=", "<>", "<", "<=", ">", ">="],
+            string_ops: &["=", "<>"],
+        }
+    }
+}
+
+/// SQL deparser for a [`Dialect`]
+#[derive(Debug, Clone, Copy)]
+pub struct Deparser {
+    dialect: Dialect,
+    policy: OperatorPolicy,
+}
+
+fn unsupported_type(cell: &Cell, dialect: Dialect) -> FdwError {
+    FdwError::new(
+        PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+        format!("value {:?} is not supported by {:?} dialect", cell, dialect),
+    )
+}
+
+impl Deparser {
+    pub const fn new(dialect: Dialect) -> Self {
+        Self {
+            dialect,
+            policy: dialect.operator_policy(),
+        }
+    }
+
+    /// Set the operators trusted to give the same result on the remote
+    /// engine, instead of the default of the dialect
+    pub const fn operator_policy(mut self, policy: OperatorPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    // check if a condition gives the same result on the remote engine as on
+    // Postgres, only boolean, numeric and string values are trusted
+    fn is_remote_cond(&self, operator: &str, value: &Value) -> bool {
+        if operator == "is" || operator == "is not" {
+            return true;
+        }
+        let cells = match value {
+            Value::Cell(cell) => vec![cell],
+            Value::Array(cells) => cells.iter().collect(),
+        };
+        cells.iter().all(|cell| match cell {
+            Cell::String(_) => self.policy.string_ops.contains(&operator),
+            Cell::Bool(_)
+            | Cell::I8(_)
+            | Cell::I16(_)
+            | Cell::I32(_)
+            | Cell::I64(_)
+            | Cell::F32(_)
+            | Cell::F64(_)
+            | Cell::Numeric(_) => self.policy.ops.contains(&operator),
+            _ => false,
+        })
+    }
+
+    /// Check if a [`Qual`] gives the same result on the remote engine as on
+    /// Postgres, so it doesn't need to be checked again locally
+    pub fn is_remote_qual(&self, qual: &Qual) -> bool {
+        self.is_remote_cond(&qual.operator, &qual.value)
+    }
+
+    /// Check if all the leaf quals of a [`QualExpr`] are remote quals, see
+    /// [`is_remote_qual`](Self::is_remote_qual)
+    pub fn is_remote_qual_expr(&self, expr: &QualExpr) -> bool {
+        expr.quals()
+            .into_iter()
+            .all(|qual| self.is_remote_qual(qual))
+    }
+
+    /// Check if a [`HavingQual`] can be deparsed and gives the same result on
+    /// the remote engine as on Postgres
+    pub fn is_remote_having_qual(&self, qual: &HavingQual) -> bool {
+        self.is_remote_cond(&qual.operator, &qual.value) && self.having_qual(qual).is_ok()
+    }
+
+    /// Get the quals to be sent to the remote engine in a scan
+    ///
+    /// Remote quals are always sent, the others are only sent if they can be
+    /// deparsed, as Postgres checks them locally anyway.
+    pub fn sendable_quals(&self, quals: &[Qual]) -> Vec<Qual> {
+        quals
+            .iter()
+            .filter(|qual| self.is_remote_qual(qual) || self.qual(qual).is_ok())
+            .cloned()
+            .collect()
+    }
+
+    /// Quote an identifier, such as a column name
+    pub fn quote_ident(&self, ident: &str) -> String {
+        match self.dialect {
+            Dialect::Ansi => format!("\"{}\"", ident.replace('"', "\"\"")),
+            Dialect::BigQuery | Dialect::ClickHouse => {
+                format!("`{}`", ident.replace('\\', "\\\\").replace('`', "\\`"))
+            }
+            Dialect::MySql => format!("`{}`", ident.replace('`', "``")),
+        }
+    }
+
+    /// Quote a table name, which can be qualified by its schema or dataset
+    ///
+    /// A name starting with `(` is a sub query and returned as is, so is a
+    /// name already quoted by [`quote_ident`](Self::quote_ident).
+    pub fn quote_table(&self, table: &str) -> String {
+        let quote = match self.dialect {
+            Dialect::Ansi => '"',
+            Dialect::BigQuery | Dialect::ClickHouse | Dialect::MySql => '`',
+        };
+        if table.starts_with('(') || table.starts_with(quote) {
+            return table.to_owned();
+        }
+        match self.dialect {
+            // BigQuery accepts the whole table path as one identifier
+            Dialect::BigQuery => self.quote_ident(table),
+            _ => table
+                .split('.')
+                .map(|part| self.quote_ident(part))
+                .collect::<Vec<String>>()
+                .join("."),
+        }
+    }
+
+    /// Quote a string literal
+    pub fn quote_string(&self, s: &str) -> String {
+        let mut ret = String::with_capacity(s.len() + 2);
+        ret.push('\'');
+        match self.dialect {
+            Dialect::Ansi => ret.push_str(&s.replace('\'', "''")),
+            Dialect::BigQuery | Dialect::ClickHouse | Dialect::MySql => {
+                for c in s.chars() {
+                    match c {
+                        '\\' => ret.push_str("\\\\"),
+                        '\'' => ret.push_str("\\'"),
+                        '\n' => ret.push_str("\\n"),
+                        '\r' => ret.push_str("\\r"),
+                        '\t' => ret.push_str("\\t"),
+                        _ => ret.push(c),
+                    }
+                }
+            }
+        }
+        ret.push('\'');
+        ret
+    }
+
+    // date and time literal in ISO 8601 format and UTC, so it doesn't depend
+    // on the session settings. ANSI needs the type keyword while the other
+    // dialects coerce string literals to the column type.
+    fn typed_literal(&self, type_name: &str, cell: &Cell) -> FdwResult<String> {
+        let tz_suffix = match self.dialect {
+            Dialect::Ansi | Dialect::BigQuery => "+00",
+            Dialect::MySql => "+00:00",
+            // the time zone is given to toDateTime64() below
+            Dialect::ClickHouse => "",
+        };
+        let text =
+            date_time_text(cell, tz_suffix).ok_or_else(|| unsupported_type(cell, self.dialect))?;
+        // infinity and BC dates are Postgres only
+        if self.dialect != Dialect::Ansi && (text.ends_with("infinity") || text.ends_with(" BC")) {
+            return Err(unsupported_type(cell, self.dialect));
+        }
+        let text = self.quote_string(&text);
+        match (self.dialect, cell) {
+            (Dialect::Ansi, _) => Ok(format!("{} {}", type_name, text)),
+            (Dialect::ClickHouse, Cell::TimestampTz(_)) => {
+                Ok(format!("toDateTime64({}, 6, 'UTC')", text))
+            }
+            _ => Ok(text),
+        }
+    }
+
+    /// Deparse a cell to a literal
+    pub fn literal(&self, cell: &Cell) -> FdwResult<String> {
+        let lit = match cell {
+            Cell::Bool(v) => v.to_string(),
+            Cell::I8(v) => v.to_string(),
+            Cell::I16(v) => v.to_string(),
+            Cell::I32(v) => v.to_string(),
+            Cell::I64(v) => v.to_string(),
+            Cell::F32(v) if v.is_finite() => v.to_string(),
+            Cell::F64(v) if v.is_finite() => v.to_string(),
+            Cell::Numeric(v) => {
+                // NaN and infinity have no literal in most engines
+                let text = v.to_string();
+                if !text
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c == '-' || c == '.')
+                {
+                    return Err(unsupported_type(cell, self.dialect));
+                }
+                text
+            }
+            Cell::String(v) => self.quote_string(v),
+            Cell::Date(_) => self.typed_literal("date", cell)?,
+            Cell::Time(_) => self.typed_literal("time", cell)?,
+            Cell::Timestamp(_) => self.typed_literal("timestamp", cell)?,
+            Cell::TimestampTz(_) => self.typed_literal("timestamp with time zone", cell)?,
+            Cell::Interval(_) if self.dialect == Dialect::Ansi => {
+                self.typed_literal("interval", cell)?
+            }
+            Cell::Json(v) => self.quote_string(&v.0.to_string()),
+            Cell::Uuid(v) => self.quote_string(&v.to_string()),
+            Cell::Inet(v) => self.quote_string(&v.0),
+            Cell::Bytea(v) => {
+                let hex: String = v.iter().map(|b| format!("{:02x}", b)).collect();
+                match self.dialect {
+                    Dialect::Ansi | Dialect::MySql => format!("X'{}'", hex),
+                    Dialect::BigQuery => format!("from_hex('{}')", hex),
+                    Dialect::ClickHouse => format!("unhex('{}')", hex),
+                }
+            }
+            Cell::Array(v) if self.dialect != Dialect::MySql => {
+                let elems = v
+                    .iter()
+                    .map(|elem| match elem {
+                        Some(cell) => self.literal(cell),
+                        None => Ok("null".to_string()),
+                    })
+                    .collect::<FdwResult<Vec<String>>>()?;
+                match self.dialect {
+                    Dialect::Ansi => format!("array[{}]", elems.join(", ")),
+                    _ => format!("[{}]", elems.join(", ")),
+                }
+            }
+            _ => return Err(unsupported_type(cell, self.dialect)),
+        };
+        Ok(lit)
+    }
+
+    // map a Postgres operator to the SQL operator
+    fn operator(&self, operator: &str) -> FdwResult<&'static str> {
+        let op = match operator {
+            "=" => "=",
+            "<>" | "!=" => "<>",
+            "<" => "<",
+            "<=" => "<=",
+            ">" => ">",
+            ">=" => ">=",
+            "~~" => "like",
+            "!~~" => "not like",
+            _ => {
+                return Err(FdwError::new(
+                    PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+                    format!("operator {} is not supported", operator),
+                ))
+            }
+        };
+        Ok(op)
+    }
+
+    // deparse a condition whose left operand is already deparsed
+    fn cond(&self, lhs: &str, operator: &str, value: &Value, use_or: bool) -> FdwResult<String> {
+        if operator == "is" || operator == "is not" {
+            return match value {
+                Value::Cell(Cell::String(v)) if v == "null" => {
+                    Ok(format!("{} {} null", lhs, operator))
+                }
+                _ => Err(format!("invalid condition {} {} {:?}", lhs, operator, value).into()),
+            };
+        }
+
+        let op = self.operator(operator)?;
+        match value {
+            Value::Cell(cell) => Ok(format!("{} {} {}", lhs, op, self.literal(cell)?)),
+            Value::Array(cells) => {
+                let conds = cells
+                    .iter()
+                    .map(|cell| Ok(format!("{} {} {}", lhs, op, self.literal(cell)?)))
+                    .collect::<FdwResult<Vec<String>>>()?;
+                if conds.is_empty() {
+                    // `any` of an empty array is false, `all` of it is true
+                    return Ok(if use_or { "false" } else { "true" }.to_string());
+                }
+                let sep = if use_or { " or " } else { " and " };
+                Ok(format!("({})", conds.join(sep)))
+            }
+        }
+    }
+
+    /// Deparse a [`Qual`]
+    pub fn qual(&self, qual: &Qual) -> FdwResult<String> {
+        self.cond(
+            &self.quote_ident(&qual.field),
+            &qual.operator,
+            &qual.value,
+            qual.use_or,
+        )
+    }
+
+    /// Deparse a [`QualExpr`]
+    pub fn qual_expr(&self, expr: &QualExpr) -> FdwResult<String> {
+        let join = |exprs: &[QualExpr], sep: &str, empty: &str| -> FdwResult<String> {
+            if exprs.is_empty() {
+                return Ok(empty.to_string());
+            }
+            let conds = exprs
+                .iter()
+                .map(|expr| Ok(format!("({})", self.qual_expr(expr)?)))
+                .collect::<FdwResult<Vec<String>>>()?;
+            Ok(conds.join(sep))
+        };
+        match expr {
+            QualExpr::Qual(qual) => self.qual(qual),
+            QualExpr::And(exprs) => join(exprs, " and ", "true"),
+            QualExpr::Or(exprs) => join(exprs, " or ", "false"),
+            QualExpr::Not(expr) => Ok(format!("not ({})", self.qual_expr(expr)?)),
+        }
+    }
+
+    /// Deparse a [`HavingQual`]
+    pub fn having_qual(&self, qual: &HavingQual) -> FdwResult<String> {
+        self.cond(
+            &self.aggregate_func(&qual.func),
+            &qual.operator,
+            &qual.value,
+            false,
+        )
+    }
+
+    /// Deparse a [`JoinQual`] with the given outer and inner relation aliases
+    pub fn join_qual(
+        &self,
+        qual: &JoinQual,
+        outer_alias: &str,
+        inner_alias: &str,
+    ) -> FdwResult<String> {
+        Ok(format!(
+            "{}.{} {} {}.{}",
+            outer_alias,
+            self.quote_ident(&qual.outer_field),
+            self.operator(&qual.operator)?,
+            inner_alias,
+            self.quote_ident(&qual.inner_field)
+        ))
+    }
+
+    /// Deparse an [`AggregateFunc`]
+    pub fn aggregate_func(&self, func: &AggregateFunc) -> String {
+        match &func.column {
+            Some(column) if func.distinct => {
+                format!("{}(distinct {})", func.name, self.quote_ident(column))
+            }
+            Some(column) => format!("{}({})", func.name, self.quote_ident(column)),
+            None => format!("{}(*)", func.name),
+        }
+    }
+
+    /// Deparse a [`Sort`], its collation is ignored
+    pub fn sort(&self, sort: &Sort) -> String {
+        let field = self.quote_ident(&sort.field);
+        let dir = if sort.reversed { "desc" } else { "asc" };
+        match self.dialect {
+            // MySQL has no `nulls first` and sorts nulls as the lowest values
+            Dialect::MySql => {
+                let nulls_dir = if sort.nulls_first { "desc" } else { "asc" };
+                format!("{} is null {}, {} {}", field, nulls_dir, field, dir)
+            }
+            _ => {
+                let nulls = if sort.nulls_first { "first" } else { "last" };
+                format!("{} {} nulls {}", field, dir, nulls)
+            }
+        }
+    }
+
+    /// Deparse a [`Limit`]
+    pub fn limit(&self, limit: &Limit) -> String {
+        format!("limit {} offset {}", limit.count, limit.offset)
+    }
+
+    // deparse conditions combined with `and` to a `WHERE` clause with a
+    // leading space, or an empty string if there is no condition. `extra` are
+    // conditions already in the remote SQL.
+    fn where_clause(
+        &self,
+        quals: &[Qual],
+        exprs: &[QualExpr],
+        extra: &[String],
+    ) -> FdwResult<String> {
+        let conds = quals
+            .iter()
+            .map(|qual| self.qual(qual))
+            .chain(
+                exprs
+                    .iter()
+                    .map(|expr| Ok(format!("({})", self.qual_expr(expr)?))),
+            )
+            .chain(extra.iter().map(|cond| Ok(format!("({})", cond))))
+            .collect::<FdwResult<Vec<String>>>()?;
+        if conds.is_empty() {
+            return Ok(String::new());
+        }
+        Ok(format!(" where {}", conds.join(" and ")))
+    }
+
+    // join target list items, which are renamed to c1, c2, ... by their
+    // positions in BigQuery, as its result columns are read by names and the
+    // names can be duplicated in a join
+    fn target_list(&self, tgts: impl Iterator<Item = String>) -> String {
+        tgts.enumerate()
+            .map(|(i, tgt)| match self.dialect {
+                Dialect::BigQuery => format!("{} as c{}", tgt, i + 1),
+                _ => tgt,
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    // aggregate function in a target list. BigQuery result is cast to the
+    // type expected by Postgres, for example sum(bigint) returns numeric in
+    // Postgres but INT64 in BigQuery.
+    fn aggregate_target(&self, func: &AggregateFunc) -> String {
+        let sql = self.aggregate_func(func);
+        if self.dialect != Dialect::BigQuery {
+            return sql;
+        }
+        match func.type_oid {
+            pg_sys::INT8OID => format!("cast({} as int64)", sql),
+            pg_sys::FLOAT8OID => format!("cast({} as float64)", sql),
+            pg_sys::NUMERICOID => format!("cast({} as numeric)", sql),
+            _ => sql,
+        }
+    }
+
+    /// Deparse a `SELECT` statement
+    ///
+    /// - `table` - the table name or a sub query, see [`quote_table`](Self::quote_table)
+    /// - `columns` - target columns, all columns are selected if it is empty
+    /// - `quals`, `exprs` - conditions combined with `and`
+    /// - `sorts` - `ORDER BY` clause
+    /// - `limit` - `LIMIT` clause
+    pub fn select(
+        &self,
+        table: &str,
+        columns: &[Column],
+        quals: &[Qual],
+        exprs: &[QualExpr],
+        sorts: &[Sort],
+        limit: &Option<Limit>,
+    ) -> FdwResult<String> {
+        let mut sql = self.select_with_conds(table, columns, quals, exprs, &[])?;
+
+        if !sorts.is_empty() {
+            let order_by = sorts
+                .iter()
+                .map(|sort| self.sort(sort))
+                .collect::<Vec<String>>()
+                .join(", ");
+            sql.push_str(&format!(" order by {}", order_by));
+        }
+
+        if let Some(limit) = limit {
+            sql.push_str(&format!(" {}", self.limit(limit)));
+        }
+
+        Ok(sql)
+    }
+
+    /// Deparse a `SELECT` statement without sorts and limit, `conds` are extra
+    /// conditions in the remote SQL, such as the predicate of a partition in
+    /// parallel scan
+    ///
+    /// The `quals`, `exprs` and `conds` are combined with `and`, see
+    /// [`select`](Self::select) for the other arguments.
+    pub fn select_with_conds(
+        &self,
+        table: &str,
+        columns: &[Column],
+        quals: &[Qual],
+        exprs: &[QualExpr],
+        conds: &[String],
+    ) -> FdwResult<String> {
+        let tgts = if columns.is_empty() {
+            "*".to_string()
+        } else {
+            columns
+                .iter()
+                .map(|c| self.quote_ident(&c.name))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let mut sql = format!("select {} from {}", tgts, self.quote_table(table));
+        sql.push_str(&self.where_clause(quals, exprs, conds)?);
+        Ok(sql)
+    }
+
+    /// Deparse a `SELECT` statement of a [`Join`], each side of the join is
+    /// a sub query of its table
+    ///
+    /// - `outer_table`, `inner_table` - the table names or sub queries of the
+    ///   outer and inner relations, see [`quote_table`](Self::quote_table)
+    ///
+    /// The result columns are the outer columns followed by the inner columns.
+    /// They are named `c1`, `c2`, ... by their positions in BigQuery dialect.
+    pub fn join(&self, join: &Join, outer_table: &str, inner_table: &str) -> FdwResult<String> {
+        let tgts = join
+            .outer
+            .columns
+            .iter()
+            .map(|c| ("r1", c))
+            .chain(join.inner.columns.iter().map(|c| ("r2", c)))
+            .map(|(alias, c)| format!("{}.{}", alias, self.quote_ident(&c.name)));
+        let cond = join
+            .quals
+            .iter()
+            .map(|q| self.join_qual(q, "r1", "r2"))
+            .collect::<FdwResult<Vec<String>>>()?
+            .join(" and ");
+        let deparse_rel = |table: &str, rel: &JoinRel| {
+            self.select(table, &rel.columns, &rel.quals, &[], &[], &None)
+        };
+
+        let mut sql = format!(
+            "select {} from ({}) as r1",
+            self.target_list(tgts),
+            deparse_rel(outer_table, &join.outer)?
+        );
+        if cond.is_empty() {
+            sql.push_str(" cross join ");
+        } else {
+            sql.push_str(&format!(" {} ", join.join_type.deparse()));
+        }
+        sql.push_str(&format!(
+            "({}) as r2",
+            deparse_rel(inner_table, &join.inner)?
+        ));
+        if !cond.is_empty() {
+            sql.push_str(&format!(" on {}", cond));
+        }
+        Ok(sql)
+    }
+
+    /// Deparse a `SELECT` statement of an [`Aggregate`] on `table`, see
+    /// [`quote_table`](Self::quote_table)
+    ///
+    /// The result columns are the `group_by` columns followed by the aggregate
+    /// functions. They are named `c1`, `c2`, ... by their positions in BigQuery
+    /// dialect.
+    pub fn aggregate(&self, aggregate: &Aggregate, table: &str) -> FdwResult<String> {
+        let tgts = aggregate
+            .group_by
+            .iter()
+            .map(|c| self.quote_ident(&c.name))
+            .chain(aggregate.funcs.iter().map(|f| self.aggregate_target(f)));
+        let mut sql = format!(
+            "select {} from {}",
+            self.target_list(tgts),
+            self.quote_table(table)
+        );
+        sql.push_str(&self.where_clause(&aggregate.quals, &[], &[])?);
+        if !aggregate.group_by.is_empty() {
+            let group_by = match self.dialect {
+                // use ordinals to avoid conflicts between column names and aliases
+                Dialect::BigQuery => (1..=aggregate.group_by.len())
+                    .map(|i| i.to_string())
+                    .collect::<Vec<String>>(),
+                _ => aggregate
+                    .group_by
+                    .iter()
+                    .map(|c| self.quote_ident(&c.name))
+                    .collect(),
+            };
+            sql.push_str(&format!(" group by {}", group_by.join(", ")));
+        }
+        if !aggregate.having.is_empty() {
+            let cond = aggregate
+                .having
+                .iter()
+                .map(|q| self.having_qual(q))
+                .collect::<FdwResult<Vec<String>>>()?
+                .join(" and ");
+            sql.push_str(&format!(" having {}", cond));
+        }
+        Ok(sql)
+    }
+
+    /// Deparse an `UPDATE` statement which updates one row by its row id
+    ///
+    /// The row id column in `row` is not updated.
+    pub fn update(
+        &self,
+        table: &str,
+        row: &Row,
+        rowid_col: &str,
+        rowid: &Cell,
+    ) -> FdwResult<String> {
+        let sets = row
+            .iter()
+            .filter(|(col, _)| col.as_str() != rowid_col)
+            .map(|(col, cell)| {
+                let value = match cell {
+                    Some(cell) => self.literal(cell)?,
+                    None => "null".to_string(),
+                };
+                Ok(format!("{} = {}", self.quote_ident(col), value))
+            })
+            .collect::<FdwResult<Vec<String>>>()?
+            .join(", ");
+        let table = self.quote_table(table);
+        let cond = format!("{} = {}", self.quote_ident(rowid_col), self.literal(rowid)?);
+        let sql = match self.dialect {
+            Dialect::ClickHouse => format!("alter table {} update {} where {}", table, sets, cond),
+            _ => format!("update {} set {} where {}", table, sets, cond),
+        };
+        Ok(sql)
+    }
+
+    /// Deparse a `DELETE` statement which deletes one row by its row id
+    pub fn delete(&self, table: &str, rowid_col: &str, rowid: &Cell) -> FdwResult<String> {
+        let table = self.quote_table(table);
+        let cond = format!("{} = {}", self.quote_ident(rowid_col), self.literal(rowid)?);
+        let sql = match self.dialect {
+            Dialect::ClickHouse => format!("alter table {} delete where {}", table, cond),
+            _ => format!("delete from {} where {}", table, cond),
+        };
+        Ok(sql)
+    }
+
+    /// Deparse a `TRUNCATE` statement which deletes all rows of a table
+    pub fn truncate(&self, table: &str) -> String {
+        format!("truncate table {}", self.quote_table(table))
+    }
+}
@@ -1,12 +1,61 @@
 use crate::prelude::*;
 use pgx::prelude::*;
+use pgx::IntoDatum;
+use std::collections::HashMap;
 
-use super::utils;
+use super::connection;
+use super::utils::{self, ReportUnwrap};
 
-// create a fdw instance
+// attribute number of umoptions in pg_user_mapping catalog
+const ANUM_PG_USER_MAPPING_UMOPTIONS: pg_sys::AttrNumber = 4;
+
+// create a fdw instance, report error to Postgres if it failed
 pub(super) unsafe fn create_fdw_instance<W: ForeignDataWrapper>(ftable_id: pg_sys::Oid) -> W {
     let ftable = pg_sys::GetForeignTable(ftable_id);
-    let fserver = pg_sys::GetForeignServer((*ftable).serverid);
-    let fserver_opts = utils::options_to_hashmap((*fserver).options);
-    W::new(&fserver_opts)
+    create_fdw_instance_from_server_id((*ftable).serverid)
+}
+
+// create a fdw instance from a foreign server, report error to Postgres if it failed
+//
+// the options of user mapping for current user are merged into the foreign
+// server options and take precedence
+pub(super) unsafe fn create_fdw_instance_from_server_id<W: ForeignDataWrapper>(
+    fserver_id: pg_sys::Oid,
+) -> W {
+    let fserver = pg_sys::GetForeignServer(fserver_id);
+    let mut fserver_opts = utils::options_to_hashmap((*fserver).options);
+    fserver_opts.extend(get_user_mapping_options(fserver_id));
+    connection::with_server::<W, _>(fserver_id, || W::new(&fserver_opts)).report_unwrap()
+}
+
+// get options of the user mapping for current user on the foreign server, or
+// the one for PUBLIC if current user has no user mapping
+unsafe fn get_user_mapping_options(fserver_id: pg_sys::Oid) -> HashMap<String, String> {
+    let cache_id = pg_sys::SysCacheIdentifier_USERMAPPINGUSERSERVER as _;
+    for user_id in [pg_sys::GetUserId(), pg_sys::InvalidOid] {
+        let tuple = pg_sys::SearchSysCache2(
+            cache_id,
+            user_id.into_datum().unwrap(),
+            fserver_id.into_datum().unwrap(),
+        );
+        if tuple.is_null() {
+            continue;
+        }
+
+        let mut is_null = true;
+        let datum = pg_sys::SysCacheGetAttr(
+            cache_id,
+            tuple,
+            ANUM_PG_USER_MAPPING_UMOPTIONS,
+            &mut is_null,
+        );
+        let opts = if is_null {
+            HashMap::new()
+        } else {
+            utils::options_to_hashmap(pg_sys::untransformRelOptions(datum))
+        };
+        pg_sys::ReleaseSysCache(tuple);
+        return opts;
+    }
+    HashMap::new()
 }
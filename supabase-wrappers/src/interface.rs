@@ -1,22 +1,32 @@
 //! Provides interface types and trait to develop Postgres foreign data wrapper
 //!
 
+use crate::options::{validate_options, OptionDef};
 use crate::FdwRoutine;
-use pgx::prelude::{Date, Timestamp};
+use pgx::prelude::{Date, Time, Timestamp, TimestampWithTimeZone};
 use pgx::{
-    pg_sys::{self, Datum, Oid},
-    AllocatedByRust, AnyNumeric, FromDatum, IntoDatum, JsonB, PgBuiltInOids, PgOid,
+    pg_sys::{self, panic::ErrorReport, Datum, Oid},
+    AllocatedByRust, AnyNumeric, FromDatum, Inet, IntoDatum, JsonB, PgBuiltInOids, PgLogLevel,
+    PgOid, PgSqlErrorCode, Uuid,
 };
 use std::collections::HashMap;
+use std::error::Error;
+use std::ffi::CString;
 use std::fmt;
+use std::io::Write;
 use std::iter::Zip;
 use std::mem;
-use std::slice::Iter;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::net::UnixStream;
+use std::ptr;
+use std::slice::{self, Iter};
+use std::sync::Arc;
 
 // fdw system catalog oids
 // https://doxygen.postgresql.org/pg__foreign__data__wrapper_8h.html
 // https://doxygen.postgresql.org/pg__foreign__server_8h.html
 // https://doxygen.postgresql.org/pg__foreign__table_8h.html
+// https://doxygen.postgresql.org/pg__user__mapping_8h.html
 
 /// Constant can be used in [validator](ForeignDataWrapper::validator)
 pub const FOREIGN_DATA_WRAPPER_RELATION_ID: pg_sys::Oid = 2328;
@@ -27,6 +37,159 @@ pub const FOREIGN_SERVER_RELATION_ID: pg_sys::Oid = 1417;
 /// Constant can be used in [validator](ForeignDataWrapper::validator)
 pub const FOREIGN_TABLE_RELATION_ID: pg_sys::Oid = 3118;
 
+/// Constant can be used in [validator](ForeignDataWrapper::validator)
+pub const USER_MAPPING_RELATION_ID: pg_sys::Oid = 1418;
+
+/// An error raised by a [`ForeignDataWrapper`] callback
+///
+/// The error carries a SQLSTATE code, a primary message and optional detail and
+/// hint. When a callback returns an error, the framework reports it to Postgres
+/// as an `ERROR`, which aborts the current transaction.
+///
+/// A `String` or `&str` can be converted to an error with code
+/// `ERRCODE_FDW_ERROR`, so `?` can be used with `map_err` directly. For example,
+///
+/// ```rust,no_run
+/// let resp = rt
+///     .block_on(client.get(url).send())
+///     .map_err(|err| format!("request failed: {}", err))?;
+/// ```
+///
+/// Or with a specific SQLSTATE code and more information,
+///
+/// ```rust,no_run
+/// return Err(FdwError::new(
+///     PgSqlErrorCode::ERRCODE_FDW_INVALID_OPTION_NAME,
+///     "invalid format option",
+/// )
+/// .hint("format can only be 'csv' or 'jsonl'"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FdwError {
+    /// SQLSTATE error code
+    pub code: PgSqlErrorCode,
+
+    /// primary error message
+    pub message: String,
+
+    /// optional error detail
+    pub detail: Option<String>,
+
+    /// optional hint about how to fix the error
+    pub hint: Option<String>,
+}
+
+impl FdwError {
+    /// Create an error with SQLSTATE code and message
+    pub fn new<S: Into<String>>(code: PgSqlErrorCode, message: S) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            detail: None,
+            hint: None,
+        }
+    }
+
+    /// Set the error detail
+    pub fn detail<S: Into<String>>(mut self, detail: S) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Set the error hint
+    pub fn hint<S: Into<String>>(mut self, hint: S) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// Report this error to Postgres as an `ERROR`, this function never returns
+    pub fn report(self) -> ! {
+        // pgx's ErrorReport has no hint slot, so hint is appended to the detail
+        let detail = match (self.detail, self.hint) {
+            (Some(detail), Some(hint)) => Some(format!("{}\nHINT: {}", detail, hint)),
+            (None, Some(hint)) => Some(format!("HINT: {}", hint)),
+            (detail, None) => detail,
+        };
+        let mut report = ErrorReport::new(self.code, self.message, "Wrappers");
+        if let Some(detail) = detail {
+            report = report.detail(detail);
+        }
+        report.report(PgLogLevel::ERROR);
+        unreachable!()
+    }
+}
+
+impl fmt::Display for FdwError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for FdwError {}
+
+impl From<String> for FdwError {
+    fn from(message: String) -> Self {
+        Self::new(PgSqlErrorCode::ERRCODE_FDW_ERROR, message)
+    }
+}
+
+impl From<&str> for FdwError {
+    fn from(message: &str) -> Self {
+        Self::new(PgSqlErrorCode::ERRCODE_FDW_ERROR, message)
+    }
+}
+
+/// Result type returned by [`ForeignDataWrapper`] callbacks
+pub type FdwResult<T> = Result<T, FdwError>;
+
+/// Time interval, same as Postgres `interval` type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Interval {
+    /// number of months
+    pub months: i32,
+
+    /// number of days
+    pub days: i32,
+
+    /// number of microseconds
+    pub micros: i64,
+}
+
+impl IntoDatum for Interval {
+    fn into_datum(self) -> Option<Datum> {
+        unsafe {
+            let ptr = pg_sys::palloc(mem::size_of::<pg_sys::Interval>()) as *mut pg_sys::Interval;
+            *ptr = pg_sys::Interval {
+                time: self.micros,
+                day: self.days,
+                month: self.months,
+            };
+            Some(Datum::from(ptr))
+        }
+    }
+
+    fn type_oid() -> Oid {
+        pg_sys::INTERVALOID
+    }
+}
+
+impl FromDatum for Interval {
+    unsafe fn from_polymorphic_datum(datum: Datum, is_null: bool, _typoid: Oid) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        if is_null {
+            return None;
+        }
+        let interval = *datum.cast_mut_ptr::<pg_sys::Interval>();
+        Some(Interval {
+            months: interval.month,
+            days: interval.day,
+            micros: interval.time,
+        })
+    }
+}
+
 /// A data cell in a data row
 #[derive(Debug)]
 pub enum Cell {
@@ -42,6 +205,145 @@ pub enum Cell {
     Date(Date),
     Timestamp(Timestamp),
     Json(JsonB),
+    Uuid(Uuid),
+    Bytea(Vec<u8>),
+    Time(Time),
+    TimestampTz(TimestampWithTimeZone),
+    Interval(Interval),
+    Inet(Inet),
+    Array(Vec<Option<Cell>>),
+}
+
+// Postgres epoch 2000-01-01 in days since Unix epoch
+const PG_EPOCH_UNIX_DAYS: i64 = 10_957;
+
+const USECS_PER_DAY: i64 = 86_400_000_000;
+
+// write a string as a quoted literal, with single quotes doubled
+fn write_quoted(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "'{}'", s.replace('\'', "''"))
+}
+
+// write seconds in microseconds, trailing zeros of the fraction are omitted
+fn write_seconds<W: fmt::Write>(f: &mut W, usecs: i64, width: usize) -> fmt::Result {
+    if usecs < 0 {
+        f.write_str("-")?;
+    }
+    let usecs = usecs.unsigned_abs();
+    write!(f, "{:0width$}", usecs / 1_000_000, width = width)?;
+    let frac = usecs % 1_000_000;
+    if frac != 0 {
+        let frac = format!("{:06}", frac);
+        write!(f, ".{}", frac.trim_end_matches('0'))?;
+    }
+    Ok(())
+}
+
+// write microseconds since midnight as time of day
+fn write_time<W: fmt::Write>(f: &mut W, usecs: i64) -> fmt::Result {
+    let mins = usecs / 60_000_000;
+    write!(f, "{:02}:{:02}:", mins / 60, mins % 60)?;
+    write_seconds(f, usecs % 60_000_000, 2)
+}
+
+// write days since Postgres epoch and optional microseconds since midnight as
+// ISO 8601 date and time, followed by the time zone `suffix`
+//
+// Years before 1 AD have a `BC` suffix, same as Postgres.
+fn write_date_time<W: fmt::Write>(
+    f: &mut W,
+    days: i64,
+    usecs: Option<i64>,
+    suffix: &str,
+) -> fmt::Result {
+    // civil date from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + PG_EPOCH_UNIX_DAYS + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let bc = year <= 0;
+    let year = if bc { 1 - year } else { year };
+    write!(f, "{:04}-{:02}-{:02}", year, month, day)?;
+    if let Some(usecs) = usecs {
+        f.write_str(" ")?;
+        write_time(f, usecs)?;
+    }
+    f.write_str(suffix)?;
+    if bc {
+        f.write_str(" BC")?;
+    }
+    Ok(())
+}
+
+// write timestamp in microseconds since Postgres epoch
+fn write_timestamp<W: fmt::Write>(f: &mut W, usecs: i64, suffix: &str) -> fmt::Result {
+    match usecs {
+        i64::MIN => f.write_str("-infinity"),
+        i64::MAX => f.write_str("infinity"),
+        _ => write_date_time(
+            f,
+            usecs.div_euclid(USECS_PER_DAY),
+            Some(usecs.rem_euclid(USECS_PER_DAY)),
+            suffix,
+        ),
+    }
+}
+
+// write interval in ISO 8601 duration format, e.g. `P1Y2M3DT4H5M6.5S`
+fn write_interval<W: fmt::Write>(f: &mut W, v: &Interval) -> fmt::Result {
+    f.write_str("P")?;
+    if v.months / 12 != 0 {
+        write!(f, "{}Y", v.months / 12)?;
+    }
+    if v.months % 12 != 0 {
+        write!(f, "{}M", v.months % 12)?;
+    }
+    if v.days != 0 {
+        write!(f, "{}D", v.days)?;
+    }
+    if v.micros != 0 || (v.months == 0 && v.days == 0) {
+        let hours = v.micros / 3_600_000_000;
+        let mins = v.micros / 60_000_000 % 60;
+        let usecs = v.micros % 60_000_000;
+        f.write_str("T")?;
+        if hours != 0 {
+            write!(f, "{}H", hours)?;
+        }
+        if mins != 0 {
+            write!(f, "{}M", mins)?;
+        }
+        if usecs != 0 || v.micros == 0 {
+            write_seconds(f, usecs, 1)?;
+            f.write_str("S")?;
+        }
+    }
+    Ok(())
+}
+
+// write a date and time cell as ISO 8601 text, timestamp with time zone is in
+// UTC and followed by the time zone `suffix`. The text doesn't depend on the
+// session `DateStyle`, `TimeZone` and `IntervalStyle`.
+//
+// Return `None` if the cell is not a date and time cell.
+pub(crate) fn date_time_text(cell: &Cell, tz_suffix: &str) -> Option<String> {
+    let mut text = String::new();
+    let ret = match cell {
+        Cell::Date(v) if v.is_neg_infinity() => fmt::Write::write_str(&mut text, "-infinity"),
+        Cell::Date(v) if v.is_infinity() => fmt::Write::write_str(&mut text, "infinity"),
+        Cell::Date(v) => write_date_time(&mut text, v.to_pg_epoch_days().into(), None, ""),
+        Cell::Time(v) => write_time(&mut text, v.0 as i64),
+        Cell::Timestamp(v) => write_timestamp(&mut text, v.clone().into(), ""),
+        Cell::TimestampTz(v) => write_timestamp(&mut text, v.clone().into(), tz_suffix),
+        Cell::Interval(v) => write_interval(&mut text, v),
+        _ => return None,
+    };
+    ret.ok().map(|_| text)
 }
 
 impl Clone for Cell {
@@ -59,10 +361,24 @@ impl Clone for Cell {
             Cell::Date(v) => Cell::Date(v.clone()),
             Cell::Timestamp(v) => Cell::Timestamp(v.clone()),
             Cell::Json(v) => Cell::Json(JsonB(v.0.clone())),
+            Cell::Uuid(v) => Cell::Uuid(*v),
+            Cell::Bytea(v) => Cell::Bytea(v.clone()),
+            Cell::Time(v) => Cell::Time(v.clone()),
+            Cell::TimestampTz(v) => Cell::TimestampTz(v.clone()),
+            Cell::Interval(v) => Cell::Interval(*v),
+            Cell::Inet(v) => Cell::Inet(Inet(v.0.clone())),
+            Cell::Array(v) => Cell::Array(v.clone()),
         }
     }
 }
 
+/// Format a cell as a readable literal, such as `42`, `'it''s'` or
+/// `'2023-01-02 03:04:05'`
+///
+/// The format doesn't depend on the session settings, e.g. `DateStyle` or
+/// `TimeZone`, and string values are always quoted and escaped. It is not
+/// meant for the SQL sent to a remote engine, use
+/// [`Deparser`](crate::deparse::Deparser) for that.
 impl fmt::Display for Cell {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -73,11 +389,32 @@ impl fmt::Display for Cell {
             Cell::I32(v) => write!(f, "{}", v),
             Cell::F64(v) => write!(f, "{}", v),
             Cell::I64(v) => write!(f, "{}", v),
-            Cell::Numeric(v) => write!(f, "{:?}", v),
-            Cell::String(v) => write!(f, "'{}'", v),
-            Cell::Date(v) => write!(f, "{:?}", v),
-            Cell::Timestamp(v) => write!(f, "{:?}", v),
-            Cell::Json(v) => write!(f, "{:?}", v),
+            Cell::Numeric(v) => write!(f, "{}", v),
+            Cell::String(v) => write_quoted(f, v),
+            Cell::Json(v) => write_quoted(f, &v.0.to_string()),
+            Cell::Uuid(v) => write!(f, "'{}'", v),
+            Cell::Bytea(v) => {
+                let hex: String = v.iter().map(|b| format!("{:02x}", b)).collect();
+                write!(f, "'\\x{}'", hex)
+            }
+            Cell::Date(_)
+            | Cell::Time(_)
+            | Cell::Timestamp(_)
+            | Cell::TimestampTz(_)
+            | Cell::Interval(_) => {
+                write!(f, "'{}'", date_time_text(self, "+00").unwrap_or_default())
+            }
+            Cell::Inet(v) => write_quoted(f, &v.0),
+            Cell::Array(v) => {
+                let elems: Vec<String> = v
+                    .iter()
+                    .map(|elem| match elem {
+                        Some(cell) => cell.to_string(),
+                        None => "null".to_string(),
+                    })
+                    .collect();
+                write!(f, "ARRAY[{}]", elems.join(", "))
+            }
         }
     }
 }
@@ -97,6 +434,30 @@ impl IntoDatum for Cell {
             Cell::Date(v) => v.into_datum(),
             Cell::Timestamp(v) => v.into_datum(),
             Cell::Json(v) => v.into_datum(),
+            Cell::Uuid(v) => v.into_datum(),
+            Cell::Bytea(v) => v.into_datum(),
+            Cell::Time(v) => v.into_datum(),
+            Cell::TimestampTz(v) => v.into_datum(),
+            Cell::Interval(v) => v.into_datum(),
+            Cell::Inet(v) => v.into_datum(),
+            Cell::Array(v) => {
+                // the element type is only known from the non-null elements,
+                // use cell_into_datum() with the target column instead
+                let datum = match v.iter().flatten().next().map(|cell| cell.type_oid()) {
+                    Some(elem_type) if elem_type != pg_sys::InvalidOid => unsafe {
+                        array_into_datum(v, elem_type, -1)
+                    },
+                    _ => Err("element type of an empty or all null array is unknown".to_string()),
+                };
+                match datum {
+                    Ok(datum) => Some(datum),
+                    Err(err) => FdwError::new(
+                        PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+                        format!("cannot convert array to datum: {}", err),
+                    )
+                    .report(),
+                }
+            }
         }
     }
 
@@ -151,9 +512,284 @@ impl FromDatum for Cell {
             PgOid::BuiltIn(PgBuiltInOids::JSONBOID) => {
                 Some(Cell::Json(JsonB::from_datum(datum, false).unwrap()))
             }
-            _ => None,
+            PgOid::BuiltIn(PgBuiltInOids::UUIDOID) => {
+                Some(Cell::Uuid(Uuid::from_datum(datum, false).unwrap()))
+            }
+            PgOid::BuiltIn(PgBuiltInOids::BYTEAOID) => {
+                Some(Cell::Bytea(Vec::<u8>::from_datum(datum, false).unwrap()))
+            }
+            PgOid::BuiltIn(PgBuiltInOids::TIMEOID) => {
+                Some(Cell::Time(Time::from_datum(datum, false).unwrap()))
+            }
+            PgOid::BuiltIn(PgBuiltInOids::TIMESTAMPTZOID) => Some(Cell::TimestampTz(
+                TimestampWithTimeZone::from_datum(datum, false).unwrap(),
+            )),
+            PgOid::BuiltIn(PgBuiltInOids::INTERVALOID) => {
+                Some(Cell::Interval(Interval::from_datum(datum, false).unwrap()))
+            }
+            PgOid::BuiltIn(PgBuiltInOids::INETOID) => {
+                Some(Cell::Inet(Inet::from_datum(datum, false).unwrap()))
+            }
+            _ => {
+                let elem_type = pg_sys::get_element_type(typoid);
+                if elem_type == pg_sys::InvalidOid {
+                    return None;
+                }
+                array_from_datum(datum, elem_type).map(Cell::Array)
+            }
+        }
+    }
+}
+
+impl Cell {
+    // get Postgres type of the cell, InvalidOid if it is an empty or all null
+    // array whose element type is unknown
+    pub(crate) fn type_oid(&self) -> Oid {
+        match self {
+            Cell::Bool(_) => pg_sys::BOOLOID,
+            Cell::I8(_) => pg_sys::CHAROID,
+            Cell::I16(_) => pg_sys::INT2OID,
+            Cell::F32(_) => pg_sys::FLOAT4OID,
+            Cell::I32(_) => pg_sys::INT4OID,
+            Cell::F64(_) => pg_sys::FLOAT8OID,
+            Cell::I64(_) => pg_sys::INT8OID,
+            Cell::Numeric(_) => pg_sys::NUMERICOID,
+            Cell::String(_) => pg_sys::TEXTOID,
+            Cell::Date(_) => pg_sys::DATEOID,
+            Cell::Timestamp(_) => pg_sys::TIMESTAMPOID,
+            Cell::Json(_) => pg_sys::JSONBOID,
+            Cell::Uuid(_) => pg_sys::UUIDOID,
+            Cell::Bytea(_) => pg_sys::BYTEAOID,
+            Cell::Time(_) => pg_sys::TIMEOID,
+            Cell::TimestampTz(_) => pg_sys::TIMESTAMPTZOID,
+            Cell::Interval(_) => pg_sys::INTERVALOID,
+            Cell::Inet(_) => pg_sys::INETOID,
+            Cell::Array(v) => match v.iter().flatten().next() {
+                Some(cell) => unsafe { pg_sys::get_array_type(cell.type_oid()) },
+                None => pg_sys::InvalidOid,
+            },
+        }
+    }
+}
+
+// convert an one-dimensional array datum to array elements, return None if
+// the array is multi-dimensional or any element cannot be converted
+unsafe fn array_from_datum(datum: Datum, elem_type: Oid) -> Option<Vec<Option<Cell>>> {
+    let arr = pg_sys::pg_detoast_datum(datum.cast_mut_ptr()) as *mut pg_sys::ArrayType;
+    if (*arr).ndim > 1 {
+        return None;
+    }
+
+    let mut typlen = 0;
+    let mut typbyval = false;
+    let mut typalign = 0;
+    pg_sys::get_typlenbyvalalign(elem_type, &mut typlen, &mut typbyval, &mut typalign);
+
+    let mut elems = ptr::null_mut();
+    let mut nulls = ptr::null_mut();
+    let mut nelems = 0;
+    pg_sys::deconstruct_array(
+        arr,
+        elem_type,
+        typlen as c_int,
+        typbyval,
+        typalign,
+        &mut elems,
+        &mut nulls,
+        &mut nelems,
+    );
+    if nelems == 0 {
+        return Some(Vec::new());
+    }
+
+    let elems = slice::from_raw_parts(elems, nelems as usize);
+    let nulls = slice::from_raw_parts(nulls, nelems as usize);
+    elems
+        .iter()
+        .zip(nulls.iter())
+        .map(|(elem, is_null)| {
+            if *is_null {
+                Some(None)
+            } else {
+                Cell::from_polymorphic_datum(*elem, false, elem_type).map(Some)
+            }
+        })
+        .collect()
+}
+
+// convert a cell to datum of a column, the cell is coerced to the column type
+// and type modifier if they are different, array cell is converted to an array
+// of the column element type
+//
+// Errors raised by the type input functions, e.g. invalid input syntax or a
+// too long value for varchar(n), are not caught and reported by Postgres as is.
+pub(crate) unsafe fn cell_into_datum(cell: Cell, col: &Column) -> FdwResult<Datum> {
+    let datum = match cell {
+        Cell::Array(elems) => {
+            let elem_type = pg_sys::get_element_type(col.type_oid);
+            if elem_type == pg_sys::InvalidOid {
+                return Err(FdwError::new(
+                    PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+                    format!("column '{}' is not an array", col.name),
+                ));
+            }
+            array_into_datum(elems, elem_type, col.typmod)
+        }
+        cell => coerce_into_datum(cell, col.type_oid, col.typmod),
+    };
+    datum.map_err(|err| {
+        FdwError::new(
+            PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+            format!("cannot convert value for column '{}': {}", col.name, err),
+        )
+    })
+}
+
+// convert a cell to datum of the type and type modifier, return the error
+// message if it cannot be converted
+//
+// Numeric is cast directly, string is parsed by the input function of the
+// type, other types are converted through their text representation. A cell
+// of the same type is also converted through its text representation if
+// there is a type modifier, so that the modifier is applied by the input
+// function, e.g. numeric(p, s) is rounded and timestamp(p) is truncated.
+unsafe fn coerce_into_datum(cell: Cell, type_oid: Oid, typmod: i32) -> Result<Datum, String> {
+    let cell = match cast_numeric(&cell, type_oid) {
+        Some(cast) => cast?,
+        None => cell,
+    };
+
+    let src_type = cell.type_oid();
+    if type_oid == pg_sys::InvalidOid || (type_oid == src_type && typmod < 0) {
+        return cell.into_datum().ok_or_else(|| "invalid value".to_string());
+    }
+
+    if let Cell::String(s) = &cell {
+        let text = CString::new(s.as_str()).map_err(|_| "string contains null character")?;
+        return Ok(input_datum(text.as_ptr() as _, type_oid, typmod));
+    }
+
+    let datum = cell
+        .into_datum()
+        .ok_or_else(|| "invalid value".to_string())?;
+    let mut output_func = pg_sys::InvalidOid;
+    let mut is_varlena = false;
+    pg_sys::getTypeOutputInfo(src_type, &mut output_func, &mut is_varlena);
+    let text = pg_sys::OidOutputFunctionCall(output_func, datum);
+    Ok(input_datum(text, type_oid, typmod))
+}
+
+// convert a string to datum by the input function of the type
+unsafe fn input_datum(text: *mut c_char, type_oid: Oid, typmod: i32) -> Datum {
+    let mut input_func = pg_sys::InvalidOid;
+    let mut io_param = pg_sys::InvalidOid;
+    pg_sys::getTypeInputInfo(type_oid, &mut input_func, &mut io_param);
+    pg_sys::OidInputFunctionCall(input_func, text, io_param, typmod)
+}
+
+// cast a numeric cell to another numeric type, return None if it is not a
+// numeric cast
+//
+// Float with a fractional part cannot be cast to integer, as silently rounding
+// it would lose data. FDW should round the value by itself if that is intended.
+fn cast_numeric(cell: &Cell, type_oid: Oid) -> Option<Result<Cell, String>> {
+    let int = match cell {
+        Cell::I16(v) => Some(*v as i64),
+        Cell::I32(v) => Some(*v as i64),
+        Cell::I64(v) => Some(*v),
+        _ => None,
+    };
+    let float = match cell {
+        Cell::F32(v) => Some(*v as f64),
+        Cell::F64(v) => Some(*v),
+        _ => None,
+    };
+    if int.is_none() && float.is_none() {
+        return None;
+    }
+
+    let out_of_range = |type_name: &str| format!("value out of range for type {}", type_name);
+    let to_int = |type_name: &str| match (int, float) {
+        (Some(v), _) => Ok(v),
+        (_, Some(v)) if v.is_finite() && v.fract() != 0.0 => {
+            Err(format!("non-integral value {} for type {}", v, type_name))
+        }
+        (_, Some(v)) if v.is_finite() && v >= i64::MIN as f64 && v < i64::MAX as f64 => {
+            Ok(v as i64)
+        }
+        _ => Err(out_of_range(type_name)),
+    };
+    let to_float = || int.map(|v| v as f64).or(float).unwrap();
+
+    let cast = match type_oid {
+        pg_sys::INT2OID => to_int("smallint").and_then(|v| {
+            i16::try_from(v)
+                .map(Cell::I16)
+                .map_err(|_| out_of_range("smallint"))
+        }),
+        pg_sys::INT4OID => to_int("integer").and_then(|v| {
+            i32::try_from(v)
+                .map(Cell::I32)
+                .map_err(|_| out_of_range("integer"))
+        }),
+        pg_sys::INT8OID => to_int("bigint").map(Cell::I64),
+        pg_sys::FLOAT4OID => Ok(Cell::F32(to_float() as f32)),
+        pg_sys::FLOAT8OID => Ok(Cell::F64(to_float())),
+        pg_sys::NUMERICOID => match int {
+            Some(v) => Ok(Cell::Numeric(AnyNumeric::from(v))),
+            None => AnyNumeric::try_from(to_float())
+                .map(Cell::Numeric)
+                .map_err(|err| err.to_string()),
+        },
+        _ => return None,
+    };
+    Some(cast)
+}
+
+// convert array elements to an one-dimensional array datum of the element
+// type, the elements are coerced to the element type and type modifier
+pub(crate) unsafe fn array_into_datum(
+    cells: Vec<Option<Cell>>,
+    elem_type: Oid,
+    typmod: i32,
+) -> Result<Datum, String> {
+    if cells.is_empty() {
+        return Ok(Datum::from(pg_sys::construct_empty_array(elem_type)));
+    }
+
+    let mut typlen = 0;
+    let mut typbyval = false;
+    let mut typalign = 0;
+    pg_sys::get_typlenbyvalalign(elem_type, &mut typlen, &mut typbyval, &mut typalign);
+
+    let mut dims = [cells.len() as c_int];
+    let mut lbs = [1];
+    let mut elems = Vec::with_capacity(cells.len());
+    let mut nulls = Vec::with_capacity(cells.len());
+    for cell in cells {
+        match cell {
+            Some(cell) => {
+                elems.push(coerce_into_datum(cell, elem_type, typmod)?);
+                nulls.push(false);
+            }
+            None => {
+                elems.push(Datum::from(0));
+                nulls.push(true);
+            }
         }
     }
+    let arr = pg_sys::construct_md_array(
+        elems.as_mut_ptr(),
+        nulls.as_mut_ptr(),
+        1,
+        dims.as_mut_ptr(),
+        lbs.as_mut_ptr(),
+        elem_type,
+        typlen as c_int,
+        typbyval,
+        typalign,
+    );
+    Ok(Datum::from(arr))
 }
 
 /// A data row in a table
@@ -211,10 +847,103 @@ impl Row {
     }
 }
 
+/// A batch of data rows in a table
+///
+/// Unlike [`Row`], the batch has no column names. Its column order is fixed
+/// to the target columns when the scan begins, and cells are filled by
+/// position.
+#[derive(Debug, Clone, Default)]
+pub struct RowBatch {
+    // number of cells in each row
+    num_cols: usize,
+
+    // suggested maximum number of rows
+    capacity: usize,
+
+    // cells of all rows, row by row
+    cells: Vec<Option<Cell>>,
+
+    // number of rows
+    len: usize,
+
+    // number of rows already taken by the framework
+    taken: usize,
+}
+
+impl RowBatch {
+    /// Create an empty batch with `num_cols` columns and up to `capacity` rows
+    pub fn new(num_cols: usize, capacity: usize) -> Self {
+        Self {
+            num_cols,
+            capacity: capacity.max(1),
+            cells: Vec::with_capacity(num_cols * capacity.max(1)),
+            len: 0,
+            taken: 0,
+        }
+    }
+
+    /// Return the number of columns in each row
+    pub fn num_cols(&self) -> usize {
+        self.num_cols
+    }
+
+    /// Return the suggested maximum number of rows
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Return the number of rows in this batch
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return true if this batch has no rows
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return true if this batch has reached its capacity
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity
+    }
+
+    /// Push a row with all cells set to `None`, and return its cells to be
+    /// filled by position
+    pub fn push_row(&mut self) -> &mut [Option<Cell>] {
+        let start = self.cells.len();
+        self.cells.resize(start + self.num_cols, None);
+        self.len += 1;
+        &mut self.cells[start..]
+    }
+
+    /// Clear the batch, removing all rows
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.len = 0;
+        self.taken = 0;
+    }
+
+    // true if all rows are taken by the framework
+    pub(crate) fn taken_all(&self) -> bool {
+        self.taken >= self.len
+    }
+
+    // take cells of the next row which is not taken yet, return None if all
+    // rows are taken
+    pub(crate) fn take_row(&mut self) -> Option<&mut [Option<Cell>]> {
+        if self.taken >= self.len {
+            return None;
+        }
+        let start = self.taken * self.num_cols;
+        self.taken += 1;
+        Some(&mut self.cells[start..start + self.num_cols])
+    }
+}
+
 /// A column definition in a table
 ///
 /// The column represents a column definition in a table.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct Column {
     /// column name
     pub name: String,
@@ -224,6 +953,21 @@ pub struct Column {
 
     /// column type OID, can be used to match pg_sys::BuiltinOid
     pub type_oid: pg_sys::Oid,
+
+    /// column type modifier, e.g. the length of `varchar(n)`, -1 if there is
+    /// no modifier
+    pub typmod: i32,
+}
+
+impl Default for Column {
+    fn default() -> Self {
+        Column {
+            name: String::default(),
+            num: 0,
+            type_oid: pg_sys::InvalidOid,
+            typmod: -1,
+        }
+    }
 }
 
 /// A restiction value used in [`Qual`], either a [`Cell`] or an array of [`Cell`]
@@ -233,6 +977,19 @@ pub enum Value {
     Array(Vec<Cell>),
 }
 
+/// Query parameter, whose value is only known when the scan is executed
+///
+/// A parameter comes from a prepared statement, a sub query or the outer
+/// relation of a nested loop join.
+#[derive(Debug, Clone)]
+pub struct Param {
+    /// 1-based position of the parameter in the foreign scan
+    pub id: usize,
+
+    /// parameter type OID, can be used to match pg_sys::BuiltinOid
+    pub type_oid: pg_sys::Oid,
+}
+
 /// Query restrictions, a.k.a conditions in `WHERE` clause
 ///
 /// A Qual defines a simple condition wich can be used by the FDW to restrict the number
@@ -242,33 +999,52 @@ pub enum Value {
 /// <strong>Warning</strong>: Currently only simple conditions are supported, see below for examples. Other kinds of conditions, like JSON attribute filter e.g. `where json_col->>'key' = 'value'`, are not supported yet.
 /// </pre></div>
 ///
+/// A qual with `param` compares with a [`Param`] rather than a constant. Its
+/// `value` is a placeholder of the parameter type when planning, and is set
+/// to the parameter value before `begin_scan` is called. When the parameter
+/// value changes, for example for each outer row of a nested loop join, the
+/// scan is restarted by `end_scan` and `begin_scan` with the new value
+/// instead of `re_scan`.
+///
 /// ## Examples
 ///
 /// ```sql
 /// where id = 1;
-/// -- [Qual { field: "id", operator: "=", value: Cell(I32(1)), use_or: false }]
+/// -- [Qual { field: "id", operator: "=", value: Cell(I32(1)), use_or: false, param: None }]
 /// ```
 ///
 /// ```sql
 /// where id in (1, 2);
-/// -- [Qual { field: "id", operator: "=", value: Array([I64(1), I64(2)]), use_or: true }]
+/// -- [Qual { field: "id", operator: "=", value: Array([I64(1), I64(2)]), use_or: true, param: None }]
 /// ```
 ///
 /// ```sql
 /// where col is null
-/// -- [Qual { field: "col", operator: "is", value: Cell(String("null")), use_or: false }]
+/// -- [Qual { field: "col", operator: "is", value: Cell(String("null")), use_or: false, param: None }]
 /// ```
 ///
 /// ```sql
 /// where bool_col
-/// -- [Qual { field: "bool_col", operator: "=", value: Cell(Bool(true)), use_or: false }]
+/// -- [Qual { field: "bool_col", operator: "=", value: Cell(Bool(true)), use_or: false, param: None }]
+/// ```
+///
+/// ```sql
+/// -- in a prepared statement
+/// where id = $1;
+/// -- [Qual { field: "id", operator: "=", value: Cell(I32(0)), use_or: false, param: Some(Param { id: 1, type_oid: 23 }) }]
+/// ```
+///
+/// ```sql
+/// -- in a nested loop join with another table t
+/// where id = t.foreign_id;
+/// -- [Qual { field: "id", operator: "=", value: Cell(I32(0)), use_or: false, param: Some(Param { id: 1, type_oid: 23 }) }]
 /// ```
 ///
 /// ```sql
 /// where id > 1 and col = 'foo';
 /// -- [
-/// --   Qual { field: "id", operator: ">", value: Cell(I32(1)), use_or: false },
-/// --   Qual { field: "col", operator: "=", value: Cell(String("foo")), use_or: false }
+/// --   Qual { field: "id", operator: ">", value: Cell(I32(1)), use_or: false, param: None },
+/// --   Qual { field: "col", operator: "=", value: Cell(String("foo")), use_or: false, param: None }
 /// -- ]
 /// ```
 #[derive(Debug, Clone)]
@@ -277,9 +1053,14 @@ pub struct Qual {
     pub operator: String,
     pub value: Value,
     pub use_or: bool,
+    pub param: Option<Param>,
 }
 
 impl Qual {
+    /// Deparse the qual to a readable condition, values are formatted by
+    /// [`Cell`]'s `Display` and identifiers are not quoted. Use
+    /// [`Deparser`](crate::deparse::Deparser) for the SQL sent to a remote
+    /// engine.
     pub fn deparse(&self) -> String {
         if self.use_or {
             match &self.value {
@@ -309,6 +1090,62 @@ impl Qual {
     }
 }
 
+/// Query restriction with boolean operators, a.k.a a condition in `WHERE`
+/// clause which cannot be expressed as a single [`Qual`]
+///
+/// Each leaf is a simple condition in the same form as [`Qual`]. Conditions
+/// with a [`Param`] are not extracted as `QualExpr`.
+///
+/// ## Examples
+///
+/// ```sql
+/// where status = 'open' or status = 'paid';
+/// -- [Or([Qual(Qual { field: "status", operator: "=", value: Cell(String("open")), .. }),
+/// --      Qual(Qual { field: "status", operator: "=", value: Cell(String("paid")), .. })])]
+/// ```
+///
+/// ```sql
+/// where not (a = 1 and b = 2);
+/// -- [Not(And([Qual(Qual { field: "a", operator: "=", value: Cell(I32(1)), .. }),
+/// --           Qual(Qual { field: "b", operator: "=", value: Cell(I32(2)), .. })]))]
+/// ```
+#[derive(Debug, Clone)]
+pub enum QualExpr {
+    Qual(Qual),
+    And(Vec<QualExpr>),
+    Or(Vec<QualExpr>),
+    Not(Box<QualExpr>),
+}
+
+impl QualExpr {
+    pub fn deparse(&self) -> String {
+        let join = |exprs: &[QualExpr], op: &str| {
+            let conds: Vec<String> = exprs
+                .iter()
+                .map(|expr| format!("({})", expr.deparse()))
+                .collect();
+            conds.join(op)
+        };
+        match self {
+            QualExpr::Qual(qual) => qual.deparse(),
+            QualExpr::And(exprs) => join(exprs, " and "),
+            QualExpr::Or(exprs) => join(exprs, " or "),
+            QualExpr::Not(expr) => format!("not ({})", expr.deparse()),
+        }
+    }
+
+    /// Get all the leaf quals of the expression
+    pub fn quals(&self) -> Vec<&Qual> {
+        match self {
+            QualExpr::Qual(qual) => vec![qual],
+            QualExpr::And(exprs) | QualExpr::Or(exprs) => {
+                exprs.iter().flat_map(|expr| expr.quals()).collect()
+            }
+            QualExpr::Not(expr) => expr.quals(),
+        }
+    }
+}
+
 /// Query sort, a.k.a `ORDER BY` clause
 ///
 /// ## Examples
@@ -345,6 +1182,9 @@ pub struct Sort {
 }
 
 impl Sort {
+    /// Deparse the sort to a readable `ORDER BY` item, identifiers are not
+    /// quoted. Use [`Deparser`](crate::deparse::Deparser) for the SQL sent to
+    /// a remote engine.
     pub fn deparse(&self) -> String {
         let mut sql = self.field.to_string();
 
@@ -399,42 +1239,353 @@ impl Limit {
     }
 }
 
-/// The Foreign Data Wrapper trait
+/// Cost estimate of a foreign table scan, in the units of Postgres planner
+/// cost, e.g. `seq_page_cost`
 ///
-/// This is the main interface for your foreign data wrapper. Required functions
-/// are listed below, all the others are optional.
+/// The cost of a scan returning `rows` rows is estimated as,
 ///
-/// 1. new
-/// 2. begin_scan
-/// 3. iter_scan
-/// 4. end_scan
+/// ```text
+/// startup cost = startup_cost + request_cost
+/// total cost   = startup_cost + request_cost * requests + tuple_cost * rows
+/// ```
 ///
-/// See the module-level document for more details.
+/// where `requests` is the number of remote requests needed to fetch the
+/// rows, `rows / request_size` rounded up, or 1 if `request_size` is 0.
 ///
-pub trait ForeignDataWrapper {
-    /// Create a FDW instance
-    ///
-    /// `options` is the key-value pairs defined in `CREATE SERVER` SQL. For example,
-    ///
-    /// ```sql
-    /// create server my_helloworld_server
-    ///   foreign data wrapper wrappers_helloworld
-    ///   options (
-    ///     foo 'bar'
-    /// );
-    /// ```
-    ///
-    /// `options` passed here will be a hashmap { 'foo' -> 'bar' }.
-    ///
-    /// You can do any initalization in this function, like saving connection
-    /// info or API url in an variable, but don't do heavy works like database
-    /// connection or API call.
-    fn new(options: &HashMap<String, String>) -> Self;
+/// The `startup_cost` and `tuple_cost` can be overridden by `fdw_startup_cost`
+/// and `fdw_tuple_cost` options on the foreign server or foreign table.
+#[derive(Debug, Clone)]
+pub struct ScanCost {
+    /// cost before the first request is sent, e.g. connection or authentication
+    pub startup_cost: f64,
 
-    /// Obtain relation size estimates for a foreign table
-    ///
-    /// Return the expected number of rows and row size (in bytes) by the
-    /// foreign table scan.
+    /// cost of each returned row, e.g. data transfer and conversion
+    pub tuple_cost: f64,
+
+    /// cost of each remote request, e.g. network latency or query job overhead
+    pub request_cost: f64,
+
+    /// maximum number of rows returned by one request, e.g. API page size,
+    /// 0 means all rows are returned by one request
+    pub request_size: usize,
+}
+
+impl Default for ScanCost {
+    fn default() -> Self {
+        ScanCost {
+            startup_cost: 0.0,
+            tuple_cost: 1.0,
+            request_cost: 0.0,
+            request_size: 0,
+        }
+    }
+}
+
+impl ScanCost {
+    /// Return the startup cost and total cost of a scan returning `rows` rows
+    pub fn path_costs(&self, rows: f64) -> (f64, f64) {
+        let requests = if self.request_size > 0 {
+            (rows / self.request_size as f64).ceil().max(1.0)
+        } else {
+            1.0
+        };
+        let startup_cost = self.startup_cost + self.request_cost;
+        let total_cost = self.startup_cost + self.request_cost * requests + self.tuple_cost * rows;
+        (startup_cost, total_cost)
+    }
+}
+
+/// Notifier of an asynchronous foreign scan
+///
+/// It is passed to [`begin_async_scan`](ForeignDataWrapper::begin_async_scan)
+/// and can be sent to another thread, which calls [`notify`](Self::notify)
+/// once the scan result is ready to be fetched by `iter_scan`.
+#[derive(Debug, Clone)]
+pub struct AsyncNotifier {
+    sock: Arc<UnixStream>,
+}
+
+impl AsyncNotifier {
+    pub(crate) fn new(sock: UnixStream) -> Self {
+        AsyncNotifier {
+            sock: Arc::new(sock),
+        }
+    }
+
+    /// Wake up Postgres to fetch the scan result
+    ///
+    /// It is fine to call it more than once, or after the scan has ended.
+    pub fn notify(&self) {
+        let _ = (&*self.sock).write(&[1]);
+    }
+}
+
+/// Join type of a [`Join`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinType {
+    #[default]
+    Inner,
+    Left,
+    Right,
+    Full,
+}
+
+impl JoinType {
+    pub fn deparse(&self) -> &'static str {
+        match self {
+            JoinType::Inner => "inner join",
+            JoinType::Left => "left join",
+            JoinType::Right => "right join",
+            JoinType::Full => "full join",
+        }
+    }
+}
+
+/// One side of a [`Join`], which is a foreign table scan
+///
+/// - `quals` - conditions on this foreign table only, they must be applied
+///   before the join
+/// - `columns` - columns of this foreign table needed by the join result
+/// - `options` - the options defined when `CREATE FOREIGN TABLE`
+#[derive(Debug, Clone, Default)]
+pub struct JoinRel {
+    pub quals: Vec<Qual>,
+    pub columns: Vec<Column>,
+    pub options: HashMap<String, String>,
+}
+
+/// Join condition, a.k.a the `ON` clause of a join
+///
+/// Currently only binary operators between a column of the outer relation
+/// and a column of the inner relation are supported. Use
+/// [`Deparser::join_qual`](crate::deparse::Deparser::join_qual) to deparse it
+/// for a remote engine.
+///
+/// ## Examples
+///
+/// ```sql
+/// select * from a join b on a.id = b.a_id;
+/// -- JoinQual { outer_field: "id", operator: "=", inner_field: "a_id" }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct JoinQual {
+    pub outer_field: String,
+    pub operator: String,
+    pub inner_field: String,
+}
+
+/// A join between two foreign tables on the same foreign server
+///
+/// Rows produced by the join scan must contain the `outer` columns first,
+/// followed by the `inner` columns.
+///
+/// ## Examples
+///
+/// ```sql
+/// select a.id, b.col from a left join b on a.id = b.a_id where a.id > 42;
+/// -- Join {
+/// --   join_type: Left,
+/// --   outer: JoinRel {
+/// --     quals: [Qual { field: "id", operator: ">", value: Cell(I32(42)), use_or: false, param: None }],
+/// --     columns: [Column { name: "id", num: 1, type_oid: 20, typmod: -1 }],
+/// --     options: {"table": "a"}
+/// --   },
+/// --   inner: JoinRel {
+/// --     quals: [],
+/// --     columns: [Column { name: "a_id", num: 1, type_oid: 20, typmod: -1 }, Column { name: "col", num: 2, type_oid: 25, typmod: -1 }],
+/// --     options: {"table": "b"}
+/// --   },
+/// --   quals: [JoinQual { outer_field: "id", operator: "=", inner_field: "a_id" }]
+/// -- }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Join {
+    pub join_type: JoinType,
+    pub outer: JoinRel,
+    pub inner: JoinRel,
+    pub quals: Vec<JoinQual>,
+}
+
+/// Aggregate function call in an [`Aggregate`]
+///
+/// Only built-in aggregate functions without `ORDER BY` or `FILTER` clause,
+/// and with no argument or a single column argument are supported.
+///
+/// - `column` - the argument column, `None` for `count(*)`
+/// - `type_oid` - type of the aggregate result, cells produced by the FDW
+///   must be of this type
+///
+/// ## Examples
+///
+/// ```sql
+/// count(*)
+/// -- AggregateFunc { name: "count", column: None, distinct: false, type_oid: 20 }
+/// ```
+///
+/// ```sql
+/// sum(distinct amount)
+/// -- AggregateFunc { name: "sum", column: Some("amount"), distinct: true, type_oid: 1700 }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AggregateFunc {
+    pub name: String,
+    pub column: Option<String>,
+    pub distinct: bool,
+    pub type_oid: Oid,
+}
+
+/// `HAVING` condition of an [`Aggregate`], which compares an aggregate
+/// function with a constant
+///
+/// ## Examples
+///
+/// ```sql
+/// having sum(amount) > 100
+/// -- HavingQual { func: AggregateFunc { name: "sum", column: Some("amount"), distinct: false, type_oid: 1700 }, operator: ">", value: Cell(I32(100)) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HavingQual {
+    pub func: AggregateFunc,
+    pub operator: String,
+    pub value: Value,
+}
+
+/// An aggregation on a foreign table, a.k.a aggregate functions with
+/// `GROUP BY` and `HAVING` clauses
+///
+/// - `quals` - `WHERE` clause, which must be applied before the aggregation
+/// - `group_by` - `GROUP BY` columns
+/// - `funcs` - aggregate functions to be computed for each group
+/// - `having` - `HAVING` clause
+/// - `options` - the options defined when `CREATE FOREIGN TABLE`
+///
+/// Rows produced by the aggregate scan must contain the `group_by` columns
+/// first, followed by the results of `funcs`.
+///
+/// ## Examples
+///
+/// ```sql
+/// select col, count(*) from foo where id > 42 group by col having sum(amount) > 100;
+/// -- Aggregate {
+/// --   quals: [Qual { field: "id", operator: ">", value: Cell(I32(42)), use_or: false, param: None }],
+/// --   group_by: [Column { name: "col", num: 2, type_oid: 25, typmod: -1 }],
+/// --   funcs: [AggregateFunc { name: "count", column: None, distinct: false, type_oid: 20 }],
+/// --   having: [HavingQual { func: AggregateFunc { name: "sum", column: Some("amount"), distinct: false, type_oid: 1700 }, operator: ">", value: Cell(I32(100)) }],
+/// --   options: {"table": "foo"}
+/// -- }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Aggregate {
+    pub quals: Vec<Qual>,
+    pub group_by: Vec<Column>,
+    pub funcs: Vec<AggregateFunc>,
+    pub having: Vec<HavingQual>,
+    pub options: HashMap<String, String>,
+}
+
+/// Table list type of an `IMPORT FOREIGN SCHEMA` statement
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ImportSchemaType {
+    /// import all remote tables
+    #[default]
+    All,
+    /// `LIMIT TO (...)`, import the listed remote tables only
+    LimitTo,
+    /// `EXCEPT (...)`, import all remote tables except the listed ones
+    Except,
+}
+
+/// An `IMPORT FOREIGN SCHEMA` statement
+///
+/// ## Examples
+///
+/// ```sql
+/// import foreign schema public
+///   limit to (customers, invoices)
+///   from server my_server into stripe
+///   options (foo 'bar');
+/// -- ImportForeignSchemaStmt {
+/// --   server_name: "my_server",
+/// --   remote_schema: "public",
+/// --   local_schema: "stripe",
+/// --   list_type: LimitTo,
+/// --   table_list: ["customers", "invoices"],
+/// --   options: {"foo": "bar"}
+/// -- }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ImportForeignSchemaStmt {
+    pub server_name: String,
+    pub remote_schema: String,
+    pub local_schema: String,
+    pub list_type: ImportSchemaType,
+    pub table_list: Vec<String>,
+    pub options: HashMap<String, String>,
+}
+
+impl ImportForeignSchemaStmt {
+    /// Check if a remote table should be imported according to the `LIMIT TO`
+    /// or `EXCEPT` table list
+    pub fn is_table_included(&self, table: &str) -> bool {
+        match self.list_type {
+            ImportSchemaType::All => true,
+            ImportSchemaType::LimitTo => self.table_list.iter().any(|t| t == table),
+            ImportSchemaType::Except => !self.table_list.iter().any(|t| t == table),
+        }
+    }
+}
+
+/// The Foreign Data Wrapper trait
+///
+/// This is the main interface for your foreign data wrapper. Required functions
+/// are listed below, all the others are optional.
+///
+/// 1. new
+/// 2. begin_scan
+/// 3. iter_scan
+/// 4. end_scan
+///
+/// See the module-level document for more details.
+///
+pub trait ForeignDataWrapper {
+    /// Create a FDW instance
+    ///
+    /// `options` is the key-value pairs defined in `CREATE SERVER` SQL. For example,
+    ///
+    /// ```sql
+    /// create server my_helloworld_server
+    ///   foreign data wrapper wrappers_helloworld
+    ///   options (
+    ///     foo 'bar'
+    /// );
+    /// ```
+    ///
+    /// `options` passed here will be a hashmap { 'foo' -> 'bar' }.
+    ///
+    /// The options of `CREATE USER MAPPING` for current user, or for `PUBLIC`
+    /// if current user has none, are merged into `options` and take precedence
+    /// over the server ones. So credentials can be defined for each user.
+    ///
+    /// You can do any initalization in this function, like saving connection
+    /// info or API url in an variable, but don't do heavy works like database
+    /// connection or API call.
+    ///
+    /// Return an [`FdwError`] if the instance cannot be created, for example
+    /// a required option is missing.
+    fn new(options: &HashMap<String, String>) -> FdwResult<Self>
+    where
+        Self: Sized;
+
+    /// Obtain relation size estimates for a foreign table
+    ///
+    /// Return the expected number of rows and row size (in bytes) by the
+    /// foreign table scan.
+    ///
+    /// It is called if the `use_remote_estimate` option of the foreign server
+    /// or foreign table is `true`, or if the foreign table has not been
+    /// analyzed and [`can_estimate_rel_size`](Self::can_estimate_rel_size)
+    /// returns `true`. Otherwise the local statistics collected by `ANALYZE`
+    /// are used instead.
     ///
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-SCAN).
     fn get_rel_size(
@@ -444,8 +1595,85 @@ pub trait ForeignDataWrapper {
         _sorts: &[Sort],
         _limit: &Option<Limit>,
         _options: &HashMap<String, String>,
-    ) -> (i64, i32) {
-        (0, 0)
+    ) -> FdwResult<(i64, i32)> {
+        Ok((0, 0))
+    }
+
+    /// Called when planning a scan on a foreign table which has no local
+    /// statistics, before the FDW instance is created
+    ///
+    /// - `options` - the options defined when `CREATE FOREIGN TABLE`
+    ///
+    /// Return `true` to estimate the row count by [`get_rel_size`](Self::get_rel_size)
+    /// until the table is analyzed. Otherwise 1000 rows are assumed, which is
+    /// the default.
+    fn can_estimate_rel_size(_options: &HashMap<String, String>) -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+
+    /// Obtain cost estimates for a foreign table scan
+    ///
+    /// - `options` - the options defined when `CREATE FOREIGN TABLE`
+    ///
+    /// Return the cost shape of scans on the foreign table, which is used
+    /// together with the estimated number of rows to cost the scan, join and
+    /// aggregation paths. See [`ScanCost`] for how the cost is calculated.
+    ///
+    /// By default, each row costs `1.0` and there is no startup or request
+    /// cost.
+    fn get_rel_cost(&mut self, _options: &HashMap<String, String>) -> FdwResult<ScanCost> {
+        Ok(ScanCost::default())
+    }
+
+    /// Called when planning a scan on a foreign table of this FDW
+    ///
+    /// - `quals` - the conditions extracted from the query, the same as the
+    ///   ones passed to `begin_scan`
+    /// - `options` - the options defined when `CREATE FOREIGN TABLE`
+    ///
+    /// Return a flag for each of the `quals`, in the same order. `true` means
+    /// the FDW fully evaluates the qual remotely, so Postgres doesn't need to
+    /// check it again on the returned rows. `false` means the qual needs a
+    /// local recheck, which is always safe. All the quals still are passed to
+    /// `begin_scan` regardless of the flags.
+    ///
+    /// The split is shown as `remote quals` and `local quals` in `EXPLAIN`.
+    fn can_push_down_quals(
+        &mut self,
+        quals: &[Qual],
+        _options: &HashMap<String, String>,
+    ) -> FdwResult<Vec<bool>> {
+        Ok(vec![false; quals.len()])
+    }
+
+    /// Called when planning a scan on a foreign table of this FDW
+    ///
+    /// - `exprs` - the conditions with `AND`, `OR` or `NOT` which cannot be
+    ///   extracted as [`Qual`]
+    /// - `options` - the options defined when `CREATE FOREIGN TABLE`
+    ///
+    /// Return a flag for each of the `exprs`, in the same order. `true` means
+    /// the FDW fully evaluates the expression remotely, the accepted ones are
+    /// passed to [`begin_scan_qual_exprs`](Self::begin_scan_qual_exprs).
+    /// `false` means the expression is evaluated locally by Postgres.
+    fn can_push_down_qual_exprs(
+        &mut self,
+        exprs: &[QualExpr],
+        _options: &HashMap<String, String>,
+    ) -> FdwResult<Vec<bool>> {
+        Ok(vec![false; exprs.len()])
+    }
+
+    /// Called when begin executing a foreign scan, right before `begin_scan`,
+    /// or `explain_scan` when the scan is explained
+    ///
+    /// - `exprs` - the expressions accepted by [`can_push_down_qual_exprs`](Self::can_push_down_qual_exprs),
+    ///   which must be applied together with the `quals` of `begin_scan`
+    fn begin_scan_qual_exprs(&mut self, _exprs: &[QualExpr]) -> FdwResult<()> {
+        Ok(())
     }
 
     /// Called when begin executing a foreign scan
@@ -464,24 +1692,301 @@ pub trait ForeignDataWrapper {
         sorts: &[Sort],
         limit: &Option<Limit>,
         options: &HashMap<String, String>,
-    );
+    ) -> FdwResult<()>;
 
     /// Called when fetch one row from the foreign source
     ///
-    /// FDW must save fetched foreign data into the [`Row`], or return `None` if no more rows to read.
+    /// FDW must save fetched foreign data into the [`Row`], or return `Ok(None)` if no more rows to read.
+    ///
+    /// The cells are converted to the types of target columns, so a cell can
+    /// be of a different type, for example, a [`Cell::String`] is parsed by
+    /// the input function of the column type. The column type modifier is
+    /// applied as well, e.g. the length of `varchar(n)` is checked. A float
+    /// cell with a fractional part is not rounded to an integer column, the
+    /// FDW must round it by itself if that is intended.
     ///
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-SCAN).
-    fn iter_scan(&mut self, row: &mut Row) -> Option<()>;
+    fn iter_scan(&mut self, row: &mut Row) -> FdwResult<Option<()>>;
+
+    /// Returns the number of rows fetched in one batch by
+    /// [`iter_scan_batch`](Self::iter_scan_batch)
+    ///
+    /// - `options` - the options defined when `CREATE FOREIGN TABLE`
+    ///
+    /// Default is `None`, which fetches rows one by one by [`iter_scan`](Self::iter_scan).
+    fn scan_batch_size(_options: &HashMap<String, String>) -> Option<usize>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    /// Called when fetch a batch of rows from the foreign source, used instead
+    /// of [`iter_scan`](Self::iter_scan) if [`scan_batch_size`](Self::scan_batch_size)
+    /// returns a batch size
+    ///
+    /// The batch is empty when it is called, and its columns are in the same
+    /// order as the target columns given in `begin_scan`, or the ones of the
+    /// pushed down join and aggregation. FDW must push rows
+    /// by [`RowBatch::push_row`] and fill their cells by position, up to the
+    /// batch capacity.
+    ///
+    /// Return the number of rows pushed, or `Ok(0)` if no more rows to read.
+    fn iter_scan_batch(&mut self, _batch: &mut RowBatch) -> FdwResult<usize> {
+        Ok(0)
+    }
 
     /// Called when restart the scan from the beginning.
     ///
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-SCAN).
-    fn re_scan(&mut self) {}
+    fn re_scan(&mut self) -> FdwResult<()> {
+        Ok(())
+    }
 
     /// Called when end the scan
     ///
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-SCAN).
-    fn end_scan(&mut self);
+    fn end_scan(&mut self) -> FdwResult<()>;
+
+    /// Called when `EXPLAIN` a foreign scan
+    ///
+    /// - `quals`, `columns`, `sorts`, `limit` and `options` - same as `begin_scan`
+    /// - `verbose` - true if `EXPLAIN VERBOSE` is used
+    ///
+    /// Return a list of `(label, value)` properties to be shown in the
+    /// `EXPLAIN` output, for example the query or URL sent to the remote
+    /// source. `begin_scan` is not called before it unless `EXPLAIN ANALYZE`
+    /// is used, so the properties must be built from the arguments. Quals
+    /// with parameters hold placeholder values in a plain `EXPLAIN`.
+    ///
+    /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-EXPLAIN).
+    fn explain_scan(
+        &self,
+        _quals: &[Qual],
+        _columns: &[Column],
+        _sorts: &[Sort],
+        _limit: &Option<Limit>,
+        _options: &HashMap<String, String>,
+        _verbose: bool,
+    ) -> FdwResult<Vec<(String, String)>> {
+        Ok(Vec::new())
+    }
+
+    /// Called when planning a scan on a foreign table of this FDW
+    ///
+    /// - `options` - the options defined when `CREATE FOREIGN TABLE`
+    ///
+    /// Return `true` if the scan can be executed asynchronously, in which
+    /// case Postgres may run it concurrently with other asynchronous scans
+    /// under the same `Append` node, for example the partitions of a
+    /// partitioned table or the branches of a `UNION ALL`, and call
+    /// [`begin_async_scan`](Self::begin_async_scan) instead of `begin_scan`.
+    /// Joins and aggregations pushed down are always executed synchronously.
+    ///
+    /// This requires the `enable_async_append` setting, which is on by default.
+    ///
+    /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-ASYNC).
+    fn can_async_scan(&mut self, _options: &HashMap<String, String>) -> FdwResult<bool> {
+        Ok(false)
+    }
+
+    /// Called when begin executing an asynchronous scan accepted by [`can_async_scan`](Self::can_async_scan)
+    ///
+    /// - `quals`, `columns`, `sorts`, `limit` and `options` - same as `begin_scan`
+    /// - `notifier` - to wake up Postgres when the scan result is ready
+    ///
+    /// The FDW should start the remote request in the background, for example
+    /// in another thread, and return without waiting for it. Once the result
+    /// is ready, it must call [`AsyncNotifier::notify`], after which the rows
+    /// are fetched by `iter_scan` as usual. The background work must not call
+    /// any Postgres functions, and should be stopped by `end_scan` if it is
+    /// still running.
+    ///
+    /// It may not be called at all if the `Append` node finishes early, but
+    /// `end_scan` is always called. The default implementation calls
+    /// `begin_scan` and notifies immediately.
+    fn begin_async_scan(
+        &mut self,
+        quals: &[Qual],
+        columns: &[Column],
+        sorts: &[Sort],
+        limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+        notifier: AsyncNotifier,
+    ) -> FdwResult<()> {
+        self.begin_scan(quals, columns, sorts, limit, options)?;
+        notifier.notify();
+        Ok(())
+    }
+
+    /// Called when planning a query on a foreign table of this FDW
+    ///
+    /// - `options` - the options defined when `CREATE FOREIGN TABLE`
+    ///
+    /// Return `true` if scans on the table are parallel safe, so that the
+    /// query can use parallel workers. A scan in a parallel worker is executed
+    /// by a separate FDW instance created by `new` in the worker process, so it
+    /// must not depend on any state of the leader process. Pushed down joins
+    /// and aggregations are always executed in the leader. Default is `false`,
+    /// which disables parallel query for the whole plan.
+    ///
+    /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-PARALLEL).
+    fn is_parallel_safe(_options: &HashMap<String, String>) -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+
+    /// Called when planning a scan accepted by [`is_parallel_safe`](Self::is_parallel_safe)
+    ///
+    /// - `quals` - same as `begin_scan`, the parameter values are placeholders
+    /// - `options` - the options defined when `CREATE FOREIGN TABLE`
+    ///
+    /// Return the number of partitions the scan can be split into. If it is
+    /// more than 1, a parallel scan may be planned where the leader and
+    /// workers claim the partitions one by one, and scan each of them by
+    /// [`begin_parallel_scan`](Self::begin_parallel_scan), `iter_scan` and
+    /// `end_scan`. Sort and limit are not pushed down to parallel scan.
+    ///
+    /// Default is 1, the scan is not split but can still be executed in a
+    /// parallel worker, for example on the inner side of a parallel join.
+    fn get_parallel_partitions(
+        &mut self,
+        _quals: &[Qual],
+        _options: &HashMap<String, String>,
+    ) -> FdwResult<usize> {
+        Ok(1)
+    }
+
+    /// Called when begin scanning a partition of a parallel scan
+    ///
+    /// - `quals`, `columns` and `options` - same as `begin_scan`
+    /// - `partition` - the partition to scan, from 0 to `partitions - 1`
+    /// - `partitions` - the number of partitions returned by [`get_parallel_partitions`](Self::get_parallel_partitions)
+    ///
+    /// The rows of all partitions together must be the same as the rows
+    /// returned by `begin_scan`. Each partition is scanned exactly once, by
+    /// either the leader or a worker.
+    fn begin_parallel_scan(
+        &mut self,
+        _quals: &[Qual],
+        _columns: &[Column],
+        _options: &HashMap<String, String>,
+        _partition: usize,
+        _partitions: usize,
+    ) -> FdwResult<()> {
+        Err(FdwError::new(
+            PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+            "parallel scan is not supported",
+        ))
+    }
+
+    /// Called when planning a join between two foreign tables of this FDW on
+    /// the same foreign server
+    ///
+    /// - `join` - the join to be pushed down
+    ///
+    /// Return `true` to execute the join remotely, in which case Postgres
+    /// will call [`begin_join_scan`](Self::begin_join_scan) instead of
+    /// `begin_scan` and the FDW must apply all the quals of both relations
+    /// and the join quals by itself. Return `false` to let Postgres scan the
+    /// two foreign tables separately and join them locally.
+    ///
+    /// Joins are only considered when all the conditions on both foreign
+    /// tables and the join can be expressed as [`Qual`] and [`JoinQual`], all
+    /// of those quals are accepted by
+    /// [`can_push_down_quals`](Self::can_push_down_quals), and the query
+    /// doesn't lock rows.
+    ///
+    /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-JOIN-SCAN).
+    fn can_push_down_join(&mut self, _join: &Join) -> FdwResult<bool> {
+        Ok(false)
+    }
+
+    /// Called when begin executing a join accepted by [`can_push_down_join`](Self::can_push_down_join)
+    ///
+    /// - `join` - the join to be executed remotely
+    ///
+    /// The rows are then fetched by `iter_scan`, each row must contain the
+    /// columns of `join.outer.columns` followed by the columns of
+    /// `join.inner.columns`, in the same order.
+    fn begin_join_scan(&mut self, _join: &Join) -> FdwResult<()> {
+        Err(FdwError::new(
+            PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+            "join push down is not supported",
+        ))
+    }
+
+    /// Called when `EXPLAIN` a join accepted by [`can_push_down_join`](Self::can_push_down_join)
+    ///
+    /// Same as [`explain_scan`](Self::explain_scan), but for the join
+    /// executed by [`begin_join_scan`](Self::begin_join_scan).
+    fn explain_join_scan(&self, _join: &Join, _verbose: bool) -> FdwResult<Vec<(String, String)>> {
+        Ok(Vec::new())
+    }
+
+    /// Called when planning an aggregation on a foreign table of this FDW
+    ///
+    /// - `aggregate` - the aggregation to be pushed down
+    ///
+    /// Return `true` to compute the aggregation remotely, in which case
+    /// Postgres will call [`begin_aggregate_scan`](Self::begin_aggregate_scan)
+    /// instead of `begin_scan` and the FDW must apply the quals, grouping and
+    /// `HAVING` conditions by itself. Return `false` to let Postgres scan the
+    /// foreign table and aggregate the rows locally.
+    ///
+    /// Aggregations are only considered when all the conditions on the
+    /// foreign table can be expressed as [`Qual`] and are accepted by
+    /// [`can_push_down_quals`](Self::can_push_down_quals), and all the
+    /// grouping expressions are plain columns.
+    ///
+    /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-UPPER-PLANNING).
+    fn can_push_down_aggregate(&mut self, _aggregate: &Aggregate) -> FdwResult<bool> {
+        Ok(false)
+    }
+
+    /// Called when begin executing an aggregation accepted by [`can_push_down_aggregate`](Self::can_push_down_aggregate)
+    ///
+    /// - `aggregate` - the aggregation to be executed remotely
+    ///
+    /// The rows are then fetched by `iter_scan`, each row must contain the
+    /// columns of `aggregate.group_by` followed by the results of
+    /// `aggregate.funcs`, in the same order.
+    fn begin_aggregate_scan(&mut self, _aggregate: &Aggregate) -> FdwResult<()> {
+        Err(FdwError::new(
+            PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+            "aggregate push down is not supported",
+        ))
+    }
+
+    /// Called when `EXPLAIN` an aggregation accepted by [`can_push_down_aggregate`](Self::can_push_down_aggregate)
+    ///
+    /// Same as [`explain_scan`](Self::explain_scan), but for the aggregation
+    /// executed by [`begin_aggregate_scan`](Self::begin_aggregate_scan).
+    fn explain_aggregate_scan(
+        &self,
+        _aggregate: &Aggregate,
+        _verbose: bool,
+    ) -> FdwResult<Vec<(String, String)>> {
+        Ok(Vec::new())
+    }
+
+    /// Called when planning a scan on a foreign table of this FDW
+    ///
+    /// - `sorts` - a sort order which is useful to the query, for example
+    ///   its `ORDER BY` clause or a merge join key
+    ///
+    /// Return `true` if the FDW guarantees to return rows in exactly this
+    /// order, including the collation and nulls ordering, whenever the same
+    /// list is passed as `sorts` to `begin_scan`. Postgres may then skip the
+    /// local sort on top of the foreign scan. Return `false` to let Postgres
+    /// sort the rows locally.
+    ///
+    /// This may be called several times for different sort orders.
+    fn can_push_down_sort(&mut self, _sorts: &[Sort]) -> FdwResult<bool> {
+        Ok(false)
+    }
 
     /// Called when begin executing a foreign table modification operation.
     ///
@@ -505,49 +2010,268 @@ pub trait ForeignDataWrapper {
     /// ```
     ///
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-UPDATE).
-    fn begin_modify(&mut self, _options: &HashMap<String, String>) {}
+    fn begin_modify(&mut self, _options: &HashMap<String, String>) -> FdwResult<()> {
+        Ok(())
+    }
 
     /// Called when insert one row into the foreign table
     ///
     /// - row - the new row to be inserted
     ///
+    /// Return the row actually inserted on the foreign source, such as one
+    /// with server-generated id, to be used by `RETURNING` clause. Columns in
+    /// the returned row override the ones in the new row, and `None` means
+    /// the new row is returned as is.
+    ///
+    /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-UPDATE).
+    fn insert(&mut self, _row: &Row) -> FdwResult<Option<Row>> {
+        Ok(None)
+    }
+
+    /// Called when insert a batch of rows into the foreign table
+    ///
+    /// - rows - the new rows to be inserted
+    ///
+    /// Batch insert is enabled by setting `batch_size` option on the foreign
+    /// table or its server, it cannot be used with `RETURNING` clause. The
+    /// default implementation calls [`insert`](Self::insert) for each row.
+    ///
+    /// For example,
+    ///
+    /// ```sql
+    /// create foreign table my_foreign_table (
+    ///   id bigint,
+    ///   name text
+    /// )
+    ///   server my_server
+    ///   options (
+    ///     batch_size '1000'
+    ///   );
+    /// ```
+    ///
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-UPDATE).
-    fn insert(&mut self, _row: &Row) {}
+    fn insert_batch(&mut self, rows: &[Row]) -> FdwResult<()> {
+        for row in rows {
+            self.insert(row)?;
+        }
+        Ok(())
+    }
 
     /// Called when update one row into the foreign table
     ///
     /// - rowid - the `rowid_column` cell
     /// - new_row - the new row with updated cells
     ///
+    /// Return the updated row to be used by `RETURNING` clause, columns not in
+    /// the returned row keep their values in the new row.
+    ///
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-UPDATE).
-    fn update(&mut self, _rowid: &Cell, _new_row: &Row) {}
+    fn update(&mut self, _rowid: &Cell, _new_row: &Row) -> FdwResult<Option<Row>> {
+        Ok(None)
+    }
 
     /// Called when delete one row into the foreign table
     ///
     /// - rowid - the `rowid_column` cell
     ///
+    /// Return the deleted row to be used by `RETURNING` clause, columns not in
+    /// the returned row are null.
+    ///
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-UPDATE).
-    fn delete(&mut self, _rowid: &Cell) {}
+    fn delete(&mut self, _rowid: &Cell) -> FdwResult<Option<Row>> {
+        Ok(None)
+    }
 
     /// Called when end the table update
     ///
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-UPDATE).
-    fn end_modify(&mut self) {}
+    fn end_modify(&mut self) -> FdwResult<()> {
+        Ok(())
+    }
+
+    /// Called when `EXPLAIN` a foreign table modification operation
+    ///
+    /// - `options` - the options defined when `CREATE FOREIGN TABLE`
+    /// - `verbose` - true if `EXPLAIN VERBOSE` is used
+    ///
+    /// Return a list of `(label, value)` properties to be shown in the
+    /// `EXPLAIN` output. `begin_modify` is not called before it unless
+    /// `EXPLAIN ANALYZE` is used.
+    ///
+    /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-EXPLAIN).
+    fn explain_modify(
+        &self,
+        _options: &HashMap<String, String>,
+        _verbose: bool,
+    ) -> FdwResult<Vec<(String, String)>> {
+        Ok(Vec::new())
+    }
+
+    /// Called when `TRUNCATE` a foreign table of this FDW
+    ///
+    /// - `options` - the options defined when `CREATE FOREIGN TABLE`
+    /// - `restart_seqs` - true if `RESTART IDENTITY` is specified
+    /// - `cascade` - true if `CASCADE` is specified
+    ///
+    /// It is called once for each foreign table to be truncated. When more
+    /// than one foreign table on the same server are truncated in one
+    /// statement, they are truncated by the same FDW instance.
+    ///
+    /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-TRUNCATE).
+    fn truncate(
+        &mut self,
+        _options: &HashMap<String, String>,
+        _restart_seqs: bool,
+        _cascade: bool,
+    ) -> FdwResult<()> {
+        Err(FdwError::new(
+            PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+            "truncate is not supported",
+        ))
+    }
+
+    /// Called before the local transaction commits, if this FDW instance
+    /// modified a foreign table in the transaction
+    ///
+    /// It can be used to flush the modifications buffered since
+    /// [`begin_modify`](Self::begin_modify) to the foreign source. Return an
+    /// error to abort the local transaction, [`on_abort`](Self::on_abort) is
+    /// then called on all the instances, including this one.
+    fn on_pre_commit(&mut self) -> FdwResult<()> {
+        Ok(())
+    }
+
+    /// Called after the local transaction is committed, if this FDW instance
+    /// modified a foreign table in the transaction
+    ///
+    /// The transaction cannot be aborted anymore, so an error returned here
+    /// is reported as a warning.
+    fn on_commit(&mut self) -> FdwResult<()> {
+        Ok(())
+    }
+
+    /// Called when the local transaction is aborted, if this FDW instance
+    /// modified a foreign table in the transaction
+    ///
+    /// It can be used to discard the buffered modifications, or to issue
+    /// compensating actions for the ones already sent to the foreign source.
+    /// An error returned here is reported as a warning.
+    fn on_abort(&mut self) -> FdwResult<()> {
+        Ok(())
+    }
+
+    /// Called before a savepoint is released, if this FDW instance modified a
+    /// foreign table after the savepoint was set
+    ///
+    /// The instance is moved to the enclosing transaction or savepoint, so it
+    /// is still notified when that ends. Return an error to abort the
+    /// savepoint.
+    fn on_release_savepoint(&mut self) -> FdwResult<()> {
+        Ok(())
+    }
+
+    /// Called when rolling back to a savepoint, if this FDW instance modified
+    /// a foreign table after the savepoint was set
+    ///
+    /// This is the last hook called on the instance, an error returned here
+    /// is reported as a warning.
+    fn on_rollback_savepoint(&mut self) -> FdwResult<()> {
+        Ok(())
+    }
+
+    /// Called when `ANALYZE` a foreign table of this FDW, before the FDW
+    /// instance is created
+    ///
+    /// - `options` - the options defined when `CREATE FOREIGN TABLE`
+    ///
+    /// Return `true` if the table can be analyzed by [`analyze`](Self::analyze).
+    /// Otherwise `ANALYZE` skips the table, which is the default.
+    fn can_analyze(_options: &HashMap<String, String>) -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+
+    /// Called when `ANALYZE` a foreign table accepted by [`can_analyze`](Self::can_analyze)
+    ///
+    /// - `columns` - all the columns of the foreign table
+    /// - `sample_size` - the target number of sample rows
+    /// - `options` - the options defined when `CREATE FOREIGN TABLE`
+    ///
+    /// Return the total number of rows in the foreign table, and up to
+    /// `sample_size` rows randomly sampled from it. The sample rows can be
+    /// empty if only the row count is cheap to get, in which case no column
+    /// statistics are collected. Rows more than `sample_size` are ignored.
+    ///
+    /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-ANALYZE).
+    fn analyze(
+        &mut self,
+        _columns: &[Column],
+        _sample_size: usize,
+        _options: &HashMap<String, String>,
+    ) -> FdwResult<(i64, Vec<Row>)> {
+        Err(FdwError::new(
+            PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+            "analyze is not supported",
+        ))
+    }
+
+    /// Called when `IMPORT FOREIGN SCHEMA` is executed
+    ///
+    /// - `stmt` - the import statement, including the remote schema, the
+    ///   `LIMIT TO`/`EXCEPT` table list and the statement options
+    ///
+    /// The FDW instance is created with the options of the server named in
+    /// the statement. Return a list of `CREATE FOREIGN TABLE` SQL commands,
+    /// each of which must use `stmt.server_name` as its server. Postgres
+    /// creates those tables in `stmt.local_schema` and skips the ones excluded
+    /// by the table list, but FDW should also use
+    /// [`ImportForeignSchemaStmt::is_table_included`] to avoid unnecessary
+    /// remote introspection.
+    ///
+    /// For example,
+    ///
+    /// ```rust,no_run
+    /// fn import_foreign_schema(
+    ///     &mut self,
+    ///     stmt: ImportForeignSchemaStmt,
+    /// ) -> FdwResult<Vec<String>> {
+    ///     let mut ret = Vec::new();
+    ///     if stmt.is_table_included("hello") {
+    ///         ret.push(format!(
+    ///             "create foreign table if not exists hello (id bigint, col text) server {}",
+    ///             stmt.server_name
+    ///         ));
+    ///     }
+    ///     Ok(ret)
+    /// }
+    /// ```
+    ///
+    /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-IMPORT).
+    fn import_foreign_schema(&mut self, _stmt: ImportForeignSchemaStmt) -> FdwResult<Vec<String>> {
+        Err(FdwError::new(
+            PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+            "IMPORT FOREIGN SCHEMA is not supported",
+        ))
+    }
 
     /// Returns a FdwRoutine for the FDW
     ///
     /// Not to be used directly, use [`wrappers_fdw`](crate::wrappers_fdw) macro instead.
     fn fdw_routine() -> FdwRoutine
     where
-        Self: Sized,
+        Self: Sized + 'static,
     {
-        use crate::{modify, scan};
+        use crate::{analyze, import_foreign_schema, modify, scan, truncate};
         let mut fdw_routine =
             FdwRoutine::<AllocatedByRust>::alloc_node(pg_sys::NodeTag_T_FdwRoutine);
 
         // plan phase
         fdw_routine.GetForeignRelSize = Some(scan::get_foreign_rel_size::<Self>);
         fdw_routine.GetForeignPaths = Some(scan::get_foreign_paths::<Self>);
+        fdw_routine.GetForeignJoinPaths = Some(scan::get_foreign_join_paths::<Self>);
+        fdw_routine.GetForeignUpperPaths = Some(scan::get_foreign_upper_paths::<Self>);
         fdw_routine.GetForeignPlan = Some(scan::get_foreign_plan::<Self>);
         fdw_routine.ExplainForeignScan = Some(scan::explain_foreign_scan::<Self>);
 
@@ -557,14 +2281,41 @@ pub trait ForeignDataWrapper {
         fdw_routine.ReScanForeignScan = Some(scan::re_scan_foreign_scan::<Self>);
         fdw_routine.EndForeignScan = Some(scan::end_foreign_scan::<Self>);
 
+        // async scan phase
+        fdw_routine.IsForeignPathAsyncCapable = Some(scan::is_foreign_path_async_capable::<Self>);
+        fdw_routine.ForeignAsyncRequest = Some(scan::foreign_async_request::<Self>);
+        fdw_routine.ForeignAsyncConfigureWait = Some(scan::foreign_async_configure_wait::<Self>);
+        fdw_routine.ForeignAsyncNotify = Some(scan::foreign_async_notify::<Self>);
+
+        // parallel scan phase
+        fdw_routine.IsForeignScanParallelSafe = Some(scan::is_foreign_scan_parallel_safe::<Self>);
+        fdw_routine.EstimateDSMForeignScan = Some(scan::estimate_dsm_foreign_scan::<Self>);
+        fdw_routine.InitializeDSMForeignScan = Some(scan::initialize_dsm_foreign_scan::<Self>);
+        fdw_routine.ReInitializeDSMForeignScan = Some(scan::re_initialize_dsm_foreign_scan::<Self>);
+        fdw_routine.InitializeWorkerForeignScan =
+            Some(scan::initialize_worker_foreign_scan::<Self>);
+
         // modify phase
         fdw_routine.AddForeignUpdateTargets = Some(modify::add_foreign_update_targets);
         fdw_routine.PlanForeignModify = Some(modify::plan_foreign_modify::<Self>);
         fdw_routine.BeginForeignModify = Some(modify::begin_foreign_modify::<Self>);
         fdw_routine.ExecForeignInsert = Some(modify::exec_foreign_insert::<Self>);
+        fdw_routine.GetForeignModifyBatchSize = Some(modify::get_foreign_modify_batch_size::<Self>);
+        fdw_routine.ExecForeignBatchInsert = Some(modify::exec_foreign_batch_insert::<Self>);
         fdw_routine.ExecForeignDelete = Some(modify::exec_foreign_delete::<Self>);
         fdw_routine.ExecForeignUpdate = Some(modify::exec_foreign_update::<Self>);
         fdw_routine.EndForeignModify = Some(modify::end_foreign_modify::<Self>);
+        fdw_routine.ExplainForeignModify = Some(modify::explain_foreign_modify::<Self>);
+
+        // truncate
+        fdw_routine.ExecForeignTruncate = Some(truncate::exec_foreign_truncate::<Self>);
+
+        // analyze
+        fdw_routine.AnalyzeForeignTable = Some(analyze::analyze_foreign_table::<Self>);
+
+        // import foreign schema
+        fdw_routine.ImportForeignSchema =
+            Some(import_foreign_schema::import_foreign_schema::<Self>);
 
         Self::fdw_routine_hook(&mut fdw_routine);
         fdw_routine.into_pg_boxed()
@@ -574,21 +2325,35 @@ pub trait ForeignDataWrapper {
     /// after completing its initialization.
     fn fdw_routine_hook(_routine: &mut FdwRoutine<AllocatedByRust>) {}
 
+    /// Returns the options accepted by the FDW
+    ///
+    /// The declared options are checked by the default [`validator`](Self::validator)
+    /// and listed by the `<snake_case_fdw_name>_options()` SQL function. The
+    /// default is an empty list, which means options are not checked. See
+    /// [`options`](crate::options) module for more details.
+    fn options() -> &'static [OptionDef] {
+        &[]
+    }
+
     /// Validator function for validating options given in `CREATE` and `ALTER`
     /// commands for its foreign data wrapper, as well as foreign servers, user
     /// mappings, and foreign tables using the wrapper.
     ///
+    /// The default implementation validates options against the ones declared
+    /// by [`options`](Self::options), it rejects unknown or malformed options
+    /// and missing required options.
+    ///
     /// [See more details about validator](https://www.postgresql.org/docs/current/fdw-functions.html)
     ///
     /// # Example
     ///
     /// ```rust,no_run
-    /// fn validator(opt_list: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) {
+    /// fn validator(opt_list: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) -> FdwResult<()> {
     ///     if let Some(oid) = catalog {
     ///         match oid {
     ///             FOREIGN_DATA_WRAPPER_RELATION_ID => {
     ///                 // check a required option when create foreign data wrapper
-    ///                 check_options_contain(&opt_list, "required_option");
+    ///                 check_options_contain(&opt_list, "required_option")?;
     ///             }
     ///             FOREIGN_SERVER_RELATION_ID => {
     ///                 // check option here when create server
@@ -596,10 +2361,17 @@ pub trait ForeignDataWrapper {
     ///             FOREIGN_TABLE_RELATION_ID => {
     ///                 // check option here when create foreign table
     ///             }
+    ///             USER_MAPPING_RELATION_ID => {
+    ///                 // check option here when create user mapping
+    ///             }
     ///             _ => {}
     ///         }
     ///     }
+    ///
+    ///     Ok(())
     /// }
     /// ```
-    fn validator(_options: Vec<Option<String>>, _catalog: Option<pg_sys::Oid>) {}
+    fn validator(options: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) -> FdwResult<()> {
+        validate_options(Self::options(), &options, catalog)
+    }
 }
@@ -33,7 +33,9 @@
 //! - i64
 //! - String
 //! - Timestamp
+//! - TimestampWithTimeZone
 //! - JsonB
+//! - Uuid
 //!
 //! See the full supported types list in [`interface::Cell`]. More types will be added in the future if needed or you can [raise a request](https://github.com/supabase/wrappers/issues) to us.
 //!
@@ -43,17 +45,38 @@
 //!
 //! - Query planning phase
 //!   - [get_rel_size()](`interface::ForeignDataWrapper#method.get_rel_size`)
+//!   - [can_estimate_rel_size()](`interface::ForeignDataWrapper#method.can_estimate_rel_size`)
 //! - Scan phase
 //!   - [begin_scan()](`interface::ForeignDataWrapper#tymethod.begin_scan`) *required*
 //!   - [iter_scan()](`interface::ForeignDataWrapper#tymethod.iter_scan`) *required*
 //!   - [re_scan()](`interface::ForeignDataWrapper#method.re_scan`)
 //!   - [end_scan()](`interface::ForeignDataWrapper#tymethod.end_scan`) *required*
+//! - Async scan phase
+//!   - [can_async_scan()](`interface::ForeignDataWrapper#method.can_async_scan`)
+//!   - [begin_async_scan()](`interface::ForeignDataWrapper#method.begin_async_scan`)
+//! - Parallel scan phase
+//!   - [is_parallel_safe()](`interface::ForeignDataWrapper#method.is_parallel_safe`)
+//!   - [get_parallel_partitions()](`interface::ForeignDataWrapper#method.get_parallel_partitions`)
+//!   - [begin_parallel_scan()](`interface::ForeignDataWrapper#method.begin_parallel_scan`)
 //! - Modify phase
 //!   - [begin_modify()](`interface::ForeignDataWrapper#method.begin_modify`)
 //!   - [insert()](`interface::ForeignDataWrapper#method.insert`)
 //!   - [update()](`interface::ForeignDataWrapper#method.update`)
 //!   - [delete()](`interface::ForeignDataWrapper#method.delete`)
 //!   - [end_modify()](`interface::ForeignDataWrapper#method.end_modify`)
+//!   - [truncate()](`interface::ForeignDataWrapper#method.truncate`)
+//! - Transaction phase
+//!   - [on_pre_commit()](`interface::ForeignDataWrapper#method.on_pre_commit`)
+//!   - [on_commit()](`interface::ForeignDataWrapper#method.on_commit`)
+//!   - [on_abort()](`interface::ForeignDataWrapper#method.on_abort`)
+//!   - [on_release_savepoint()](`interface::ForeignDataWrapper#method.on_release_savepoint`)
+//!   - [on_rollback_savepoint()](`interface::ForeignDataWrapper#method.on_rollback_savepoint`)
+//! - Analyze phase
+//!   - [can_analyze()](`interface::ForeignDataWrapper#method.can_analyze`)
+//!   - [analyze()](`interface::ForeignDataWrapper#method.analyze`)
+//! - Explain phase
+//!   - [explain_scan()](`interface::ForeignDataWrapper#method.explain_scan`)
+//!   - [explain_modify()](`interface::ForeignDataWrapper#method.explain_modify`)
 //!
 //! To give different functionalities to your FDW, you can choose different callback functions to implement. The required ones are `begin_scan`, `iter_scan` and `end_scan`, all the others are optional. See [Postgres FDW document](https://www.postgresql.org/docs/current/fdw-callbacks.html) for more details about FDW development.
 //!
@@ -76,7 +99,7 @@
 //! }
 //!
 //! impl ForeignDataWrapper for HelloWorldFdw {
-//!     pub fn new(options: &HashMap<String, String>) -> Self {
+//!     fn new(options: &HashMap<String, String>) -> FdwResult<Self> {
 //!         // 'options' is the key-value pairs defined in `CREATE SERVER` SQL, for example,
 //!         //
 //!         // create server my_helloworld_server
@@ -90,10 +113,10 @@
 //!         // You can do any initalization in this new() function, like saving connection
 //!         // info or API url in an variable, but don't do heavy works like database
 //!         // connection or API call.
-//!         Self {
+//!         Ok(Self {
 //!             row_cnt: 0,
 //!             tgt_cols: Vec::new(),
-//!         }
+//!         })
 //!     }
 //! }
 //! ```
@@ -128,15 +151,17 @@
 //!         _sorts: &[Sort],
 //!         _limit: &Option<Limit>,
 //!         _options: &HashMap<String, String>,
-//!     ) {
+//!     ) -> FdwResult<()> {
 //!         // reset row count
 //!         self.row_cnt = 0;
 //!
 //!         // save a copy of target columns
 //!         self.tgt_cols = columns.to_vec();
+//!
+//!         Ok(())
 //!     }
 //!
-//!     fn iter_scan(&mut self, row: &mut Row) -> Option<()> {
+//!     fn iter_scan(&mut self, row: &mut Row) -> FdwResult<Option<()>> {
 //!         // this is called on each row and we only return one row here
 //!         if self.row_cnt < 1 {
 //!             // add values to row if they are in target column list
@@ -151,15 +176,16 @@
 //!             self.row_cnt += 1;
 //!
 //!             // return the 'Some(())' to Postgres and continue data scan
-//!             return Some(());
+//!             return Ok(Some(()));
 //!         }
 //!
 //!         // return 'None' to stop data scan
-//!         None
+//!         Ok(None)
 //!     }
 //!
-//!     fn end_scan(&mut self) {
+//!     fn end_scan(&mut self) -> FdwResult<()> {
 //!         // we do nothing here, but you can things like resource cleanup and etc.
+//!         Ok(())
 //!     }
 //! }
 //! ```
@@ -210,13 +236,52 @@
 //!          Sort Key: col
 //!          ->  Foreign Scan on hello  (cost=0.00..1.00 rows=1 width=0)
 //!                Filter: (id = 1)
-//!                Wrappers: quals = [Qual { field: "id", operator: "=", value: Cell(I32(1)), use_or: false }]
-//!                Wrappers: tgts = [Column { name: "id", num: 1, type_oid: 20 }, Column { name: "col", num: 2, type_oid: 25 }]
+//!                Wrappers: remote quals = []
+//!                Wrappers: local quals = [Qual { field: "id", operator: "=", value: Cell(I32(1)), use_or: false, param: None }]
+//!                Wrappers: tgts = [Column { name: "id", num: 1, type_oid: 20, typmod: -1 }, Column { name: "col", num: 2, type_oid: 25, typmod: -1 }]
 //!                Wrappers: sorts = [Sort { field: "col", field_no: 2, reversed: false, nulls_first: false, collate: None }]
 //!                Wrappers: limit = Some(Limit { count: 1, offset: 0 })
-//! (9 rows)
+//! (10 rows)
 //! ```
 //!
+//! The FDW can add its own properties to the output by implementing
+//! [explain_scan()](`interface::ForeignDataWrapper#method.explain_scan`), for
+//! example the query sent to the remote server. With `EXPLAIN ANALYZE`, the
+//! time spent in the FDW is also shown as `Wrappers: elapsed = ...`.
+//!
+//! The planner costs a foreign scan by the [`ScanCost`](interface::ScanCost)
+//! returned from [get_rel_cost()](`interface::ForeignDataWrapper#method.get_rel_cost`)
+//! and the row count from [get_rel_size()](`interface::ForeignDataWrapper#method.get_rel_size`).
+//! They can be tuned by these options on the foreign server or foreign table,
+//! the table options take precedence,
+//!
+//! - `fdw_startup_cost` - cost before the first request is sent
+//! - `fdw_tuple_cost` - cost of each returned row
+//! - `use_remote_estimate` - set to `true` to ask the FDW for the row count,
+//!   which may send a query to the remote server on every planning, instead of
+//!   estimating it from local statistics collected by `ANALYZE`, default is `false`.
+//!   The FDW is still asked for the row count of a table which has not been
+//!   analyzed, if [can_estimate_rel_size()](`interface::ForeignDataWrapper#method.can_estimate_rel_size`)
+//!   returns `true`.
+//!
+//! ```sql
+//! alter foreign table hello options (add fdw_startup_cost '100', add use_remote_estimate 'true');
+//! ```
+//!
+//! Connections to the foreign source can be cached across queries in the same
+//! session by creating them with [`cached_connection()`](connection::cached_connection)
+//! in `new()`, see [`connection`] module for more details.
+//!
+//! The options accepted by a FDW can be declared once in
+//! [`options()`](interface::ForeignDataWrapper::options), they are then
+//! checked by the default validator and can be read by typed getters, see
+//! [`options`] module for more details.
+//!
+//! For large scans, a FDW can return a [`Some`] batch size from
+//! [`scan_batch_size()`](interface::ForeignDataWrapper::scan_batch_size) and
+//! fill rows by position in [`iter_scan_batch()`](interface::ForeignDataWrapper::iter_scan_batch)
+//! instead of `iter_scan()`, which saves the per-row overhead of column names.
+//!
 //! ### More FDW Examples
 //!
 //! See more FDW examples which interact with RDBMS or RESTful API.
@@ -228,12 +293,18 @@
 //! - [Airtable](https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/airtable_fdw): A FDW for [Airtable](https://airtable.com/) API which supports data read only.
 //! - [S3](https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/s3_fdw): A FDW for [AWS S3](https://aws.amazon.com/s3/) which supports data read only.
 
+pub mod connection;
+pub mod deparse;
 pub mod interface;
+pub mod options;
 pub mod utils;
 
 /// The prelude includes all necessary imports to make Wrappers work
 pub mod prelude {
+    pub use crate::connection::cached_connection;
+    pub use crate::deparse::{Deparser, Dialect, OperatorPolicy};
     pub use crate::interface::*;
+    pub use crate::options::{OptionCatalog, OptionDef, OptionType, OptionValue};
     pub use crate::utils::*;
     pub use crate::wrappers_fdw;
     pub use ::tokio::runtime::Runtime;
@@ -242,13 +313,19 @@ pub mod prelude {
 use pgx::prelude::*;
 use pgx::AllocatedByPostgres;
 
+mod aggregate;
+mod analyze;
+mod import_foreign_schema;
 mod instance;
 mod limit;
 mod modify;
+mod parallel;
 mod polyfill;
 mod qual;
 mod scan;
 mod sort;
+mod transaction;
+mod truncate;
 
 /// PgBox'ed `FdwRoutine`, used in [`fdw_routine`](interface::ForeignDataWrapper::fdw_routine)
 pub type FdwRoutine<A = AllocatedByPostgres> = PgBox<pg_sys::FdwRoutine, A>;
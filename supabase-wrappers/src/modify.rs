@@ -1,21 +1,27 @@
 use pgx::{
-    debug2, memcxt::PgMemoryContexts, prelude::*, rel::PgRelation, tupdesc::PgTupleDesc, FromDatum,
-    PgSqlErrorCode,
+    debug2, memcxt::PgMemoryContexts, pg_sys::Datum, prelude::*, rel::PgRelation,
+    tupdesc::PgTupleDesc, FromDatum, PgSqlErrorCode,
 };
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::os::raw::c_int;
 use std::ptr;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
+use crate::interface::cell_into_datum;
+use crate::options::BATCH_SIZE;
 use crate::prelude::*;
 
 use super::instance;
 use super::polyfill;
-use super::utils;
+use super::transaction;
+use super::utils::{self, ReportUnwrap};
 
 // Fdw private state for modify
 struct FdwModifyState<W: ForeignDataWrapper> {
-    // foreign data wrapper instance
-    instance: W,
+    // foreign data wrapper instance, shared with the transaction hooks
+    instance: Rc<RefCell<W>>,
 
     // row id attribute number and type id
     rowid_name: String,
@@ -25,41 +31,82 @@ struct FdwModifyState<W: ForeignDataWrapper> {
     // foreign table options
     opts: HashMap<String, String>,
 
+    // number of rows inserted in one batch
+    batch_size: c_int,
+
     // temporary memory context
     tmp_ctx: PgMemoryContexts,
+
+    // true if the time spent in the FDW is measured for EXPLAIN ANALYZE
+    timing: bool,
+
+    // time spent in the FDW when executing the modification
+    elapsed: Duration,
 }
 
 impl<W: ForeignDataWrapper> FdwModifyState<W> {
     unsafe fn new(foreigntableid: pg_sys::Oid) -> Self {
         Self {
-            instance: instance::create_fdw_instance(foreigntableid),
+            instance: Rc::new(RefCell::new(instance::create_fdw_instance(foreigntableid))),
             rowid_name: String::default(),
             rowid_attno: 0,
             rowid_typid: 0,
             opts: HashMap::new(),
+            batch_size: 1,
             tmp_ctx: PgMemoryContexts::CurTransactionContext
                 .switch_to(|_| PgMemoryContexts::new("Wrappers temp modify data")),
+            timing: false,
+            elapsed: Duration::ZERO,
         }
     }
 
-    fn begin_modify(&mut self) {
-        self.instance.begin_modify(&self.opts);
+    fn begin_modify(&mut self) -> FdwResult<()> {
+        let start = self.timing.then(Instant::now);
+        let ret = self.instance.borrow_mut().begin_modify(&self.opts);
+        self.add_elapsed(start);
+        ret
+    }
+
+    fn insert(&mut self, row: &Row) -> FdwResult<Option<Row>> {
+        let start = self.timing.then(Instant::now);
+        let ret = self.instance.borrow_mut().insert(row);
+        self.add_elapsed(start);
+        ret
     }
 
-    fn insert(&mut self, row: &Row) {
-        self.instance.insert(row);
+    fn insert_batch(&mut self, rows: &[Row]) -> FdwResult<()> {
+        let start = self.timing.then(Instant::now);
+        let ret = self.instance.borrow_mut().insert_batch(rows);
+        self.add_elapsed(start);
+        ret
     }
 
-    fn update(&mut self, rowid: &Cell, new_row: &Row) {
-        self.instance.update(rowid, new_row);
+    fn update(&mut self, rowid: &Cell, new_row: &Row) -> FdwResult<Option<Row>> {
+        let start = self.timing.then(Instant::now);
+        let ret = self.instance.borrow_mut().update(rowid, new_row);
+        self.add_elapsed(start);
+        ret
     }
 
-    fn delete(&mut self, rowid: &Cell) {
-        self.instance.delete(rowid);
+    fn delete(&mut self, rowid: &Cell) -> FdwResult<Option<Row>> {
+        let start = self.timing.then(Instant::now);
+        let ret = self.instance.borrow_mut().delete(rowid);
+        self.add_elapsed(start);
+        ret
+    }
+
+    fn explain(&self, verbose: bool) -> FdwResult<Vec<(String, String)>> {
+        self.instance.borrow().explain_modify(&self.opts, verbose)
+    }
+
+    fn add_elapsed(&mut self, start: Option<Instant>) {
+        if let Some(start) = start {
+            self.elapsed += start.elapsed();
+        }
     }
 
-    fn end_modify(&mut self) {
-        self.instance.end_modify();
+    fn end_modify(&mut self) -> FdwResult<()> {
+        self.instance.borrow_mut().end_modify()
     }
 
     fn clear(&mut self) {
@@ -83,11 +130,7 @@ pub(super) extern "C" fn add_foreign_update_targets(
         // get rowid column name from table options
         let ftable = pg_sys::GetForeignTable((*target_relation).rd_id);
         let opts = utils::options_to_hashmap((*ftable).options);
-        let rowid_name = if let Some(name) = require_option("rowid_column", &opts) {
-            name
-        } else {
-            return;
-        };
+        let rowid_name = require_option("rowid_column", &opts).report_unwrap();
 
         // find rowid attribute
         let tup_desc = PgTupleDesc::from_pg_copy((*target_relation).rd_att);
@@ -116,22 +159,29 @@ pub(super) extern "C" fn add_foreign_update_targets(
     }
 }
 
+// get batch size from foreign table options, or from the foreign server
+// options if it is not specified in table
+unsafe fn get_batch_size(
+    ftable: *mut pg_sys::ForeignTable,
+    table_opts: &HashMap<String, String>,
+) -> FdwResult<c_int> {
+    if table_opts.contains_key(BATCH_SIZE.name) {
+        return BATCH_SIZE.require(table_opts);
+    }
+    let fserver = pg_sys::GetForeignServer((*ftable).serverid);
+    let server_opts = utils::options_to_hashmap((*fserver).options);
+    BATCH_SIZE.require(&server_opts)
+}
+
 #[pg_guard]
 pub(super) extern "C" fn plan_foreign_modify<W: ForeignDataWrapper>(
     root: *mut pg_sys::PlannerInfo,
-    plan: *mut pg_sys::ModifyTable,
+    _plan: *mut pg_sys::ModifyTable,
     result_relation: pg_sys::Index,
     _subplan_index: c_int,
 ) -> *mut pg_sys::List {
     debug2!("---> plan_foreign_modify");
     unsafe {
-        if !(*plan).returningLists.is_null() {
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                "RETURNING is not supported",
-            )
-        }
-
         let rte = pg_sys::planner_rt_fetch(result_relation, root);
 
         // core code already has some lock on each rel being planned, so we can
@@ -161,6 +211,7 @@ pub(super) extern "C" fn plan_foreign_modify<W: ForeignDataWrapper>(
 
                 state.rowid_name = rowid_name.to_string();
                 state.rowid_typid = attr.atttypid;
+                state.batch_size = get_batch_size(ftable, &opts).report_unwrap();
                 state.opts = opts;
 
                 let boxed_state =
@@ -180,7 +231,7 @@ pub(super) extern "C" fn plan_foreign_modify<W: ForeignDataWrapper>(
 }
 
 #[pg_guard]
-pub(super) extern "C" fn begin_foreign_modify<W: ForeignDataWrapper>(
+pub(super) extern "C" fn begin_foreign_modify<W: ForeignDataWrapper + 'static>(
     mtstate: *mut pg_sys::ModifyTableState,
     rinfo: *mut pg_sys::ResultRelInfo,
     fdw_private: *mut pg_sys::List,
@@ -204,7 +255,12 @@ pub(super) extern "C" fn begin_foreign_modify<W: ForeignDataWrapper>(
         state.rowid_attno =
             pg_sys::ExecFindJunkAttributeInTlist((*subplan).targetlist, rowid_name_c);
 
-        state.begin_modify();
+        // measure the time spent in the FDW for EXPLAIN ANALYZE
+        let instrument = (*mtstate).ps.instrument;
+        state.timing = !instrument.is_null() && (*instrument).need_timer;
+
+        state.begin_modify().report_unwrap();
+        transaction::register_instance(&state.instance);
 
         (*rinfo).ri_FdwState = state.into_pg() as _;
 
@@ -228,7 +284,12 @@ pub(super) extern "C" fn exec_foreign_insert<W: ForeignDataWrapper>(
         let mut old_ctx = state.tmp_ctx.set_as_current();
 
         let row = utils::tuple_table_slot_to_row(slot);
-        state.insert(&row);
+        let returning = state.insert(&row).report_unwrap();
+        if has_returning(rinfo) {
+            if let Some(returning) = returning {
+                store_returning_row(slot, returning).report_unwrap();
+            }
+        }
 
         old_ctx.set_as_current();
     }
@@ -236,6 +297,109 @@ pub(super) extern "C" fn exec_foreign_insert<W: ForeignDataWrapper>(
     slot
 }
 
+// check if the modify statement has a RETURNING clause
+#[inline]
+unsafe fn has_returning(rinfo: *mut pg_sys::ResultRelInfo) -> bool {
+    !(*rinfo).ri_projectReturning.is_null()
+}
+
+// store the row returned by FDW into the result slot, so it can be projected
+// by RETURNING clause. Columns not in the returned row keep their values in
+// the slot, or are set to null if the slot is empty. The returned cells are
+// coerced to the column types.
+unsafe fn store_returning_row(slot: *mut pg_sys::TupleTableSlot, mut row: Row) -> FdwResult<()> {
+    let tup_desc = PgTupleDesc::from_pg_copy((*slot).tts_tupleDescriptor);
+    let is_empty = (*slot).tts_flags as u32 & pg_sys::TTS_FLAG_EMPTY != 0;
+    let natts = tup_desc.len();
+    let mut values: Vec<Datum> = vec![0.into(); natts];
+    let mut nulls: Vec<bool> = vec![true; natts];
+
+    for (att_idx, attr) in tup_desc.iter().enumerate() {
+        if attr.attisdropped {
+            continue;
+        }
+        let attname = pgx::name_data_to_str(&attr.attname);
+        match row.cols.iter().position(|col| col == attname) {
+            Some(i) => {
+                if let Some(cell) = row.cells[i].take() {
+                    let col = Column {
+                        name: attname.to_owned(),
+                        num: attr.attnum as usize,
+                        type_oid: attr.atttypid,
+                        typmod: attr.atttypmod,
+                    };
+                    values[att_idx] = cell_into_datum(cell, &col)?;
+                    nulls[att_idx] = false;
+                }
+            }
+            None if !is_empty => {
+                let mut is_null = true;
+                values[att_idx] =
+                    polyfill::slot_getattr(slot, (att_idx + 1) as c_int, &mut is_null);
+                nulls[att_idx] = is_null;
+            }
+            None => {}
+        }
+    }
+
+    polyfill::exec_store_values(slot, &mut values, &mut nulls);
+    Ok(())
+}
+
+#[pg_guard]
+pub(super) extern "C" fn get_foreign_modify_batch_size<W: ForeignDataWrapper>(
+    rinfo: *mut pg_sys::ResultRelInfo,
+) -> c_int {
+    debug2!("---> get_foreign_modify_batch_size");
+    unsafe {
+        // fdw state is not created for EXPLAIN only
+        let fdw_state = (*rinfo).ri_FdwState as *mut FdwModifyState<W>;
+        if fdw_state.is_null() {
+            return 1;
+        }
+
+        // disable batching if there is RETURNING clause or any row-level
+        // insert triggers, as they need rows to be inserted one by one
+        let trig_desc = (*rinfo).ri_TrigDesc;
+        if has_returning(rinfo)
+            || (!trig_desc.is_null()
+                && ((*trig_desc).trig_insert_before_row || (*trig_desc).trig_insert_after_row))
+        {
+            return 1;
+        }
+
+        (*fdw_state).batch_size
+    }
+}
+
+#[pg_guard]
+pub(super) extern "C" fn exec_foreign_batch_insert<W: ForeignDataWrapper>(
+    _estate: *mut pg_sys::EState,
+    rinfo: *mut pg_sys::ResultRelInfo,
+    slots: *mut *mut pg_sys::TupleTableSlot,
+    _plan_slots: *mut *mut pg_sys::TupleTableSlot,
+    num_slots: *mut c_int,
+) -> *mut *mut pg_sys::TupleTableSlot {
+    debug2!("---> exec_foreign_batch_insert");
+    unsafe {
+        let mut state =
+            PgBox::<FdwModifyState<W>>::from_pg((*rinfo).ri_FdwState as *mut FdwModifyState<W>);
+
+        state.tmp_ctx.reset();
+        let mut old_ctx = state.tmp_ctx.set_as_current();
+
+        let rows = std::slice::from_raw_parts(slots, *num_slots as usize)
+            .iter()
+            .map(|slot| utils::tuple_table_slot_to_row(*slot))
+            .collect::<Vec<Row>>();
+        state.insert_batch(&rows).report_unwrap();
+
+        old_ctx.set_as_current();
+    }
+
+    slots
+}
+
 unsafe fn get_rowid_cell<W: ForeignDataWrapper>(
     state: &FdwModifyState<W>,
     plan_slot: *mut pg_sys::TupleTableSlot,
@@ -262,7 +426,12 @@ pub(super) extern "C" fn exec_foreign_delete<W: ForeignDataWrapper>(
 
         let cell = get_rowid_cell(&state, plan_slot);
         if let Some(rowid) = cell {
-            state.delete(&rowid);
+            let returning = state.delete(&rowid).report_unwrap();
+            if has_returning(rinfo) {
+                if let Some(returning) = returning {
+                    store_returning_row(slot, returning).report_unwrap();
+                }
+            }
         }
 
         old_ctx.set_as_current();
@@ -300,7 +469,12 @@ pub(super) extern "C" fn exec_foreign_update<W: ForeignDataWrapper>(
                 }) && state.rowid_name != col.as_str()
             });
 
-            state.update(&rowid, &new_row);
+            let returning = state.update(&rowid, &new_row).report_unwrap();
+            if has_returning(rinfo) {
+                if let Some(returning) = returning {
+                    store_returning_row(slot, returning).report_unwrap();
+                }
+            }
         }
 
         old_ctx.set_as_current();
@@ -319,8 +493,48 @@ pub(super) extern "C" fn end_foreign_modify<W: ForeignDataWrapper>(
         let fdw_state = (*rinfo).ri_FdwState as *mut FdwModifyState<W>;
         if !fdw_state.is_null() {
             let mut state = PgBox::<FdwModifyState<W>>::from_rust(fdw_state);
-            state.end_modify();
+            state.end_modify().report_unwrap();
             state.clear();
         }
     }
 }
+
+#[pg_guard]
+pub(super) extern "C" fn explain_foreign_modify<W: ForeignDataWrapper>(
+    _mtstate: *mut pg_sys::ModifyTableState,
+    rinfo: *mut pg_sys::ResultRelInfo,
+    fdw_private: *mut pg_sys::List,
+    _subplan_index: c_int,
+    es: *mut pg_sys::ExplainState,
+) {
+    debug2!("---> explain_foreign_modify");
+    unsafe {
+        // fdw state is not created for EXPLAIN only, use the planned one
+        let fdw_state = (*rinfo).ri_FdwState as *mut FdwModifyState<W>;
+        let mut state = if fdw_state.is_null() {
+            FdwModifyState::<W>::deserialize_from_list(fdw_private as _)
+        } else {
+            PgBox::<FdwModifyState<W>>::from_pg(fdw_state)
+        };
+
+        let mut old_ctx = state.tmp_ctx.set_as_current();
+
+        if (*es).analyze && state.timing {
+            let label = PgMemoryContexts::CurrentMemoryContext.pstrdup("Wrappers");
+            let value = PgMemoryContexts::CurrentMemoryContext.pstrdup(&format!(
+                "elapsed = {:.3} ms",
+                state.elapsed.as_secs_f64() * 1000.0
+            ));
+            pg_sys::ExplainPropertyText(label, value, es);
+        }
+
+        // properties defined by the FDW
+        for (label, value) in state.explain((*es).verbose).report_unwrap() {
+            let label = PgMemoryContexts::CurrentMemoryContext.pstrdup(&label);
+            let value = PgMemoryContexts::CurrentMemoryContext.pstrdup(&value);
+            pg_sys::ExplainPropertyText(label, value, es);
+        }
+
+        old_ctx.set_as_current();
+    }
+}
@@ -0,0 +1,381 @@
+//! Declarative option schema of FDWs
+//!
+//! A FDW can declare the options it accepts once by implementing
+//! [`options()`](crate::interface::ForeignDataWrapper::options). The declared
+//! options are then used by the default
+//! [`validator()`](crate::interface::ForeignDataWrapper::validator) to reject
+//! unknown, malformed or missing options, and can be read with typed getters
+//! at runtime. For example,
+//!
+//! ```rust,no_run
+//! use OptionCatalog::*;
+//!
+//! const API_KEY: OptionDef = OptionDef::new("api_key", &[Server, UserMapping])
+//!     .secret()
+//!     .description("API key of the remote service");
+//! const TIMEOUT: OptionDef = OptionDef::new("timeout", &[Table])
+//!     .kind(OptionType::Int)
+//!     .default("30000")
+//!     .description("request timeout in milliseconds");
+//!
+//! impl ForeignDataWrapper for HelloWorldFdw {
+//!     fn options() -> &'static [OptionDef] {
+//!         &[API_KEY, TIMEOUT]
+//!     }
+//!
+//!     fn begin_scan(
+//!         &mut self,
+//!         _quals: &[Qual],
+//!         _columns: &[Column],
+//!         _sorts: &[Sort],
+//!         _limit: &Option<Limit>,
+//!         options: &HashMap<String, String>,
+//!     ) -> FdwResult<()> {
+//!         let timeout: i64 = TIMEOUT.require(options)?;
+//!         Ok(())
+//!     }
+//! }
+//! ```
+//!
+//! The options accepted by the framework itself, such as `rowid_column` and
+//! `batch_size`, don't need to be declared. All the options of a FDW can be
+//! listed by the SQL function created by [`wrappers_fdw`](crate::wrappers_fdw)
+//! macro,
+//!
+//! ```sql
+//! select * from hello_world_fdw_options();
+//! ```
+use pgx::prelude::*;
+use pgx::PgSqlErrorCode;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::interface::{
+    FdwError, FdwResult, FOREIGN_DATA_WRAPPER_RELATION_ID, FOREIGN_SERVER_RELATION_ID,
+    FOREIGN_TABLE_RELATION_ID, USER_MAPPING_RELATION_ID,
+};
+
+/// Catalog in which an option can be defined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionCatalog {
+    /// `CREATE FOREIGN DATA WRAPPER`
+    Wrapper,
+    /// `CREATE SERVER`
+    Server,
+    /// `CREATE USER MAPPING`
+    UserMapping,
+    /// `CREATE FOREIGN TABLE`
+    Table,
+}
+
+impl OptionCatalog {
+    fn from_oid(oid: pg_sys::Oid) -> Option<Self> {
+        match oid {
+            FOREIGN_DATA_WRAPPER_RELATION_ID => Some(Self::Wrapper),
+            FOREIGN_SERVER_RELATION_ID => Some(Self::Server),
+            USER_MAPPING_RELATION_ID => Some(Self::UserMapping),
+            FOREIGN_TABLE_RELATION_ID => Some(Self::Table),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OptionCatalog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Wrapper => write!(f, "wrapper"),
+            Self::Server => write!(f, "server"),
+            Self::UserMapping => write!(f, "user mapping"),
+            Self::Table => write!(f, "table"),
+        }
+    }
+}
+
+/// Type of an option value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionType {
+    /// any string
+    String,
+    /// `true`/`false`, `on`/`off`, `yes`/`no` or `1`/`0`
+    Bool,
+    /// 64-bit integer
+    Int,
+    /// 64-bit integer greater than 0
+    PositiveInt,
+    /// floating point number
+    Float,
+}
+
+impl OptionType {
+    fn is_valid(&self, value: &str) -> bool {
+        match self {
+            Self::String => true,
+            Self::Bool => bool::parse_option(value).is_some(),
+            Self::Int => i64::parse_option(value).is_some(),
+            Self::PositiveInt => i64::parse_option(value).is_some_and(|v| v > 0),
+            Self::Float => f64::parse_option(value).is_some(),
+        }
+    }
+}
+
+impl fmt::Display for OptionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String => write!(f, "string"),
+            Self::Bool => write!(f, "bool"),
+            Self::Int => write!(f, "int"),
+            Self::PositiveInt => write!(f, "positive int"),
+            Self::Float => write!(f, "float"),
+        }
+    }
+}
+
+/// Value type which can be parsed from an option by [`OptionDef::get`]
+pub trait OptionValue: Sized {
+    /// Parse the option value, return `None` if it is malformed
+    fn parse_option(value: &str) -> Option<Self>;
+}
+
+impl OptionValue for String {
+    fn parse_option(value: &str) -> Option<Self> {
+        Some(value.to_owned())
+    }
+}
+
+impl OptionValue for bool {
+    fn parse_option(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "true" | "on" | "yes" | "1" => Some(true),
+            "false" | "off" | "no" | "0" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_option_value {
+    ($($t:ty),*) => {
+        $(
+            impl OptionValue for $t {
+                fn parse_option(value: &str) -> Option<Self> {
+                    value.trim().parse::<$t>().ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_option_value!(i32, i64, usize, f64);
+
+/// Definition of an option accepted by the FDW
+///
+/// It is created by [`OptionDef::new`] and the builder methods, all of which
+/// are `const` so it can be defined as a constant.
+#[derive(Debug, Clone, Copy)]
+pub struct OptionDef {
+    /// option name
+    pub name: &'static str,
+
+    /// catalogs in which the option can be defined
+    pub catalogs: &'static [OptionCatalog],
+
+    /// type of the option value
+    pub kind: OptionType,
+
+    /// true if the option must be specified, only checked when it can be
+    /// defined in one catalog
+    pub required: bool,
+
+    /// default value used when the option is not specified
+    pub default: Option<&'static str>,
+
+    /// true if the option value is a credential, it is never shown in errors
+    pub secret: bool,
+
+    /// description of the option
+    pub description: &'static str,
+}
+
+impl OptionDef {
+    /// Create an optional string option which can be defined in `catalogs`
+    pub const fn new(name: &'static str, catalogs: &'static [OptionCatalog]) -> Self {
+        Self {
+            name,
+            catalogs,
+            kind: OptionType::String,
+            required: false,
+            default: None,
+            secret: false,
+            description: "",
+        }
+    }
+
+    /// Set the type of the option value
+    pub const fn kind(mut self, kind: OptionType) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Make the option required
+    pub const fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Set the default value
+    pub const fn default(mut self, default: &'static str) -> Self {
+        self.default = Some(default);
+        self
+    }
+
+    /// Mark the option value as a credential
+    pub const fn secret(mut self) -> Self {
+        self.secret = true;
+        self
+    }
+
+    /// Set the description
+    pub const fn description(mut self, description: &'static str) -> Self {
+        self.description = description;
+        self
+    }
+
+    /// Get the option value parsed as `T`, or its default value if it is not
+    /// specified
+    ///
+    /// Return an [`FdwError`] if the value is malformed, or not valid for the
+    /// option type.
+    pub fn get<T: OptionValue>(&self, options: &HashMap<String, String>) -> FdwResult<Option<T>> {
+        options
+            .get(self.name)
+            .map(|value| value.as_str())
+            .or(self.default)
+            .map(|value| {
+                Some(value)
+                    .filter(|value| self.kind.is_valid(value))
+                    .and_then(T::parse_option)
+                    .ok_or_else(|| self.invalid_value(value))
+            })
+            .transpose()
+    }
+
+    /// Get the option value parsed as `T`, or its default value if it is not
+    /// specified
+    ///
+    /// Return an [`FdwError`] if the value is malformed, or the option is not
+    /// specified and has no default value.
+    pub fn require<T: OptionValue>(&self, options: &HashMap<String, String>) -> FdwResult<T> {
+        self.get(options)?.ok_or_else(|| {
+            FdwError::new(
+                PgSqlErrorCode::ERRCODE_FDW_OPTION_NAME_NOT_FOUND,
+                format!("required option \"{}\" is not specified", self.name),
+            )
+        })
+    }
+
+    fn invalid_value(&self, value: &str) -> FdwError {
+        let err = FdwError::new(
+            PgSqlErrorCode::ERRCODE_FDW_INVALID_ATTRIBUTE_VALUE,
+            format!("invalid value for option \"{}\"", self.name),
+        );
+        if self.secret {
+            err
+        } else {
+            err.detail(format!("\"{}\" is not a valid {} value", value, self.kind))
+        }
+    }
+}
+
+use OptionCatalog::*;
+
+pub(crate) const BATCH_SIZE: OptionDef = OptionDef::new("batch_size", &[Server, Table])
+    .kind(OptionType::PositiveInt)
+    .default("1")
+    .description("number of rows inserted in one batch");
+
+// options used by the framework, which are accepted by all FDWs
+const FRAMEWORK_OPTIONS: &[OptionDef] = &[
+    OptionDef::new("rowid_column", &[Table])
+        .description("unique identification column of the table, required by data modification"),
+    BATCH_SIZE,
+    OptionDef::new("fdw_startup_cost", &[Server, Table])
+        .kind(OptionType::Float)
+        .description("cost before the first request is sent"),
+    OptionDef::new("startup_cost", &[Server, Table])
+        .kind(OptionType::Float)
+        .description("same as fdw_startup_cost"),
+    OptionDef::new("fdw_tuple_cost", &[Server, Table])
+        .kind(OptionType::Float)
+        .description("cost of each returned row"),
+    OptionDef::new("use_remote_estimate", &[Server, Table])
+        .kind(OptionType::Bool)
+        .default("false")
+        .description("estimate row count by the FDW rather than local statistics"),
+];
+
+/// Get all the options accepted by a FDW, including the framework ones
+///
+/// Not to be used directly, use the SQL function created by
+/// [`wrappers_fdw`](crate::wrappers_fdw) macro instead.
+pub fn all_options(defs: &'static [OptionDef]) -> impl Iterator<Item = &'static OptionDef> {
+    defs.iter().chain(FRAMEWORK_OPTIONS.iter())
+}
+
+/// Validate options against the declared option schema
+///
+/// It is used by the default [`validator()`](crate::interface::ForeignDataWrapper::validator),
+/// nothing is checked if no options are declared.
+pub fn validate_options(
+    defs: &'static [OptionDef],
+    options: &[Option<String>],
+    catalog: Option<pg_sys::Oid>,
+) -> FdwResult<()> {
+    let catalog = match catalog.and_then(OptionCatalog::from_oid) {
+        Some(catalog) if !defs.is_empty() => catalog,
+        _ => return Ok(()),
+    };
+    let valid_defs = all_options(defs)
+        .filter(|def| def.catalogs.contains(&catalog))
+        .collect::<Vec<_>>();
+
+    // option list is in 'name=value' format
+    let options = options
+        .iter()
+        .flatten()
+        .map(|opt| opt.split_once('=').unwrap_or((opt, "")))
+        .collect::<Vec<_>>();
+
+    for (name, value) in &options {
+        let def = valid_defs
+            .iter()
+            .find(|def| def.name == *name)
+            .ok_or_else(|| {
+                let valid_names = valid_defs
+                    .iter()
+                    .map(|def| def.name)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                FdwError::new(
+                    PgSqlErrorCode::ERRCODE_FDW_INVALID_OPTION_NAME,
+                    format!("invalid option \"{}\"", name),
+                )
+                .hint(format!(
+                    "Valid options in this context are: {}",
+                    valid_names
+                ))
+            })?;
+        if !def.kind.is_valid(value) {
+            return Err(def.invalid_value(value));
+        }
+    }
+
+    for def in valid_defs {
+        if def.required && def.catalogs.len() == 1 && !options.iter().any(|o| o.0 == def.name) {
+            return Err(FdwError::new(
+                PgSqlErrorCode::ERRCODE_FDW_OPTION_NAME_NOT_FOUND,
+                format!("required option \"{}\" is not specified", def.name),
+            ));
+        }
+    }
+
+    Ok(())
+}
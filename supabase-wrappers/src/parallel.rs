@@ -0,0 +1,271 @@
+use pgx::{pg_sys, FromDatum, IntoDatum};
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::interface::{Cell, Column, Limit, Param, Qual, QualExpr, Sort, Value};
+
+// shared state of a parallel scan in dynamic shared memory, which is used
+// by the leader and workers to claim scan partitions
+#[repr(C)]
+#[derive(Default)]
+pub(crate) struct ParallelScanShared {
+    next_partition: AtomicUsize,
+}
+
+impl ParallelScanShared {
+    // claim the next partition to scan
+    pub(crate) fn claim(&self) -> usize {
+        self.next_partition.fetch_add(1, Ordering::SeqCst)
+    }
+
+    pub(crate) fn reset(&self) {
+        self.next_partition.store(0, Ordering::SeqCst);
+    }
+}
+
+// encoder of the scan state copied to parallel workers, the state pointer
+// in plan is only valid in the leader process
+#[derive(Default)]
+pub(crate) struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub(crate) fn usize(&mut self, v: usize) {
+        self.buf.extend_from_slice(&(v as u64).to_le_bytes());
+    }
+
+    pub(crate) fn i64(&mut self, v: i64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn bool(&mut self, v: bool) {
+        self.buf.push(v as u8);
+    }
+
+    pub(crate) fn str(&mut self, v: &str) {
+        self.usize(v.len());
+        self.buf.extend_from_slice(v.as_bytes());
+    }
+
+    // cell is encoded as its type and text representation, array cell is
+    // encoded as its elements as the element type may be unknown
+    pub(crate) unsafe fn cell(&mut self, cell: &Cell) {
+        if let Cell::Array(elems) = cell {
+            self.usize(pg_sys::InvalidOid as usize);
+            self.usize(elems.len());
+            for elem in elems {
+                self.bool(elem.is_some());
+                if let Some(elem) = elem {
+                    self.cell(elem);
+                }
+            }
+            return;
+        }
+
+        let type_oid = cell.type_oid();
+        let datum = cell.clone().into_datum().unwrap();
+        let mut output_func = pg_sys::InvalidOid;
+        let mut is_varlena = false;
+        pg_sys::getTypeOutputInfo(type_oid, &mut output_func, &mut is_varlena);
+        let text = pg_sys::OidOutputFunctionCall(output_func, datum);
+        self.usize(type_oid as usize);
+        self.str(&CStr::from_ptr(text).to_string_lossy());
+    }
+
+    pub(crate) unsafe fn qual(&mut self, qual: &Qual) {
+        self.str(&qual.field);
+        self.str(&qual.operator);
+        match &qual.value {
+            Value::Cell(cell) => {
+                self.bool(false);
+                self.cell(cell);
+            }
+            Value::Array(cells) => {
+                self.bool(true);
+                self.usize(cells.len());
+                for cell in cells {
+                    self.cell(cell);
+                }
+            }
+        }
+        self.bool(qual.use_or);
+        self.bool(qual.param.is_some());
+        if let Some(param) = &qual.param {
+            self.usize(param.id);
+            self.usize(param.type_oid as usize);
+        }
+    }
+
+    pub(crate) unsafe fn qual_expr(&mut self, expr: &QualExpr) {
+        match expr {
+            QualExpr::Qual(qual) => {
+                self.usize(0);
+                self.qual(qual);
+            }
+            QualExpr::And(exprs) => {
+                self.usize(1);
+                self.qual_exprs(exprs);
+            }
+            QualExpr::Or(exprs) => {
+                self.usize(2);
+                self.qual_exprs(exprs);
+            }
+            QualExpr::Not(expr) => {
+                self.usize(3);
+                self.qual_expr(expr);
+            }
+        }
+    }
+
+    unsafe fn qual_exprs(&mut self, exprs: &[QualExpr]) {
+        self.usize(exprs.len());
+        for expr in exprs {
+            self.qual_expr(expr);
+        }
+    }
+
+    pub(crate) fn column(&mut self, col: &Column) {
+        self.str(&col.name);
+        self.usize(col.num);
+        self.usize(col.type_oid as usize);
+        self.i64(col.typmod as i64);
+    }
+
+    pub(crate) fn sort(&mut self, sort: &Sort) {
+        self.str(&sort.field);
+        self.usize(sort.field_no);
+        self.bool(sort.reversed);
+        self.bool(sort.nulls_first);
+        self.bool(sort.collate.is_some());
+        if let Some(collate) = &sort.collate {
+            self.str(collate);
+        }
+    }
+
+    pub(crate) fn limit(&mut self, limit: &Option<Limit>) {
+        self.bool(limit.is_some());
+        if let Some(limit) = limit {
+            self.i64(limit.count);
+            self.i64(limit.offset);
+        }
+    }
+}
+
+// decoder of the scan state encoded by `Encoder`, the data is always
+// produced by the same build so it panics on malformed data
+pub(crate) struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Decoder { buf }
+    }
+
+    fn bytes(&mut self, len: usize) -> &'a [u8] {
+        let (ret, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        ret
+    }
+
+    pub(crate) fn usize(&mut self) -> usize {
+        u64::from_le_bytes(self.bytes(8).try_into().unwrap()) as usize
+    }
+
+    pub(crate) fn i64(&mut self) -> i64 {
+        i64::from_le_bytes(self.bytes(8).try_into().unwrap())
+    }
+
+    pub(crate) fn bool(&mut self) -> bool {
+        self.bytes(1)[0] != 0
+    }
+
+    pub(crate) fn str(&mut self) -> String {
+        let len = self.usize();
+        String::from_utf8(self.bytes(len).to_vec()).unwrap()
+    }
+
+    pub(crate) unsafe fn cell(&mut self) -> Cell {
+        let type_oid = self.usize() as pg_sys::Oid;
+        if type_oid == pg_sys::InvalidOid {
+            let len = self.usize();
+            let elems = (0..len).map(|_| self.bool().then(|| self.cell())).collect();
+            return Cell::Array(elems);
+        }
+        let text = CString::new(self.str()).unwrap();
+        let mut input_func = pg_sys::InvalidOid;
+        let mut io_param = pg_sys::InvalidOid;
+        pg_sys::getTypeInputInfo(type_oid, &mut input_func, &mut io_param);
+        let datum = pg_sys::OidInputFunctionCall(input_func, text.as_ptr() as _, io_param, -1);
+        Cell::from_polymorphic_datum(datum, false, type_oid).unwrap()
+    }
+
+    pub(crate) unsafe fn qual(&mut self) -> Qual {
+        let field = self.str();
+        let operator = self.str();
+        let value = if self.bool() {
+            let len = self.usize();
+            Value::Array((0..len).map(|_| self.cell()).collect())
+        } else {
+            Value::Cell(self.cell())
+        };
+        let use_or = self.bool();
+        let param = self.bool().then(|| Param {
+            id: self.usize(),
+            type_oid: self.usize() as pg_sys::Oid,
+        });
+        Qual {
+            field,
+            operator,
+            value,
+            use_or,
+            param,
+        }
+    }
+
+    pub(crate) unsafe fn qual_expr(&mut self) -> QualExpr {
+        match self.usize() {
+            0 => QualExpr::Qual(self.qual()),
+            1 => {
+                let len = self.usize();
+                QualExpr::And((0..len).map(|_| self.qual_expr()).collect())
+            }
+            2 => {
+                let len = self.usize();
+                QualExpr::Or((0..len).map(|_| self.qual_expr()).collect())
+            }
+            _ => QualExpr::Not(Box::new(self.qual_expr())),
+        }
+    }
+
+    pub(crate) fn column(&mut self) -> Column {
+        Column {
+            name: self.str(),
+            num: self.usize(),
+            type_oid: self.usize() as pg_sys::Oid,
+            typmod: self.i64() as i32,
+        }
+    }
+
+    pub(crate) fn sort(&mut self) -> Sort {
+        Sort {
+            field: self.str(),
+            field_no: self.usize(),
+            reversed: self.bool(),
+            nulls_first: self.bool(),
+            collate: self.bool().then(|| self.str()),
+        }
+    }
+
+    pub(crate) fn limit(&mut self) -> Option<Limit> {
+        self.bool().then(|| Limit {
+            count: self.i64(),
+            offset: self.i64(),
+        })
+    }
+}
@@ -1,6 +1,8 @@
+use pgx::memcxt::PgMemoryContexts;
 use pgx::pg_sys::Datum;
 use pgx::prelude::*;
 use std::os::raw::c_int;
+use std::ptr;
 use std::slice;
 
 // ExecClearTuple
@@ -10,6 +12,19 @@ pub(super) unsafe fn exec_clear_tuple(slot: *mut pg_sys::TupleTableSlot) {
     }
 }
 
+// ExecForceStoreHeapTuple with a tuple formed from values and nulls, the tuple
+// is allocated in the slot's memory context and owned by the slot
+pub(super) unsafe fn exec_store_values(
+    slot: *mut pg_sys::TupleTableSlot,
+    values: &mut [Datum],
+    nulls: &mut [bool],
+) {
+    let (values, nulls) = (values.as_mut_ptr(), nulls.as_mut_ptr());
+    let tuple = PgMemoryContexts::For((*slot).tts_mcxt)
+        .switch_to(|_| pg_sys::heap_form_tuple((*slot).tts_tupleDescriptor, values, nulls));
+    pg_sys::ExecForceStoreHeapTuple(tuple, slot, true);
+}
+
 // fetch one attribute of the slot's contents.
 pub(super) unsafe fn slot_getattr(
     slot: *mut pg_sys::TupleTableSlot,
@@ -34,3 +49,51 @@ pub(super) unsafe fn slot_getattr(
 pub(super) unsafe fn outer_plan_state(node: *mut pg_sys::PlanState) -> *mut pg_sys::PlanState {
     (*node).lefttree
 }
+
+// ExecAsyncRequestDone
+pub(super) unsafe fn exec_async_request_done(
+    areq: *mut pg_sys::AsyncRequest,
+    result: *mut pg_sys::TupleTableSlot,
+) {
+    (*areq).request_complete = true;
+    (*areq).result = result;
+}
+
+// ExecAsyncRequestPending
+pub(super) unsafe fn exec_async_request_pending(areq: *mut pg_sys::AsyncRequest) {
+    (*areq).callback_pending = true;
+    (*areq).request_complete = false;
+    (*areq).result = ptr::null_mut();
+}
+
+// CHECK_FOR_INTERRUPTS
+#[inline]
+pub(super) unsafe fn check_for_interrupts() {
+    if ptr::read_volatile(ptr::addr_of!(pg_sys::InterruptPending)) != 0 {
+        pg_sys::ProcessInterrupts();
+    }
+}
+
+// ExecProcNode
+#[inline]
+pub(super) unsafe fn exec_proc_node(node: *mut pg_sys::PlanState) -> *mut pg_sys::TupleTableSlot {
+    (*node).ExecProcNode.unwrap()(node)
+}
+
+// CacheRegisterSyscacheCallback, which is not in pgx bindings
+pub(super) unsafe fn cache_register_syscache_callback(
+    cache_id: c_int,
+    func: Option<unsafe extern "C" fn(arg: Datum, cache_id: c_int, hash_value: u32)>,
+    arg: Datum,
+) {
+    // it can only fail with FATAL error when there are too many callbacks,
+    // so no need to guard it
+    extern "C" {
+        fn CacheRegisterSyscacheCallback(
+            cacheid: c_int,
+            func: Option<unsafe extern "C" fn(arg: Datum, cacheid: c_int, hashvalue: u32)>,
+            arg: Datum,
+        );
+    }
+    CacheRegisterSyscacheCallback(cache_id, func, arg)
+}
@@ -1,5 +1,6 @@
 use crate::prelude::*;
-use pgx::{is_a, pg_sys, pg_sys::Datum, FromDatum, PgBuiltInOids, PgList, PgOid};
+use pgx::prelude::{AnyNumeric, Date, Time, Timestamp, TimestampWithTimeZone};
+use pgx::{is_a, pg_sys, pg_sys::Datum, FromDatum, Inet, PgBuiltInOids, PgList, PgOid, Uuid};
 use std::ffi::CStr;
 use std::os::raw::c_int;
 
@@ -48,6 +49,24 @@ pub(crate) unsafe fn form_array_from_datum(
         PgOid::BuiltIn(PgBuiltInOids::JSONBARRAYOID) => {
             Vec::<Cell>::from_polymorphic_datum(datum, false, pg_sys::JSONBOID)
         }
+        PgOid::BuiltIn(PgBuiltInOids::UUIDARRAYOID) => {
+            Vec::<Cell>::from_polymorphic_datum(datum, false, pg_sys::UUIDOID)
+        }
+        PgOid::BuiltIn(PgBuiltInOids::BYTEAARRAYOID) => {
+            Vec::<Cell>::from_polymorphic_datum(datum, false, pg_sys::BYTEAOID)
+        }
+        PgOid::BuiltIn(PgBuiltInOids::TIMEARRAYOID) => {
+            Vec::<Cell>::from_polymorphic_datum(datum, false, pg_sys::TIMEOID)
+        }
+        PgOid::BuiltIn(PgBuiltInOids::TIMESTAMPTZARRAYOID) => {
+            Vec::<Cell>::from_polymorphic_datum(datum, false, pg_sys::TIMESTAMPTZOID)
+        }
+        PgOid::BuiltIn(PgBuiltInOids::INTERVALARRAYOID) => {
+            Vec::<Cell>::from_polymorphic_datum(datum, false, pg_sys::INTERVALOID)
+        }
+        PgOid::BuiltIn(PgBuiltInOids::INETARRAYOID) => {
+            Vec::<Cell>::from_polymorphic_datum(datum, false, pg_sys::INETOID)
+        }
         _ => None,
     }
 }
@@ -55,7 +74,7 @@ pub(crate) unsafe fn form_array_from_datum(
 pub(crate) unsafe fn get_operator(opno: pg_sys::Oid) -> pg_sys::Form_pg_operator {
     let htup = pg_sys::SearchSysCache1(
         pg_sys::SysCacheIdentifier_OPEROID.try_into().unwrap(),
-        opno.try_into().unwrap(),
+        opno.into(),
     );
     if htup.is_null() {
         pg_sys::ReleaseSysCache(htup);
@@ -76,11 +95,69 @@ pub(crate) unsafe fn unnest_clause(node: *mut pg_sys::Node) -> *mut pg_sys::Node
     }
 }
 
+// check if a node is a column of the base relation
+unsafe fn is_baserel_column(node: *mut pg_sys::Node, baserel_ids: pg_sys::Relids) -> bool {
+    if !is_a(node, pg_sys::NodeTag_T_Var) {
+        return false;
+    }
+    let var = node as *mut pg_sys::Var;
+    (*var).varlevelsup == 0
+        && (*var).varattno >= 1
+        && pg_sys::bms_is_member((*var).varno as c_int, baserel_ids)
+}
+
+// check if a node is a parameter whose value is only known at execution time,
+// which is a Param or a column of other relations in a parameterized scan
+unsafe fn is_param(node: *mut pg_sys::Node, baserel_ids: pg_sys::Relids) -> bool {
+    if is_a(node, pg_sys::NodeTag_T_Param) {
+        let param = node as *mut pg_sys::Param;
+        return (*param).paramkind == pg_sys::ParamKind_PARAM_EXTERN
+            || (*param).paramkind == pg_sys::ParamKind_PARAM_EXEC;
+    }
+    if is_a(node, pg_sys::NodeTag_T_Var) {
+        let var = node as *mut pg_sys::Var;
+        return (*var).varlevelsup == 0
+            && !pg_sys::bms_is_member((*var).varno as c_int, baserel_ids);
+    }
+    false
+}
+
+// get a placeholder value of a parameter type, which is used until the
+// parameter value is known. Return None if the type cannot be a Cell.
+fn param_placeholder(type_oid: pg_sys::Oid) -> Option<Cell> {
+    let cell = match PgOid::from(type_oid) {
+        PgOid::BuiltIn(PgBuiltInOids::BOOLOID) => Cell::Bool(false),
+        PgOid::BuiltIn(PgBuiltInOids::CHAROID) => Cell::I8(0),
+        PgOid::BuiltIn(PgBuiltInOids::INT2OID) => Cell::I16(0),
+        PgOid::BuiltIn(PgBuiltInOids::FLOAT4OID) => Cell::F32(0.0),
+        PgOid::BuiltIn(PgBuiltInOids::INT4OID) => Cell::I32(0),
+        PgOid::BuiltIn(PgBuiltInOids::FLOAT8OID) => Cell::F64(0.0),
+        PgOid::BuiltIn(PgBuiltInOids::INT8OID) => Cell::I64(0),
+        PgOid::BuiltIn(PgBuiltInOids::NUMERICOID) => Cell::Numeric(AnyNumeric::from(0)),
+        PgOid::BuiltIn(PgBuiltInOids::TEXTOID) => Cell::String(String::new()),
+        PgOid::BuiltIn(PgBuiltInOids::DATEOID) => Cell::Date(Date::from_pg_epoch_days(0)),
+        PgOid::BuiltIn(PgBuiltInOids::TIMESTAMPOID) => {
+            Cell::Timestamp(Timestamp::try_from(0).ok()?)
+        }
+        PgOid::BuiltIn(PgBuiltInOids::UUIDOID) => Cell::Uuid(Uuid::from_bytes([0; 16])),
+        PgOid::BuiltIn(PgBuiltInOids::BYTEAOID) => Cell::Bytea(Vec::new()),
+        PgOid::BuiltIn(PgBuiltInOids::TIMEOID) => Cell::Time(Time(0)),
+        PgOid::BuiltIn(PgBuiltInOids::TIMESTAMPTZOID) => {
+            Cell::TimestampTz(TimestampWithTimeZone::try_from(0).ok()?)
+        }
+        PgOid::BuiltIn(PgBuiltInOids::INTERVALOID) => Cell::Interval(Interval::default()),
+        PgOid::BuiltIn(PgBuiltInOids::INETOID) => Cell::Inet(Inet("0.0.0.0".to_string())),
+        _ => return None,
+    };
+    Some(cell)
+}
+
 pub(crate) unsafe fn extract_from_op_expr(
     _root: *mut pg_sys::PlannerInfo,
     baserel_id: pg_sys::Oid,
     baserel_ids: pg_sys::Relids,
     expr: *mut pg_sys::OpExpr,
+    params: &mut Vec<*mut pg_sys::Node>,
 ) -> Option<Qual> {
     let args: PgList<pg_sys::Node> = PgList::from_pg((*expr).args);
 
@@ -90,8 +167,7 @@ pub(crate) unsafe fn extract_from_op_expr(
     }
 
     // get operator
-    let opno = (*expr).opno;
-    let opr = get_operator(opno);
+    let mut opr = get_operator((*expr).opno);
     if opr.is_null() {
         return None;
     }
@@ -99,35 +175,59 @@ pub(crate) unsafe fn extract_from_op_expr(
     let mut left = unnest_clause(args.head().unwrap());
     let mut right = unnest_clause(args.tail().unwrap());
 
-    // swap operands if needed
-    if is_a(right, pg_sys::NodeTag_T_Var)
-        && !is_a(left, pg_sys::NodeTag_T_Var)
-        && (*opr).oprcom != 0
-    {
+    // swap operands with the commutator if the column is on the right side
+    if !is_baserel_column(left, baserel_ids) && is_baserel_column(right, baserel_ids) {
+        if (*opr).oprcom == 0 {
+            return None;
+        }
+        opr = get_operator((*opr).oprcom);
+        if opr.is_null() {
+            return None;
+        }
         std::mem::swap(&mut left, &mut right);
     }
 
-    if is_a(left, pg_sys::NodeTag_T_Var) && is_a(right, pg_sys::NodeTag_T_Const) {
-        let left = left as *mut pg_sys::Var;
+    if !is_baserel_column(left, baserel_ids) {
+        return None;
+    }
+
+    let left = left as *mut pg_sys::Var;
+    let field = pg_sys::get_attname(baserel_id, (*left).varattno, false);
+    let field = CStr::from_ptr(field).to_str().unwrap().to_string();
+    let operator = pgx::name_data_to_str(&(*opr).oprname).to_string();
+
+    if is_a(right, pg_sys::NodeTag_T_Const) {
         let right = right as *mut pg_sys::Const;
+        let value = Cell::from_polymorphic_datum(
+            (*right).constvalue,
+            (*right).constisnull,
+            (*right).consttype,
+        )?;
+        return Some(Qual {
+            field,
+            operator,
+            value: Value::Cell(value),
+            use_or: false,
+            param: None,
+        });
+    }
 
-        if pg_sys::bms_is_member((*left).varno as c_int, baserel_ids) && (*left).varattno >= 1 {
-            let field = pg_sys::get_attname(baserel_id, (*left).varattno, false);
-            let value = Cell::from_polymorphic_datum(
-                (*right).constvalue,
-                (*right).constisnull,
-                (*right).consttype,
-            );
-            if let Some(value) = value {
-                let qual = Qual {
-                    field: CStr::from_ptr(field).to_str().unwrap().to_string(),
-                    operator: pgx::name_data_to_str(&(*opr).oprname).to_string(),
-                    value: Value::Cell(value),
-                    use_or: false,
-                };
-                return Some(qual);
-            }
-        }
+    // the scan returns no rows for a null parameter value, which is only
+    // correct for strict operators
+    if is_param(right, baserel_ids) && pg_sys::op_strict((*opr).oid) {
+        let type_oid = pg_sys::exprType(right);
+        let value = param_placeholder(type_oid)?;
+        params.push(right);
+        return Some(Qual {
+            field,
+            operator,
+            value: Value::Cell(value),
+            use_or: false,
+            param: Some(Param {
+                id: params.len(),
+                type_oid,
+            }),
+        });
     }
 
     None
@@ -155,6 +255,7 @@ pub(crate) unsafe fn extract_from_null_test(
         operator: opname,
         value: Value::Cell(Cell::String("null".to_string())),
         use_or: false,
+        param: None,
     };
 
     Some(qual)
@@ -201,6 +302,7 @@ pub(crate) unsafe fn extract_from_scalar_array_op_expr(
                     operator: pgx::name_data_to_str(&(*opr).oprname).to_string(),
                     value: Value::Array(value),
                     use_or: (*expr).useOr,
+                    param: None,
                 };
                 return Some(qual);
             }
@@ -230,6 +332,7 @@ pub(crate) unsafe fn extract_from_var(
         operator: "=".to_string(),
         value: Value::Cell(Cell::Bool(true)),
         use_or: false,
+        param: None,
     };
 
     Some(qual)
@@ -248,7 +351,8 @@ pub(crate) unsafe fn extract_from_bool_expr(
     }
 
     let var = args.head().unwrap() as *mut pg_sys::Var;
-    if (*var).varattno < 1
+    if !is_a(var as _, pg_sys::NodeTag_T_Var)
+        || (*var).varattno < 1
         || (*var).vartype != pg_sys::BOOLOID
         || !pg_sys::bms_is_member((*var).varno as c_int, baserel_ids)
     {
@@ -262,42 +366,187 @@ pub(crate) unsafe fn extract_from_bool_expr(
         operator: "=".to_string(),
         value: Value::Cell(Cell::Bool(false)),
         use_or: false,
+        param: None,
     };
 
     Some(qual)
 }
 
+// extract a qual from a condition on a base relation, return None if it
+// cannot be expressed as a single qual
+unsafe fn extract_qual(
+    root: *mut pg_sys::PlannerInfo,
+    baserel_id: pg_sys::Oid,
+    baserel_ids: pg_sys::Relids,
+    expr: *mut pg_sys::Node,
+    params: &mut Vec<*mut pg_sys::Node>,
+) -> Option<Qual> {
+    if is_a(expr, pg_sys::NodeTag_T_OpExpr) {
+        extract_from_op_expr(root, baserel_id, baserel_ids, expr as _, params)
+    } else if is_a(expr, pg_sys::NodeTag_T_NullTest) {
+        extract_from_null_test(baserel_id, expr as _)
+    } else if is_a(expr, pg_sys::NodeTag_T_ScalarArrayOpExpr) {
+        extract_from_scalar_array_op_expr(root, baserel_id, baserel_ids, expr as _)
+    } else if is_a(expr, pg_sys::NodeTag_T_Var) {
+        extract_from_var(root, baserel_id, baserel_ids, expr as _)
+    } else if is_a(expr, pg_sys::NodeTag_T_BoolExpr) {
+        extract_from_bool_expr(root, baserel_id, baserel_ids, expr as _)
+    } else {
+        if let Some(stm) = pgx::nodes::node_to_string(expr) {
+            report_warning(&format!("unsupported qual: {}", stm));
+        }
+        None
+    }
+}
+
+// extract quals from the restrictions on a base relation, each qual is paired
+// with the restriction it is extracted from. Parameters used by the quals are
+// appended to `params`.
 pub(crate) unsafe fn extract_quals(
     root: *mut pg_sys::PlannerInfo,
     baserel: *mut pg_sys::RelOptInfo,
     baserel_id: pg_sys::Oid,
-) -> Vec<Qual> {
+    conds: *mut pg_sys::List,
+    params: &mut Vec<*mut pg_sys::Node>,
+) -> Vec<(Qual, *mut pg_sys::RestrictInfo)> {
     let mut quals = Vec::new();
 
-    let conds = PgList::<pg_sys::RestrictInfo>::from_pg((*baserel).baserestrictinfo);
+    let conds = PgList::<pg_sys::RestrictInfo>::from_pg(conds);
     for cond in conds.iter_ptr() {
         let expr = (*cond).clause as *mut pg_sys::Node;
-        let extracted = if is_a(expr, pg_sys::NodeTag_T_OpExpr) {
-            extract_from_op_expr(root, baserel_id, (*baserel).relids, expr as _)
-        } else if is_a(expr, pg_sys::NodeTag_T_NullTest) {
-            extract_from_null_test(baserel_id, expr as _)
-        } else if is_a(expr, pg_sys::NodeTag_T_ScalarArrayOpExpr) {
-            extract_from_scalar_array_op_expr(root, baserel_id, (*baserel).relids, expr as _)
-        } else if is_a(expr, pg_sys::NodeTag_T_Var) {
-            extract_from_var(root, baserel_id, (*baserel).relids, expr as _)
-        } else if is_a(expr, pg_sys::NodeTag_T_BoolExpr) {
-            extract_from_bool_expr(root, baserel_id, (*baserel).relids, expr as _)
+        if let Some(qual) = extract_qual(root, baserel_id, (*baserel).relids, expr, params) {
+            quals.push((qual, cond));
+        }
+    }
+
+    quals
+}
+
+// extract a boolean expression tree, all of its leaves must be quals without
+// parameters
+unsafe fn extract_qual_expr(
+    root: *mut pg_sys::PlannerInfo,
+    baserel_id: pg_sys::Oid,
+    baserel_ids: pg_sys::Relids,
+    expr: *mut pg_sys::Node,
+) -> Option<QualExpr> {
+    if !is_a(expr, pg_sys::NodeTag_T_BoolExpr) {
+        let mut params = Vec::new();
+        let qual = extract_qual(root, baserel_id, baserel_ids, expr, &mut params)?;
+        return if params.is_empty() {
+            Some(QualExpr::Qual(qual))
         } else {
-            if let Some(stm) = pgx::nodes::node_to_string(expr) {
-                report_warning(&format!("unsupported qual: {}", stm));
-            }
             None
         };
+    }
 
-        if let Some(qual) = extracted {
-            quals.push(qual);
+    let bool_expr = expr as *mut pg_sys::BoolExpr;
+    let mut args = Vec::new();
+    for arg in PgList::<pg_sys::Node>::from_pg((*bool_expr).args).iter_ptr() {
+        args.push(extract_qual_expr(root, baserel_id, baserel_ids, arg)?);
+    }
+
+    match (*bool_expr).boolop {
+        pg_sys::BoolExprType_AND_EXPR => Some(QualExpr::And(args)),
+        pg_sys::BoolExprType_OR_EXPR => Some(QualExpr::Or(args)),
+        pg_sys::BoolExprType_NOT_EXPR if args.len() == 1 => {
+            Some(QualExpr::Not(Box::new(args.pop().unwrap())))
         }
+        _ => None,
     }
+}
 
-    quals
+// extract boolean expression trees from the restrictions on a base relation
+// which are not extracted as quals, each tree is paired with the restriction
+// it is extracted from
+pub(crate) unsafe fn extract_qual_exprs(
+    root: *mut pg_sys::PlannerInfo,
+    baserel: *mut pg_sys::RelOptInfo,
+    baserel_id: pg_sys::Oid,
+    conds: *mut pg_sys::List,
+    qual_conds: &[*mut pg_sys::RestrictInfo],
+) -> Vec<(QualExpr, *mut pg_sys::RestrictInfo)> {
+    let mut exprs = Vec::new();
+
+    let conds = PgList::<pg_sys::RestrictInfo>::from_pg(conds);
+    for cond in conds.iter_ptr() {
+        let expr = (*cond).clause as *mut pg_sys::Node;
+        if !is_a(expr, pg_sys::NodeTag_T_BoolExpr) || qual_conds.contains(&cond) {
+            continue;
+        }
+        if let Some(qual_expr) = extract_qual_expr(root, baserel_id, (*baserel).relids, expr) {
+            exprs.push((qual_expr, cond));
+        }
+    }
+
+    exprs
+}
+
+// extract join condition between the outer and inner relations, only binary
+// operator between an outer column and an inner column is supported
+pub(crate) unsafe fn extract_join_qual(
+    root: *mut pg_sys::PlannerInfo,
+    outerrel: *mut pg_sys::RelOptInfo,
+    innerrel: *mut pg_sys::RelOptInfo,
+    expr: *mut pg_sys::Node,
+) -> Option<JoinQual> {
+    if !is_a(expr, pg_sys::NodeTag_T_OpExpr) {
+        return None;
+    }
+
+    let expr = expr as *mut pg_sys::OpExpr;
+    let args: PgList<pg_sys::Node> = PgList::from_pg((*expr).args);
+
+    // only deal with binary operator
+    if args.len() != 2 {
+        return None;
+    }
+
+    // get operator
+    let mut opr = get_operator((*expr).opno);
+    if opr.is_null() {
+        return None;
+    }
+
+    let left = unnest_clause(args.head().unwrap());
+    let right = unnest_clause(args.tail().unwrap());
+    if !is_a(left, pg_sys::NodeTag_T_Var) || !is_a(right, pg_sys::NodeTag_T_Var) {
+        return None;
+    }
+
+    let mut left = left as *mut pg_sys::Var;
+    let mut right = right as *mut pg_sys::Var;
+    if (*left).varattno < 1 || (*right).varattno < 1 {
+        return None;
+    }
+
+    // swap operands if the left one is from inner relation, the operator
+    // must have a commutator in that case
+    if pg_sys::bms_is_member((*left).varno as c_int, (*innerrel).relids) {
+        if (*opr).oprcom == 0 {
+            return None;
+        }
+        opr = get_operator((*opr).oprcom);
+        if opr.is_null() {
+            return None;
+        }
+        std::mem::swap(&mut left, &mut right);
+    }
+
+    if !pg_sys::bms_is_member((*left).varno as c_int, (*outerrel).relids)
+        || !pg_sys::bms_is_member((*right).varno as c_int, (*innerrel).relids)
+    {
+        return None;
+    }
+
+    let outer_rte = pg_sys::planner_rt_fetch((*left).varno as u32, root);
+    let inner_rte = pg_sys::planner_rt_fetch((*right).varno as u32, root);
+    let outer_field = pg_sys::get_attname((*outer_rte).relid, (*left).varattno, false);
+    let inner_field = pg_sys::get_attname((*inner_rte).relid, (*right).varattno, false);
+
+    Some(JoinQual {
+        outer_field: CStr::from_ptr(outer_field).to_str().unwrap().to_string(),
+        operator: pgx::name_data_to_str(&(*opr).oprname).to_string(),
+        inner_field: CStr::from_ptr(inner_field).to_str().unwrap().to_string(),
+    })
 }
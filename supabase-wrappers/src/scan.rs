@@ -1,17 +1,42 @@
-use pgx::{debug2, memcxt::PgMemoryContexts, pg_sys::Datum, prelude::*, IntoDatum, PgSqlErrorCode};
+use pgx::{
+    debug2, is_a, memcxt::PgMemoryContexts, pg_sys::Datum, prelude::*, FromDatum, IntoDatum,
+    PgList, PgSqlErrorCode,
+};
 use std::collections::HashMap;
 
-use std::os::raw::c_int;
-use std::ptr;
+use std::io::{ErrorKind, Read};
+use std::os::raw::{c_int, c_void};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::time::{Duration, Instant};
+use std::{mem, ptr};
 
+use crate::aggregate::*;
+use crate::deparse::{Deparser, Dialect};
 use crate::instance;
-use crate::interface::{Column, Limit, Qual, Row, Sort};
+use crate::interface::{
+    cell_into_datum, Aggregate, AsyncNotifier, Cell, Column, FdwError, FdwResult, Join, JoinRel,
+    JoinType, Limit, Qual, QualExpr, Row, RowBatch, ScanCost, Sort, Value,
+};
 use crate::limit::*;
+use crate::parallel::{Decoder, Encoder, ParallelScanShared};
 use crate::polyfill;
 use crate::prelude::ForeignDataWrapper;
 use crate::qual::*;
 use crate::sort::*;
-use crate::utils::{self, report_error, SerdeList};
+use crate::utils::{self, report_error, ReportUnwrap, SerdeList};
+
+// progress of an asynchronous scan
+enum AsyncScan {
+    // the scan is not begun yet
+    NotStarted,
+
+    // waiting for the FDW to notify on the socket
+    Waiting(UnixStream),
+
+    // the scan result is ready to be fetched
+    Ready,
+}
 
 // Fdw private state for scan
 struct FdwState<W: ForeignDataWrapper> {
@@ -21,6 +46,30 @@ struct FdwState<W: ForeignDataWrapper> {
     // query conditions
     quals: Vec<Qual>,
 
+    // whether each qual is fully evaluated remotely
+    remote_quals: Vec<bool>,
+
+    // restrictions which the quals are extracted from
+    qual_conds: Vec<*mut pg_sys::RestrictInfo>,
+
+    // query conditions with boolean operators
+    qual_exprs: Vec<QualExpr>,
+
+    // whether each qual expression is fully evaluated remotely
+    remote_qual_exprs: Vec<bool>,
+
+    // restrictions which the qual expressions are extracted from
+    qual_expr_conds: Vec<*mut pg_sys::RestrictInfo>,
+
+    // parameter expressions used by the quals
+    params: Vec<*mut pg_sys::Node>,
+
+    // initialized parameter expressions for execution
+    param_states: Vec<*mut pg_sys::ExprState>,
+
+    // true if the scan returns no rows because of a null parameter value
+    empty_scan: bool,
+
     // query target column list
     tgts: Vec<Column>,
 
@@ -33,13 +82,50 @@ struct FdwState<W: ForeignDataWrapper> {
     // foreign table options
     opts: HashMap<String, String>,
 
+    // scan cost estimate
+    cost: ScanCost,
+
+    // pushed down join
+    join: Option<Join>,
+
+    // pushed down aggregation
+    aggregate: Option<Aggregate>,
+
+    // target list of join or aggregate scan, which has no scan relation
+    scan_tlist: *mut pg_sys::List,
+
     // temporary memory context
     tmp_ctx: PgMemoryContexts,
 
+    // true if the time spent in the FDW is measured for EXPLAIN ANALYZE
+    timing: bool,
+
+    // time spent in the FDW when executing the scan
+    elapsed: Duration,
+
+    // asynchronous scan progress, None if the scan is synchronous
+    async_scan: Option<AsyncScan>,
+
+    // number of partitions of a parallel scan, 0 if the scan is not parallel
+    parallel_partitions: usize,
+
+    // shared state in dynamic shared memory, null if the parallel scan is
+    // executed by this process alone
+    parallel_shared: *mut ParallelScanShared,
+
+    // next partition to scan when there is no shared state
+    next_partition: usize,
+
+    // partition being scanned
+    partition: Option<usize>,
+
     // query result list
     values: Vec<Datum>,
     nulls: Vec<bool>,
     row: Row,
+
+    // batch of fetched rows, None if rows are fetched one by one
+    batch: Option<RowBatch>,
 }
 
 impl<W: ForeignDataWrapper> FdwState<W> {
@@ -47,19 +133,39 @@ impl<W: ForeignDataWrapper> FdwState<W> {
         Self {
             instance: instance::create_fdw_instance(foreigntableid),
             quals: Vec::new(),
+            remote_quals: Vec::new(),
+            qual_conds: Vec::new(),
+            qual_exprs: Vec::new(),
+            remote_qual_exprs: Vec::new(),
+            qual_expr_conds: Vec::new(),
+            params: Vec::new(),
+            param_states: Vec::new(),
+            empty_scan: false,
             tgts: Vec::new(),
             sorts: Vec::new(),
             limit: None,
             opts: HashMap::new(),
+            cost: ScanCost::default(),
+            join: None,
+            aggregate: None,
+            scan_tlist: ptr::null_mut(),
             tmp_ctx: PgMemoryContexts::CurTransactionContext
                 .switch_to(|_| PgMemoryContexts::new("Wrappers temp data")),
+            timing: false,
+            elapsed: Duration::ZERO,
+            async_scan: None,
+            parallel_partitions: 0,
+            parallel_shared: ptr::null_mut(),
+            next_partition: 0,
+            partition: None,
             values: Vec::new(),
             nulls: Vec::new(),
             row: Row::new(),
+            batch: None,
         }
     }
 
-    fn get_rel_size(&mut self) -> (i64, i32) {
+    fn get_rel_size(&mut self) -> FdwResult<(i64, i32)> {
         self.instance.get_rel_size(
             &self.quals,
             &self.tgts,
@@ -69,36 +175,420 @@ impl<W: ForeignDataWrapper> FdwState<W> {
         )
     }
 
-    fn begin_scan(&mut self) {
-        self.instance.begin_scan(
+    fn get_parallel_partitions(&mut self) -> FdwResult<usize> {
+        self.instance
+            .get_parallel_partitions(&self.quals, &self.opts)
+    }
+
+    fn can_push_down_quals(&mut self) -> FdwResult<Vec<bool>> {
+        let ret = self.instance.can_push_down_quals(&self.quals, &self.opts)?;
+        if ret.len() != self.quals.len() {
+            return Err(format!(
+                "expected {} qual push down flags but got {}",
+                self.quals.len(),
+                ret.len()
+            )
+            .into());
+        }
+        Ok(ret)
+    }
+
+    fn can_push_down_qual_exprs(&mut self) -> FdwResult<Vec<bool>> {
+        let ret = self
+            .instance
+            .can_push_down_qual_exprs(&self.qual_exprs, &self.opts)?;
+        if ret.len() != self.qual_exprs.len() {
+            return Err(format!(
+                "expected {} qual expression push down flags but got {}",
+                self.qual_exprs.len(),
+                ret.len()
+            )
+            .into());
+        }
+        Ok(ret)
+    }
+
+    // set the parameter values of quals, return false if any of them is null,
+    // in which case the scan returns no rows as the operators are strict
+    unsafe fn assign_params(&mut self, node: *mut pg_sys::ForeignScanState) -> bool {
+        let econtext = (*node).ss.ps.ps_ExprContext;
+        for qual in self.quals.iter_mut() {
+            let (id, type_oid) = match &qual.param {
+                Some(param) => (param.id, param.type_oid),
+                None => continue,
+            };
+            let expr_state = self.param_states[id - 1];
+            let cell = PgMemoryContexts::For((*econtext).ecxt_per_tuple_memory).switch_to(|_| {
+                let mut is_null = false;
+                let eval = (*expr_state).evalfunc.unwrap();
+                let datum = eval(expr_state, econtext, &mut is_null);
+                Cell::from_polymorphic_datum(datum, is_null, type_oid)
+            });
+            match cell {
+                // copy the value out of the per-tuple memory
+                Some(cell) => qual.value = Value::Cell(cell.clone()),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    // pass the qual expressions evaluated remotely to the FDW
+    fn begin_scan_qual_exprs(&mut self) -> FdwResult<()> {
+        let exprs: Vec<QualExpr> = self
+            .qual_exprs
+            .iter()
+            .zip(self.remote_qual_exprs.iter())
+            .filter(|(_, remote)| **remote)
+            .map(|(expr, _)| expr.clone())
+            .collect();
+        self.instance.begin_scan_qual_exprs(&exprs)
+    }
+
+    fn begin_scan(&mut self) -> FdwResult<()> {
+        let start = self.timing.then(Instant::now);
+        let ret = if let Some(join) = &self.join {
+            self.instance.begin_join_scan(join)
+        } else if let Some(aggregate) = &self.aggregate {
+            self.instance.begin_aggregate_scan(aggregate)
+        } else {
+            self.begin_scan_qual_exprs().and_then(|_| {
+                self.instance.begin_scan(
+                    &self.quals,
+                    &self.tgts,
+                    &self.sorts,
+                    &self.limit,
+                    &self.opts,
+                )
+            })
+        };
+        self.add_elapsed(start);
+        ret
+    }
+
+    // begin an asynchronous scan, the FDW notifies through a socket pair
+    // when the scan result is ready
+    unsafe fn begin_async_scan(&mut self, node: *mut pg_sys::ForeignScanState) -> FdwResult<()> {
+        self.empty_scan = !self.assign_params(node);
+        if self.empty_scan {
+            self.async_scan = Some(AsyncScan::Ready);
+            return Ok(());
+        }
+
+        let (reader, writer) = UnixStream::pair().map_err(|err| {
+            FdwError::new(
+                PgSqlErrorCode::ERRCODE_FDW_ERROR,
+                format!("create async scan socket failed: {}", err),
+            )
+        })?;
+        reader.set_nonblocking(true).map_err(|err| {
+            FdwError::new(
+                PgSqlErrorCode::ERRCODE_FDW_ERROR,
+                format!("set async scan socket nonblocking failed: {}", err),
+            )
+        })?;
+        self.async_scan = Some(AsyncScan::Waiting(reader));
+
+        let start = self.timing.then(Instant::now);
+        let ret = self.begin_scan_qual_exprs().and_then(|_| {
+            self.instance.begin_async_scan(
+                &self.quals,
+                &self.tgts,
+                &self.sorts,
+                &self.limit,
+                &self.opts,
+                AsyncNotifier::new(writer),
+            )
+        });
+        self.add_elapsed(start);
+        ret
+    }
+
+    // check if the FDW has notified, the scan result is ready if so or if
+    // the notifier is dropped
+    fn poll_async_scan(&mut self) -> FdwResult<bool> {
+        let sock = match &mut self.async_scan {
+            Some(AsyncScan::Waiting(sock)) => sock,
+            Some(AsyncScan::Ready) => return Ok(true),
+            _ => return Ok(false),
+        };
+        let mut buf = [0u8; 1];
+        match sock.read(&mut buf) {
+            Ok(_) => {
+                self.async_scan = Some(AsyncScan::Ready);
+                Ok(true)
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {
+                Ok(false)
+            }
+            Err(err) => Err(FdwError::new(
+                PgSqlErrorCode::ERRCODE_FDW_ERROR,
+                format!("read async scan socket failed: {}", err),
+            )),
+        }
+    }
+
+    // wait until the FDW has notified, the wait is on both the socket and
+    // the process latch so that query cancel and termination are handled
+    unsafe fn wait_async_scan(&mut self) -> FdwResult<()> {
+        while !self.poll_async_scan()? {
+            let fd = match &self.async_scan {
+                Some(AsyncScan::Waiting(sock)) => sock.as_raw_fd(),
+                _ => break,
+            };
+            let events =
+                pg_sys::WL_LATCH_SET | pg_sys::WL_SOCKET_READABLE | pg_sys::WL_EXIT_ON_PM_DEATH;
+            let rc = pg_sys::WaitLatchOrSocket(
+                pg_sys::MyLatch,
+                events as _,
+                fd,
+                -1,
+                pg_sys::PG_WAIT_EXTENSION,
+            );
+            if rc & pg_sys::WL_LATCH_SET as c_int != 0 {
+                pg_sys::ResetLatch(pg_sys::MyLatch);
+            }
+            polyfill::check_for_interrupts();
+        }
+        Ok(())
+    }
+
+    fn explain(&mut self, verbose: bool) -> FdwResult<Vec<(String, String)>> {
+        if let Some(join) = &self.join {
+            return self.instance.explain_join_scan(join, verbose);
+        }
+        if let Some(aggregate) = &self.aggregate {
+            return self.instance.explain_aggregate_scan(aggregate, verbose);
+        }
+        self.begin_scan_qual_exprs()?;
+        self.instance.explain_scan(
             &self.quals,
             &self.tgts,
             &self.sorts,
             &self.limit,
             &self.opts,
+            verbose,
         )
     }
 
-    fn iter_scan(&mut self) -> Option<()> {
-        self.instance.iter_scan(&mut self.row)
+    fn add_elapsed(&mut self, start: Option<Instant>) {
+        if let Some(start) = start {
+            self.elapsed += start.elapsed();
+        }
+    }
+
+    // get scan cost from the FDW, which can be overridden by the cost
+    // options on the foreign server or foreign table
+    unsafe fn get_rel_cost(&mut self, foreigntableid: pg_sys::Oid) -> FdwResult<ScanCost> {
+        let mut cost = self.instance.get_rel_cost(&self.opts)?;
+        let opts = self.cost_options(foreigntableid);
+        if let Some(startup_cost) = cost_option(&opts, "fdw_startup_cost")? {
+            cost.startup_cost = startup_cost;
+        } else if let Some(startup_cost) = cost_option(&opts, "startup_cost")? {
+            cost.startup_cost = startup_cost;
+        }
+        if let Some(tuple_cost) = cost_option(&opts, "fdw_tuple_cost")? {
+            cost.tuple_cost = tuple_cost;
+        }
+        Ok(cost)
+    }
+
+    // check if row count is estimated by the FDW or local statistics
+    unsafe fn use_remote_estimate(&self, foreigntableid: pg_sys::Oid) -> FdwResult<bool> {
+        let opts = self.cost_options(foreigntableid);
+        match opts.get("use_remote_estimate") {
+            Some(value) => match value.to_lowercase().as_str() {
+                "true" | "on" | "yes" | "1" => Ok(true),
+                "false" | "off" | "no" | "0" => Ok(false),
+                _ => Err(invalid_option("use_remote_estimate", value)),
+            },
+            None => Ok(false),
+        }
+    }
+
+    // foreign server options overridden by the foreign table options
+    unsafe fn cost_options(&self, foreigntableid: pg_sys::Oid) -> HashMap<String, String> {
+        let ftable = pg_sys::GetForeignTable(foreigntableid);
+        let fserver = pg_sys::GetForeignServer((*ftable).serverid);
+        let mut opts = utils::options_to_hashmap((*fserver).options);
+        opts.extend(self.opts.iter().map(|(k, v)| (k.clone(), v.clone())));
+        opts
+    }
+
+    fn iter_scan(&mut self) -> FdwResult<Option<()>> {
+        let start = self.timing.then(Instant::now);
+        let ret = if self.parallel_partitions > 0 {
+            self.iter_parallel_scan()
+        } else {
+            self.iter_instance()
+        };
+        self.add_elapsed(start);
+        ret
+    }
+
+    // fetch the next row from the instance, the rows are fetched in batches
+    // if the instance supports it
+    fn iter_instance(&mut self) -> FdwResult<Option<()>> {
+        match &mut self.batch {
+            Some(batch) => {
+                if batch.taken_all() {
+                    batch.clear();
+                    if self.instance.iter_scan_batch(batch)? == 0 || batch.is_empty() {
+                        return Ok(None);
+                    }
+                }
+                Ok(Some(()))
+            }
+            None => self.instance.iter_scan(&mut self.row),
+        }
+    }
+
+    // scan the partitions claimed by this process one after another, until
+    // all partitions are claimed by the leader and workers
+    fn iter_parallel_scan(&mut self) -> FdwResult<Option<()>> {
+        loop {
+            if self.partition.is_none() {
+                let partition = match self.claim_partition() {
+                    Some(partition) => partition,
+                    None => return Ok(None),
+                };
+                self.begin_scan_qual_exprs()?;
+                self.instance.begin_parallel_scan(
+                    &self.quals,
+                    &self.tgts,
+                    &self.opts,
+                    partition,
+                    self.parallel_partitions,
+                )?;
+                self.partition = Some(partition);
+            }
+            if self.iter_instance()?.is_some() {
+                return Ok(Some(()));
+            }
+            self.instance.end_scan()?;
+            self.partition = None;
+        }
+    }
+
+    fn claim_partition(&mut self) -> Option<usize> {
+        let partition = if self.parallel_shared.is_null() {
+            self.next_partition += 1;
+            self.next_partition - 1
+        } else {
+            unsafe { (*self.parallel_shared).claim() }
+        };
+        (partition < self.parallel_partitions).then_some(partition)
+    }
+
+    // encode the scan state needed by a parallel worker
+    unsafe fn encode(&self) -> Vec<u8> {
+        let mut enc = Encoder::default();
+        enc.usize(self.quals.len());
+        for qual in &self.quals {
+            enc.qual(qual);
+        }
+        let exprs: Vec<&QualExpr> = self
+            .qual_exprs
+            .iter()
+            .zip(self.remote_qual_exprs.iter())
+            .filter(|(_, remote)| **remote)
+            .map(|(expr, _)| expr)
+            .collect();
+        enc.usize(exprs.len());
+        for expr in exprs {
+            enc.qual_expr(expr);
+        }
+        enc.usize(self.tgts.len());
+        for tgt in &self.tgts {
+            enc.column(tgt);
+        }
+        enc.usize(self.sorts.len());
+        for sort in &self.sorts {
+            enc.sort(sort);
+        }
+        enc.limit(&self.limit);
+        enc.usize(self.parallel_partitions);
+        enc.into_bytes()
+    }
+
+    // decode the scan state in a parallel worker
+    unsafe fn decode(&mut self, buf: &[u8]) {
+        let mut dec = Decoder::new(buf);
+        let len = dec.usize();
+        self.quals = (0..len).map(|_| dec.qual()).collect();
+        let len = dec.usize();
+        self.qual_exprs = (0..len).map(|_| dec.qual_expr()).collect();
+        self.remote_qual_exprs = vec![true; len];
+        let len = dec.usize();
+        self.tgts = (0..len).map(|_| dec.column()).collect();
+        let len = dec.usize();
+        self.sorts = (0..len).map(|_| dec.sort()).collect();
+        self.limit = dec.limit();
+        self.parallel_partitions = dec.usize();
+    }
+
+    fn re_scan(&mut self) -> FdwResult<()> {
+        self.clear_batch();
+        let start = self.timing.then(Instant::now);
+        let ret = self.instance.re_scan();
+        self.add_elapsed(start);
+        ret
+    }
+
+    fn end_scan(&mut self) -> FdwResult<()> {
+        self.clear_batch();
+        self.instance.end_scan()
     }
 
-    fn re_scan(&mut self) {
-        self.instance.re_scan()
+    // drop the rows fetched but not returned yet
+    fn clear_batch(&mut self) {
+        if let Some(batch) = &mut self.batch {
+            batch.clear();
+        }
     }
 
-    fn end_scan(&mut self) {
-        self.instance.end_scan();
+    // store the next row taken from the batch into the result lists
+    unsafe fn store_batch_row(&mut self) -> FdwResult<()> {
+        let batch = self.batch.as_mut().unwrap();
+        if let Some(cells) = batch.take_row() {
+            for (tgt, cell) in self.tgts.iter().zip(cells.iter_mut()) {
+                let att_idx = tgt.num - 1;
+                match cell.take() {
+                    Some(cell) => {
+                        self.values[att_idx] = cell_into_datum(cell, tgt)?;
+                        self.nulls[att_idx] = false;
+                    }
+                    None => self.nulls[att_idx] = true,
+                }
+            }
+        }
+        Ok(())
     }
 
     fn clear(&mut self) {
         self.quals.clear();
         self.quals.shrink_to_fit();
+        self.remote_quals.clear();
+        self.remote_quals.shrink_to_fit();
+        self.qual_conds.clear();
+        self.qual_conds.shrink_to_fit();
+        self.qual_exprs.clear();
+        self.qual_exprs.shrink_to_fit();
+        self.remote_qual_exprs.clear();
+        self.remote_qual_exprs.shrink_to_fit();
+        self.qual_expr_conds.clear();
+        self.qual_expr_conds.shrink_to_fit();
+        self.params.clear();
+        self.params.shrink_to_fit();
+        self.param_states.clear();
+        self.param_states.shrink_to_fit();
         self.tgts.clear();
         self.tgts.shrink_to_fit();
         self.sorts.clear();
         self.sorts.shrink_to_fit();
         self.limit.take();
+        self.join.take();
+        self.aggregate.take();
         self.opts.clear();
         self.opts.shrink_to_fit();
         self.values.clear();
@@ -111,6 +601,26 @@ impl<W: ForeignDataWrapper> FdwState<W> {
 
 impl<W: ForeignDataWrapper> utils::SerdeList for FdwState<W> {}
 
+fn invalid_option(name: &str, value: &str) -> FdwError {
+    FdwError::new(
+        PgSqlErrorCode::ERRCODE_FDW_INVALID_OPTION_NAME,
+        format!("invalid option {}: {}", name, value),
+    )
+}
+
+// parse a non-negative cost option
+fn cost_option(opts: &HashMap<String, String>, name: &str) -> FdwResult<Option<f64>> {
+    opts.get(name)
+        .map(|value| match value.parse::<f64>() {
+            Ok(cost) if cost >= 0.0 => Ok(cost),
+            _ => Err(invalid_option(name, value)),
+        })
+        .transpose()
+}
+
+// number of rows assumed for a foreign table which has never been analyzed
+const DEFAULT_ROWS: f64 = 1000.0;
+
 #[pg_guard]
 pub(super) extern "C" fn get_foreign_rel_size<W: ForeignDataWrapper>(
     root: *mut pg_sys::PlannerInfo,
@@ -125,7 +635,26 @@ pub(super) extern "C" fn get_foreign_rel_size<W: ForeignDataWrapper>(
         let mut old_ctx = state.tmp_ctx.set_as_current();
 
         // extract qual list
-        state.quals = extract_quals(root, baserel, foreigntableid);
+        (state.quals, state.qual_conds) = extract_quals(
+            root,
+            baserel,
+            foreigntableid,
+            (*baserel).baserestrictinfo,
+            &mut state.params,
+        )
+        .into_iter()
+        .unzip();
+
+        // extract conditions with boolean operators which are not quals
+        (state.qual_exprs, state.qual_expr_conds) = extract_qual_exprs(
+            root,
+            baserel,
+            foreigntableid,
+            (*baserel).baserestrictinfo,
+            &state.qual_conds,
+        )
+        .into_iter()
+        .unzip();
 
         // extract target column list from target and restriction expression
         state.tgts = utils::extract_target_columns(root, baserel);
@@ -140,10 +669,29 @@ pub(super) extern "C" fn get_foreign_rel_size<W: ForeignDataWrapper>(
         let ftable = pg_sys::GetForeignTable(foreigntableid);
         state.opts = utils::options_to_hashmap((*ftable).options);
 
-        // get estimate row count and mean row width
-        let (rows, width) = state.get_rel_size();
-        (*baserel).rows = rows as f64;
-        (*(*baserel).reltarget).width = width;
+        // check which quals are fully evaluated remotely
+        state.remote_quals = state.can_push_down_quals().report_unwrap();
+        state.remote_qual_exprs = state.can_push_down_qual_exprs().report_unwrap();
+
+        // get estimate row count and mean row width, either from the FDW or
+        // from the local statistics. Foreign table analyzed by the FDW has at
+        // least one page, otherwise there are no statistics and the FDW is
+        // asked if it can estimate.
+        let analyzed = (*baserel).pages > 0;
+        if state.use_remote_estimate(foreigntableid).report_unwrap()
+            || (!analyzed && W::can_estimate_rel_size(&state.opts))
+        {
+            let (rows, width) = state.get_rel_size().report_unwrap();
+            (*baserel).rows = rows as f64;
+            (*(*baserel).reltarget).width = width;
+        } else {
+            if !analyzed {
+                (*baserel).tuples = DEFAULT_ROWS;
+            }
+            pg_sys::set_baserel_size_estimates(root, baserel);
+        }
+
+        state.cost = state.get_rel_cost(foreigntableid).report_unwrap();
 
         old_ctx.set_as_current();
 
@@ -156,26 +704,15 @@ pub(super) extern "C" fn get_foreign_rel_size<W: ForeignDataWrapper>(
 pub(super) extern "C" fn get_foreign_paths<W: ForeignDataWrapper>(
     root: *mut pg_sys::PlannerInfo,
     baserel: *mut pg_sys::RelOptInfo,
-    _foreigntableid: pg_sys::Oid,
+    foreigntableid: pg_sys::Oid,
 ) {
     debug2!("---> get_foreign_paths");
     unsafe {
-        let state = PgBox::<FdwState<W>>::from_pg((*baserel).fdw_private as _);
-
-        // get startup cost from foreign table options
-        let startup_cost = state
-            .opts
-            .get("startup_cost")
-            .map(|c| match c.parse::<f64>() {
-                Ok(v) => v,
-                Err(_) => {
-                    pgx::error!("invalid option startup_cost: {}", c);
-                }
-            })
-            .unwrap_or(0.0);
-        let total_cost = startup_cost + (*baserel).rows;
+        let mut state = PgBox::<FdwState<W>>::from_pg((*baserel).fdw_private as _);
 
-        // create a ForeignPath node and add it as the only possible path
+        let (startup_cost, total_cost) = state.cost.path_costs((*baserel).rows);
+
+        // create a ForeignPath node and add it as the only unsorted path
         let path = pg_sys::create_foreignscan_path(
             root,
             baserel,
@@ -189,39 +726,651 @@ pub(super) extern "C" fn get_foreign_paths<W: ForeignDataWrapper>(
             ptr::null_mut(), // no fdw_private data
         );
         pg_sys::add_path(baserel, &mut ((*path).path));
+
+        // add a partial path if the scan can be split into partitions, which
+        // are scanned by the leader and parallel workers together
+        if (*baserel).consider_parallel {
+            let partitions = state.get_parallel_partitions().report_unwrap();
+            let workers = partitions.min(pg_sys::max_parallel_workers_per_gather as usize);
+            if partitions > 1 && workers > 0 {
+                let rows = (*baserel).rows / (workers + 1) as f64;
+                let (startup_cost, total_cost) = state.cost.path_costs(rows);
+                let path = pg_sys::create_foreignscan_path(
+                    root,
+                    baserel,
+                    ptr::null_mut(), // default pathtarget
+                    rows,
+                    startup_cost,
+                    total_cost,
+                    ptr::null_mut(), // no pathkeys
+                    ptr::null_mut(), // no outer rel either
+                    ptr::null_mut(), // no extra plan
+                    ptr::null_mut(), // no fdw_private data
+                );
+                (*path).path.parallel_aware = true;
+                (*path).path.parallel_workers = workers as c_int;
+                pg_sys::add_partial_path(baserel, &mut ((*path).path));
+                state.parallel_partitions = partitions;
+            }
+        }
+
+        // add sorted paths for the sort orders the FDW can return, which cost
+        // a bit more than the unsorted path but less than a local sort
+        for pathkeys in get_useful_pathkeys(root, baserel) {
+            let sorts = match pathkeys_to_sorts(pathkeys, baserel, foreigntableid) {
+                Some(sorts) => sorts,
+                None => continue,
+            };
+            if !state.instance.can_push_down_sort(&sorts).report_unwrap() {
+                continue;
+            }
+
+            let path = pg_sys::create_foreignscan_path(
+                root,
+                baserel,
+                ptr::null_mut(), // default pathtarget
+                (*baserel).rows,
+                startup_cost,
+                total_cost + (*baserel).rows * pg_sys::cpu_operator_cost,
+                pathkeys,
+                ptr::null_mut(), // no outer rel either
+                ptr::null_mut(), // no extra plan
+                ptr::null_mut(), // no fdw_private data
+            );
+            pg_sys::add_path(baserel, &mut ((*path).path));
+        }
+
+        // add parameterized paths, so that a nested loop join only fetches
+        // the matching rows for each outer row
+        for required_outer in get_param_outer_rels(root, baserel, foreigntableid, &mut state) {
+            let param_info = pg_sys::get_baserel_parampathinfo(root, baserel, required_outer);
+            let rows = (*param_info).ppi_rows;
+            let (startup_cost, total_cost) = state.cost.path_costs(rows);
+            let path = pg_sys::create_foreignscan_path(
+                root,
+                baserel,
+                ptr::null_mut(), // default pathtarget
+                rows,
+                startup_cost,
+                total_cost,
+                ptr::null_mut(), // no pathkeys
+                required_outer,
+                ptr::null_mut(), // no extra plan
+                ptr::null_mut(), // no fdw_private data
+            );
+            pg_sys::add_path(baserel, &mut ((*path).path));
+        }
     }
 }
 
+// state of ec_member_matches_foreign(), which is used to get the join clauses
+// from equivalence classes one column at a time, same as postgres_fdw
+struct EcMemberMatch {
+    current: *mut pg_sys::Expr,
+    already_used: Vec<*mut pg_sys::Expr>,
+}
+
 #[pg_guard]
-pub(super) extern "C" fn get_foreign_plan<W: ForeignDataWrapper>(
+extern "C" fn ec_member_matches_foreign(
     _root: *mut pg_sys::PlannerInfo,
+    _rel: *mut pg_sys::RelOptInfo,
+    _ec: *mut pg_sys::EquivalenceClass,
+    em: *mut pg_sys::EquivalenceMember,
+    arg: *mut c_void,
+) -> bool {
+    unsafe {
+        let matcher = &mut *(arg as *mut EcMemberMatch);
+        let expr = (*em).em_expr;
+
+        // after the first match, only match the same expression
+        if !matcher.current.is_null() {
+            return pg_sys::equal(expr as _, matcher.current as _);
+        }
+
+        if matcher
+            .already_used
+            .iter()
+            .any(|used| pg_sys::equal(expr as _, *used as _))
+        {
+            return false;
+        }
+
+        matcher.current = expr;
+        true
+    }
+}
+
+// get the join clauses which can be moved into a parameterized scan on a
+// base relation, from both its join info and equivalence classes
+unsafe fn get_join_clauses(
+    root: *mut pg_sys::PlannerInfo,
+    baserel: *mut pg_sys::RelOptInfo,
+) -> Vec<*mut pg_sys::RestrictInfo> {
+    let mut ret: Vec<_> = PgList::<pg_sys::RestrictInfo>::from_pg((*baserel).joininfo)
+        .iter_ptr()
+        .collect();
+
+    if (*baserel).has_eclass_joins {
+        let mut matcher = EcMemberMatch {
+            current: ptr::null_mut(),
+            already_used: Vec::new(),
+        };
+        loop {
+            matcher.current = ptr::null_mut();
+            let clauses = pg_sys::generate_implied_equalities_for_column(
+                root,
+                baserel,
+                Some(ec_member_matches_foreign),
+                &mut matcher as *mut EcMemberMatch as _,
+                (*baserel).lateral_referencers,
+            );
+            if matcher.current.is_null() {
+                break;
+            }
+            ret.extend(PgList::<pg_sys::RestrictInfo>::from_pg(clauses).iter_ptr());
+            matcher.already_used.push(matcher.current);
+        }
+    }
+
+    ret.retain(|cond| pg_sys::join_clause_is_movable_to(*cond, baserel));
+    ret
+}
+
+// get the outer relation sets of parameterized paths, each of them provides
+// the parameter values of a join clause which the FDW evaluates remotely
+unsafe fn get_param_outer_rels<W: ForeignDataWrapper>(
+    root: *mut pg_sys::PlannerInfo,
+    baserel: *mut pg_sys::RelOptInfo,
+    foreigntableid: pg_sys::Oid,
+    state: &mut FdwState<W>,
+) -> Vec<pg_sys::Relids> {
+    let mut ret: Vec<pg_sys::Relids> = Vec::new();
+
+    for cond in get_join_clauses(root, baserel) {
+        let mut conds = PgList::<pg_sys::RestrictInfo>::new();
+        conds.push(cond);
+        let mut params = Vec::new();
+        let extracted = extract_quals(root, baserel, foreigntableid, conds.into_pg(), &mut params);
+        let qual = match extracted.into_iter().next() {
+            Some((qual, _)) if qual.param.is_some() => qual,
+            _ => continue,
+        };
+
+        // a parameterized path is only worth it if the FDW evaluates the
+        // join clause remotely
+        let mut quals = state.quals.clone();
+        quals.push(qual);
+        let remote_quals = state
+            .instance
+            .can_push_down_quals(&quals, &state.opts)
+            .report_unwrap();
+        if remote_quals.last() != Some(&true) {
+            continue;
+        }
+
+        let required_outer = pg_sys::bms_difference(
+            pg_sys::bms_union((*cond).clause_relids, (*baserel).lateral_relids),
+            (*baserel).relids,
+        );
+        if pg_sys::bms_is_empty(required_outer)
+            || ret
+                .iter()
+                .any(|relids| pg_sys::bms_equal(*relids, required_outer))
+        {
+            continue;
+        }
+        ret.push(required_outer);
+    }
+
+    ret
+}
+
+// check if all the restrictions on a base relation are extracted as quals
+// without parameters and are all evaluated remotely, as join and aggregate
+// scans neither evaluate parameters nor recheck quals locally
+//
+// qual expressions are not passed to join and aggregate scans, so a relation
+// with any of them, even remote ones, cannot be part of those scans
+unsafe fn all_quals_extracted<W: ForeignDataWrapper>(
+    rel: *mut pg_sys::RelOptInfo,
+    state: &FdwState<W>,
+) -> bool {
+    let conds = PgList::<pg_sys::RestrictInfo>::from_pg((*rel).baserestrictinfo);
+    conds.len() == state.quals.len()
+        && state.quals.iter().all(|qual| qual.param.is_none())
+        && state.remote_quals.len() == state.quals.len()
+        && state.remote_quals.iter().all(|remote| *remote)
+        && state.qual_exprs.is_empty()
+}
+
+// check if all the columns needed by the join are provided by its outer and
+// inner relations
+unsafe fn all_join_columns_provided(
+    joinrel: *mut pg_sys::RelOptInfo,
+    outerrel: *mut pg_sys::RelOptInfo,
+    join: &Join,
+) -> bool {
+    let vars = pg_sys::pull_var_clause(
+        (*(*joinrel).reltarget).exprs as _,
+        pg_sys::PVC_INCLUDE_PLACEHOLDERS.try_into().unwrap(),
+    );
+    let vars: PgList<pg_sys::Node> = PgList::from_pg(vars);
+    let provided = vars.iter_ptr().all(|node| {
+        // placeholder can only be evaluated locally
+        if !is_a(node, pg_sys::NodeTag_T_Var) {
+            return false;
+        }
+        let var = node as *mut pg_sys::Var;
+        let rel = if pg_sys::bms_is_member((*var).varno as c_int, (*outerrel).relids) {
+            &join.outer
+        } else {
+            &join.inner
+        };
+        (*var).varattno >= 1
+            && rel
+                .columns
+                .iter()
+                .any(|col| col.num == (*var).varattno as usize)
+    });
+    provided
+}
+
+#[pg_guard]
+pub(super) extern "C" fn get_foreign_join_paths<W: ForeignDataWrapper>(
+    root: *mut pg_sys::PlannerInfo,
+    joinrel: *mut pg_sys::RelOptInfo,
+    outerrel: *mut pg_sys::RelOptInfo,
+    innerrel: *mut pg_sys::RelOptInfo,
+    jointype: pg_sys::JoinType,
+    extra: *mut pg_sys::JoinPathExtraData,
+) {
+    debug2!("---> get_foreign_join_paths");
+    unsafe {
+        // skip if the join has been pushed down already
+        if !(*joinrel).fdw_private.is_null() {
+            return;
+        }
+
+        // only joins between two foreign tables can be pushed down
+        if (*outerrel).reloptkind != pg_sys::RelOptKind_RELOPT_BASEREL
+            || (*innerrel).reloptkind != pg_sys::RelOptKind_RELOPT_BASEREL
+            || (*outerrel).fdw_private.is_null()
+            || (*innerrel).fdw_private.is_null()
+        {
+            return;
+        }
+
+        // locked rows need EvalPlanQual recheck and lateral references need
+        // parameters, neither of them is supported
+        if !(*root).rowMarks.is_null() || !(*joinrel).lateral_relids.is_null() {
+            return;
+        }
+
+        let join_type = match jointype {
+            pg_sys::JoinType_JOIN_INNER => JoinType::Inner,
+            pg_sys::JoinType_JOIN_LEFT => JoinType::Left,
+            pg_sys::JoinType_JOIN_RIGHT => JoinType::Right,
+            pg_sys::JoinType_JOIN_FULL => JoinType::Full,
+            _ => return,
+        };
+
+        let mut outer_state = PgBox::<FdwState<W>>::from_pg((*outerrel).fdw_private as _);
+        let inner_state = PgBox::<FdwState<W>>::from_pg((*innerrel).fdw_private as _);
+
+        // restrictions on both relations must be applied before the join, so
+        // all of them must be pushed down
+        if !all_quals_extracted(outerrel, &outer_state)
+            || !all_quals_extracted(innerrel, &inner_state)
+        {
+            return;
+        }
+
+        // extract join conditions, conditions applied after an outer join
+        // are not supported
+        let mut quals = Vec::new();
+        let conds = PgList::<pg_sys::RestrictInfo>::from_pg((*extra).restrictlist);
+        for cond in conds.iter_ptr() {
+            if join_type != JoinType::Inner && (*cond).is_pushed_down {
+                return;
+            }
+            match extract_join_qual(root, outerrel, innerrel, (*cond).clause as _) {
+                Some(qual) => quals.push(qual),
+                None => return,
+            }
+        }
+
+        let join = Join {
+            join_type,
+            outer: JoinRel {
+                quals: outer_state.quals.clone(),
+                columns: outer_state.tgts.clone(),
+                options: outer_state.opts.clone(),
+            },
+            inner: JoinRel {
+                quals: inner_state.quals.clone(),
+                columns: inner_state.tgts.clone(),
+                options: inner_state.opts.clone(),
+            },
+            quals,
+        };
+
+        if !all_join_columns_provided(joinrel, outerrel, &join) {
+            return;
+        }
+
+        // ask the FDW if it can execute the join remotely
+        if !outer_state
+            .instance
+            .can_push_down_join(&join)
+            .report_unwrap()
+        {
+            return;
+        }
+
+        // the join scan is executed by a new instance created for its outer
+        // foreign table, and produces outer columns followed by inner columns
+        let outer_rte = pg_sys::planner_rt_fetch((*outerrel).relid, root);
+        let mut state = FdwState::<W>::new((*outer_rte).relid);
+        state.tgts = join
+            .outer
+            .columns
+            .iter()
+            .chain(join.inner.columns.iter())
+            .enumerate()
+            .map(|(i, col)| Column {
+                num: i + 1,
+                ..col.clone()
+            })
+            .collect();
+        state.opts = join.outer.options.clone();
+        state.scan_tlist = make_join_scan_tlist(root, &join, (*outerrel).relid, (*innerrel).relid);
+        state.join = Some(join);
+        state.cost = state.get_rel_cost((*outer_rte).relid).report_unwrap();
+
+        let (startup_cost, total_cost) = state.cost.path_costs((*joinrel).rows);
+
+        let path = pg_sys::create_foreign_join_path(
+            root,
+            joinrel,
+            ptr::null_mut(), // default pathtarget
+            (*joinrel).rows,
+            startup_cost,
+            total_cost,
+            ptr::null_mut(), // no pathkeys
+            ptr::null_mut(), // no required outer relids
+            ptr::null_mut(), // no EPQ recheck path
+            ptr::null_mut(), // no fdw_private data
+        );
+        // join scan state cannot be copied to parallel workers
+        (*path).path.parallel_safe = false;
+        pg_sys::add_path(joinrel, &mut ((*path).path));
+
+        (*joinrel).fdw_private =
+            PgBox::new_in_context(state, PgMemoryContexts::CurTransactionContext).into_pg() as _;
+    }
+}
+
+// make target list of a join scan, which is the outer columns followed by
+// the inner columns
+unsafe fn make_join_scan_tlist(
+    root: *mut pg_sys::PlannerInfo,
+    join: &Join,
+    outer_relid: pg_sys::Index,
+    inner_relid: pg_sys::Index,
+) -> *mut pg_sys::List {
+    let mut tlist = PgList::<pg_sys::TargetEntry>::new();
+    for (rel, relid) in [(&join.outer, outer_relid), (&join.inner, inner_relid)] {
+        let rte = pg_sys::planner_rt_fetch(relid, root);
+        for col in &rel.columns {
+            let attno = col.num as pg_sys::AttrNumber;
+            let mut type_oid = pg_sys::InvalidOid;
+            let mut typmod = -1;
+            let mut collid = pg_sys::InvalidOid;
+            pg_sys::get_atttypetypmodcoll(
+                (*rte).relid,
+                attno,
+                &mut type_oid,
+                &mut typmod,
+                &mut collid,
+            );
+            let var = pg_sys::makeVar(relid as c_int, attno, type_oid, typmod, collid, 0);
+            let tle = pg_sys::makeTargetEntry(
+                var as _,
+                (tlist.len() + 1) as pg_sys::AttrNumber,
+                ptr::null_mut(),
+                false,
+            );
+            tlist.push(tle);
+        }
+    }
+    tlist.into_pg()
+}
+
+#[pg_guard]
+pub(super) extern "C" fn get_foreign_upper_paths<W: ForeignDataWrapper>(
+    root: *mut pg_sys::PlannerInfo,
+    stage: pg_sys::UpperRelationKind,
+    input_rel: *mut pg_sys::RelOptInfo,
+    output_rel: *mut pg_sys::RelOptInfo,
+    extra: *mut ::std::os::raw::c_void,
+) {
+    debug2!("---> get_foreign_upper_paths");
+    unsafe {
+        // only aggregation on a foreign table can be pushed down
+        if stage != pg_sys::UpperRelationKind_UPPERREL_GROUP_AGG
+            || !(*output_rel).fdw_private.is_null()
+            || (*input_rel).reloptkind != pg_sys::RelOptKind_RELOPT_BASEREL
+            || (*input_rel).fdw_private.is_null()
+        {
+            return;
+        }
+
+        let mut input_state = PgBox::<FdwState<W>>::from_pg((*input_rel).fdw_private as _);
+
+        // restrictions must be applied before the aggregation, so all of
+        // them must be pushed down
+        if !all_quals_extracted(input_rel, &input_state) {
+            return;
+        }
+
+        let (mut aggregate, scan_tlist) = match extract_aggregate(root, input_rel, extra as _) {
+            Some(extracted) => extracted,
+            None => return,
+        };
+        aggregate.quals = input_state.quals.clone();
+        aggregate.options = input_state.opts.clone();
+
+        // ask the FDW if it can execute the aggregation remotely
+        if !input_state
+            .instance
+            .can_push_down_aggregate(&aggregate)
+            .report_unwrap()
+        {
+            return;
+        }
+
+        // estimate number of groups
+        let rows = if aggregate.group_by.is_empty() {
+            1.0
+        } else {
+            let tlist = PgList::<pg_sys::TargetEntry>::from_pg(scan_tlist);
+            let mut group_exprs = PgList::<pg_sys::Node>::new();
+            for tle in tlist.iter_ptr().take(aggregate.group_by.len()) {
+                group_exprs.push((*tle).expr as _);
+            }
+            pg_sys::estimate_num_groups(
+                root,
+                group_exprs.into_pg(),
+                (*input_rel).rows,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+
+        // the aggregate scan is executed by a new instance created for the
+        // foreign table, and produces grouping columns followed by aggregate
+        // function results
+        let rte = pg_sys::planner_rt_fetch((*input_rel).relid, root);
+        let mut state = FdwState::<W>::new((*rte).relid);
+        let func_cols = aggregate.funcs.iter().map(|func| Column {
+            name: Deparser::new(Dialect::Ansi).aggregate_func(func),
+            type_oid: func.type_oid,
+            ..Default::default()
+        });
+        state.tgts = aggregate
+            .group_by
+            .iter()
+            .cloned()
+            .chain(func_cols)
+            .enumerate()
+            .map(|(i, col)| Column { num: i + 1, ..col })
+            .collect();
+        state.opts = aggregate.options.clone();
+        state.scan_tlist = scan_tlist;
+        state.aggregate = Some(aggregate);
+        state.cost = state.get_rel_cost((*rte).relid).report_unwrap();
+
+        let (startup_cost, total_cost) = state.cost.path_costs(rows);
+
+        let path = pg_sys::create_foreign_upper_path(
+            root,
+            output_rel,
+            (*root).upper_targets[pg_sys::UpperRelationKind_UPPERREL_GROUP_AGG as usize],
+            rows,
+            startup_cost,
+            total_cost,
+            ptr::null_mut(), // no pathkeys
+            ptr::null_mut(), // no extra plan
+            ptr::null_mut(), // no fdw_private data
+        );
+        // aggregate scan state cannot be copied to parallel workers
+        (*path).path.parallel_safe = false;
+        pg_sys::add_path(output_rel, &mut ((*path).path));
+
+        (*output_rel).fdw_private =
+            PgBox::new_in_context(state, PgMemoryContexts::CurTransactionContext).into_pg() as _;
+    }
+}
+
+#[pg_guard]
+pub(super) extern "C" fn get_foreign_plan<W: ForeignDataWrapper>(
+    root: *mut pg_sys::PlannerInfo,
     baserel: *mut pg_sys::RelOptInfo,
-    _foreigntableid: pg_sys::Oid,
-    _best_path: *mut pg_sys::ForeignPath,
+    foreigntableid: pg_sys::Oid,
+    best_path: *mut pg_sys::ForeignPath,
     tlist: *mut pg_sys::List,
     scan_clauses: *mut pg_sys::List,
     outer_plan: *mut pg_sys::Plan,
 ) -> *mut pg_sys::ForeignScan {
     debug2!("---> get_foreign_plan");
     unsafe {
-        let state = PgBox::<FdwState<W>>::from_pg((*baserel).fdw_private as _);
+        let mut state = PgBox::<FdwState<W>>::from_pg((*baserel).fdw_private as _);
+
+        // if a sorted path is chosen, the FDW must return rows in its order
+        let pathkeys = (*best_path).path.pathkeys;
+        if !pathkeys.is_null() {
+            if let Some(sorts) = pathkeys_to_sorts(pathkeys, baserel, foreigntableid) {
+                state.sorts = sorts;
+            }
+        }
+
+        // each partition of a parallel scan returns only part of the rows,
+        // so sort and limit cannot be pushed down
+        if (*best_path).path.parallel_aware {
+            state.sorts.clear();
+            state.limit = None;
+        } else {
+            state.parallel_partitions = 0;
+        }
 
         // Plan and plan data (e.g. scan_clauses) must live for the entire duration of the query
         // As such, it must be allocated in the caller's memory context
 
+        // extract quals from the join clauses of a parameterized path, the
+        // join clauses are also in scan_clauses
+        let param_info = (*best_path).path.param_info;
+        if !param_info.is_null() {
+            let quals = extract_quals(
+                root,
+                baserel,
+                foreigntableid,
+                (*param_info).ppi_clauses,
+                &mut state.params,
+            );
+            for (qual, cond) in quals {
+                state.quals.push(qual);
+                state.qual_conds.push(cond);
+            }
+            state.remote_quals = state.can_push_down_quals().report_unwrap();
+        }
+
+        // parameter expressions are evaluated by executor, outer relation
+        // columns in them will be replaced with executor parameters
+        let mut fdw_exprs = PgList::<pg_sys::Node>::new();
+        for param in state.params.iter() {
+            fdw_exprs.push(*param);
+        }
+
+        // quals fully evaluated remotely don't need to be checked again
+        // locally, but still need to be rechecked by EvalPlanQual
+        let mut local_conds = PgList::<pg_sys::RestrictInfo>::new();
+        let mut remote_conds = PgList::<pg_sys::RestrictInfo>::new();
+        for cond in PgList::<pg_sys::RestrictInfo>::from_pg(scan_clauses).iter_ptr() {
+            let is_remote = state
+                .qual_conds
+                .iter()
+                .zip(state.remote_quals.iter())
+                .chain(
+                    state
+                        .qual_expr_conds
+                        .iter()
+                        .zip(state.remote_qual_exprs.iter()),
+                )
+                .any(|(qual_cond, remote)| *remote && *qual_cond == cond);
+            if is_remote {
+                remote_conds.push(cond);
+            } else {
+                local_conds.push(cond);
+            }
+        }
+
         // make foreign scan plan
-        let scan_clauses = pg_sys::extract_actual_clauses(scan_clauses, false);
+        let scan_clauses = pg_sys::extract_actual_clauses(local_conds.into_pg(), false);
+        let recheck_clauses = pg_sys::extract_actual_clauses(remote_conds.into_pg(), false);
 
-        let fdw_private = FdwState::serialize_to_list(state);
+        // join and aggregate scan has no scan relation, its scan tuple is
+        // described by fdw_scan_tlist instead
+        let (scan_relid, fdw_scan_tlist) = if state.scan_tlist.is_null() {
+            ((*baserel).relid, ptr::null_mut())
+        } else {
+            (0, state.scan_tlist)
+        };
+
+        // the plan of a parallel safe scan may be executed by parallel workers,
+        // which get the scan state encoded after the state pointer
+        let encoded = (*best_path).path.parallel_safe.then(|| state.encode());
+        let mut fdw_private = PgList::<pg_sys::Const>::from_pg(FdwState::serialize_to_list(state));
+        if let Some(buf) = encoded {
+            fdw_private.push(pg_sys::makeConst(
+                pg_sys::BYTEAOID,
+                -1,
+                pg_sys::InvalidOid,
+                -1,
+                buf.into_datum().unwrap(),
+                false,
+                false,
+            ));
+        }
+        let fdw_private = fdw_private.into_pg();
 
         pg_sys::make_foreignscan(
             tlist,
             scan_clauses,
-            (*baserel).relid,
-            ptr::null_mut(),
+            scan_relid,
+            fdw_exprs.into_pg(),
             fdw_private,
-            ptr::null_mut(),
-            ptr::null_mut(),
+            fdw_scan_tlist,
+            recheck_clauses,
             outer_plan,
         )
     }
@@ -246,10 +1395,46 @@ pub(super) extern "C" fn explain_foreign_scan<W: ForeignDataWrapper>(
 
         let label = PgMemoryContexts::CurrentMemoryContext.pstrdup("Wrappers");
 
-        let value =
-            PgMemoryContexts::CurrentMemoryContext.pstrdup(&format!("quals = {:?}", state.quals));
+        let (remote_quals, local_quals): (Vec<_>, Vec<_>) = state
+            .quals
+            .iter()
+            .zip(state.remote_quals.iter())
+            .partition(|(_, remote)| **remote);
+        let remote_quals: Vec<_> = remote_quals.into_iter().map(|(qual, _)| qual).collect();
+        let local_quals: Vec<_> = local_quals.into_iter().map(|(qual, _)| qual).collect();
+
+        let value = PgMemoryContexts::CurrentMemoryContext
+            .pstrdup(&format!("remote quals = {:?}", remote_quals));
+        pg_sys::ExplainPropertyText(label, value, es);
+
+        let value = PgMemoryContexts::CurrentMemoryContext
+            .pstrdup(&format!("local quals = {:?}", local_quals));
         pg_sys::ExplainPropertyText(label, value, es);
 
+        if !state.qual_exprs.is_empty() {
+            let (remote_exprs, local_exprs): (Vec<_>, Vec<_>) = state
+                .qual_exprs
+                .iter()
+                .zip(state.remote_qual_exprs.iter())
+                .partition(|(_, remote)| **remote);
+            let remote_exprs: Vec<_> = remote_exprs
+                .into_iter()
+                .map(|(expr, _)| expr.deparse())
+                .collect();
+            let local_exprs: Vec<_> = local_exprs
+                .into_iter()
+                .map(|(expr, _)| expr.deparse())
+                .collect();
+
+            let value = PgMemoryContexts::CurrentMemoryContext
+                .pstrdup(&format!("remote qual exprs = {:?}", remote_exprs));
+            pg_sys::ExplainPropertyText(label, value, es);
+
+            let value = PgMemoryContexts::CurrentMemoryContext
+                .pstrdup(&format!("local qual exprs = {:?}", local_exprs));
+            pg_sys::ExplainPropertyText(label, value, es);
+        }
+
         let value =
             PgMemoryContexts::CurrentMemoryContext.pstrdup(&format!("tgts = {:?}", state.tgts));
         pg_sys::ExplainPropertyText(label, value, es);
@@ -262,6 +1447,41 @@ pub(super) extern "C" fn explain_foreign_scan<W: ForeignDataWrapper>(
             PgMemoryContexts::CurrentMemoryContext.pstrdup(&format!("limit = {:?}", state.limit));
         pg_sys::ExplainPropertyText(label, value, es);
 
+        if let Some(join) = &state.join {
+            let value =
+                PgMemoryContexts::CurrentMemoryContext.pstrdup(&format!("join = {:?}", join));
+            pg_sys::ExplainPropertyText(label, value, es);
+        }
+
+        if let Some(aggregate) = &state.aggregate {
+            let value = PgMemoryContexts::CurrentMemoryContext
+                .pstrdup(&format!("aggregate = {:?}", aggregate));
+            pg_sys::ExplainPropertyText(label, value, es);
+        }
+
+        if state.parallel_partitions > 0 {
+            let value = PgMemoryContexts::CurrentMemoryContext.pstrdup(&format!(
+                "parallel partitions = {}",
+                state.parallel_partitions
+            ));
+            pg_sys::ExplainPropertyText(label, value, es);
+        }
+
+        if (*es).analyze && state.timing {
+            let value = PgMemoryContexts::CurrentMemoryContext.pstrdup(&format!(
+                "elapsed = {:.3} ms",
+                state.elapsed.as_secs_f64() * 1000.0
+            ));
+            pg_sys::ExplainPropertyText(label, value, es);
+        }
+
+        // properties defined by the FDW
+        for (label, value) in state.explain((*es).verbose).report_unwrap() {
+            let label = PgMemoryContexts::CurrentMemoryContext.pstrdup(&label);
+            let value = PgMemoryContexts::CurrentMemoryContext.pstrdup(&value);
+            pg_sys::ExplainPropertyText(label, value, es);
+        }
+
         old_ctx.set_as_current();
 
         (*node).fdw_state = state.into_pg() as _;
@@ -277,16 +1497,56 @@ pub(super) extern "C" fn begin_foreign_scan<W: ForeignDataWrapper>(
     unsafe {
         let scan_state = (*node).ss;
         let plan = scan_state.ps.plan as *mut pg_sys::ForeignScan;
-        let mut state = FdwState::<W>::deserialize_from_list((*plan).fdw_private as _);
+        let mut state = if pg_sys::ParallelWorkerNumber >= 0 {
+            // the state pointer is not valid in a parallel worker, so make a
+            // new state from the one encoded in plan
+            let relid = (*scan_state.ss_currentRelation).rd_id;
+            let mut state = PgBox::new(FdwState::<W>::new(relid));
+            let ftable = pg_sys::GetForeignTable(relid);
+            state.opts = utils::options_to_hashmap((*ftable).options);
+            let fdw_private = PgList::<pg_sys::Const>::from_pg((*plan).fdw_private);
+            let cst = fdw_private.get_ptr(1).unwrap();
+            let buf = <&[u8]>::from_datum((*cst).constvalue, (*cst).constisnull).unwrap();
+            state.decode(buf);
+            PgBox::<FdwState<W>>::from_pg(state.into_pg())
+        } else {
+            FdwState::<W>::deserialize_from_list((*plan).fdw_private as _)
+        };
 
-        // begin scan if it is not EXPLAIN statement
+        // initialize parameter expressions
+        let param_states = pg_sys::ExecInitExprList((*plan).fdw_exprs, node as _);
+        state.param_states = PgList::<pg_sys::ExprState>::from_pg(param_states)
+            .iter_ptr()
+            .collect();
+
+        // measure the time spent in the FDW for EXPLAIN ANALYZE
+        let instrument = scan_state.ps.instrument;
+        state.timing = !instrument.is_null() && (*instrument).need_timer;
+
+        // begin scan if it is not EXPLAIN statement, asynchronous scan is
+        // begun when the first tuple is requested and parallel scan is begun
+        // for each partition claimed
         if eflags & pg_sys::EXEC_FLAG_EXPLAIN_ONLY as c_int <= 0 {
-            state.begin_scan();
+            if scan_state.ps.async_capable {
+                state.async_scan = Some(AsyncScan::NotStarted);
+            } else if state.parallel_partitions > 0 {
+                state.empty_scan = !state.assign_params(node);
+            } else {
+                state.empty_scan = !state.assign_params(node);
+                if !state.empty_scan {
+                    state.begin_scan().report_unwrap();
+                }
+            }
 
-            let rel = scan_state.ss_currentRelation;
-            let tup_desc = (*rel).rd_att;
+            // use scan tuple descriptor as join and aggregate scan has no
+            // scan relation
+            let tup_desc = (*scan_state.ss_ScanTupleSlot).tts_tupleDescriptor;
             let natts = (*tup_desc).natts as usize;
 
+            // fetch rows in batches if the FDW supports it
+            state.batch = W::scan_batch_size(&state.opts)
+                .map(|batch_size| RowBatch::new(state.tgts.len(), batch_size));
+
             // initialize scan result lists
             state
                 .values
@@ -307,6 +1567,14 @@ pub(super) extern "C" fn iterate_foreign_scan<W: ForeignDataWrapper>(
     unsafe {
         let mut state = PgBox::<FdwState<W>>::from_pg((*node).fdw_state as _);
 
+        // wait for the asynchronous scan if it is not requested by Append
+        if state.async_scan.is_some() {
+            if matches!(state.async_scan, Some(AsyncScan::NotStarted)) {
+                state.begin_async_scan(node).report_unwrap();
+            }
+            state.wait_async_scan().report_unwrap();
+        }
+
         // clear slot
         let slot = (*node).ss.ss_ScanTupleSlot;
         polyfill::exec_clear_tuple(slot);
@@ -315,25 +1583,30 @@ pub(super) extern "C" fn iterate_foreign_scan<W: ForeignDataWrapper>(
         let mut old_ctx = state.tmp_ctx.set_as_current();
 
         state.row.clear();
-        if state.iter_scan().is_some() {
-            if state.row.cols.len() != state.tgts.len() {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_INVALID_COLUMN_NUMBER,
-                    "target column number not match",
-                );
-                old_ctx.set_as_current();
-                return slot;
-            }
+        if !state.empty_scan && state.iter_scan().report_unwrap().is_some() {
+            if state.batch.is_some() {
+                state.store_batch_row().report_unwrap();
+            } else {
+                if state.row.cols.len() != state.tgts.len() {
+                    report_error(
+                        PgSqlErrorCode::ERRCODE_FDW_INVALID_COLUMN_NUMBER,
+                        "target column number not match",
+                    );
+                    old_ctx.set_as_current();
+                    return slot;
+                }
 
-            for i in 0..state.row.cells.len() {
-                let att_idx = state.tgts[i].num - 1;
-                let cell = state.row.cells.get_unchecked_mut(i);
-                match cell.take() {
-                    Some(cell) => {
-                        state.values[att_idx] = cell.into_datum().unwrap();
-                        state.nulls[att_idx] = false;
+                for i in 0..state.row.cells.len() {
+                    let att_idx = state.tgts[i].num - 1;
+                    let cell = state.row.cells.get_unchecked_mut(i);
+                    match cell.take() {
+                        Some(cell) => {
+                            state.values[att_idx] =
+                                cell_into_datum(cell, &state.tgts[i]).report_unwrap();
+                            state.nulls[att_idx] = false;
+                        }
+                        None => state.nulls[att_idx] = true,
                     }
-                    None => state.nulls[att_idx] = true,
                 }
             }
 
@@ -357,7 +1630,39 @@ pub(super) extern "C" fn re_scan_foreign_scan<W: ForeignDataWrapper>(
         let fdw_state = (*node).fdw_state as *mut FdwState<W>;
         if !fdw_state.is_null() {
             let mut state = PgBox::<FdwState<W>>::from_pg(fdw_state);
-            state.re_scan();
+
+            // restart parallel scan from the first partition, the shared state
+            // is reset by the leader
+            if state.parallel_partitions > 0 {
+                if state.partition.take().is_some() {
+                    state.end_scan().report_unwrap();
+                }
+                state.next_partition = 0;
+                if !(*node).ss.ps.chgParam.is_null() && !state.param_states.is_empty() {
+                    state.empty_scan = !state.assign_params(node);
+                }
+                return;
+            }
+
+            // restart asynchronous scan from the beginning when it is requested again
+            if state.async_scan.is_some() {
+                if !matches!(state.async_scan, Some(AsyncScan::NotStarted)) {
+                    state.end_scan().report_unwrap();
+                    state.async_scan = Some(AsyncScan::NotStarted);
+                }
+                return;
+            }
+
+            // restart the scan with the new parameter values in quals
+            if !(*node).ss.ps.chgParam.is_null() && !state.param_states.is_empty() {
+                state.end_scan().report_unwrap();
+                state.empty_scan = !state.assign_params(node);
+                if !state.empty_scan {
+                    state.begin_scan().report_unwrap();
+                }
+            } else if !state.empty_scan {
+                state.re_scan().report_unwrap();
+            }
         }
     }
 }
@@ -371,8 +1676,156 @@ pub(super) extern "C" fn end_foreign_scan<W: ForeignDataWrapper>(
         let fdw_state = (*node).fdw_state as *mut FdwState<W>;
         if !fdw_state.is_null() {
             let mut state = PgBox::<FdwState<W>>::from_rust(fdw_state);
-            state.end_scan();
+            state.end_scan().report_unwrap();
             state.clear();
         }
     }
 }
+
+#[pg_guard]
+pub(super) extern "C" fn is_foreign_path_async_capable<W: ForeignDataWrapper>(
+    path: *mut pg_sys::ForeignPath,
+) -> bool {
+    debug2!("---> is_foreign_path_async_capable");
+    unsafe {
+        let rel = (*path).path.parent;
+        if (*rel).fdw_private.is_null() {
+            return false;
+        }
+        let mut state = PgBox::<FdwState<W>>::from_pg((*rel).fdw_private as _);
+        let state = &mut *state;
+
+        // pushed down join and aggregation are always synchronous
+        if state.join.is_some() || state.aggregate.is_some() {
+            return false;
+        }
+        state.instance.can_async_scan(&state.opts).report_unwrap()
+    }
+}
+
+// return the next tuple to Append if the scan result is ready, otherwise
+// ask Append to wait for the notification
+unsafe fn produce_async_tuple<W: ForeignDataWrapper>(
+    areq: *mut pg_sys::AsyncRequest,
+    state: &mut FdwState<W>,
+) {
+    if state.poll_async_scan().report_unwrap() {
+        let node = (*areq).requestee;
+        let slot = polyfill::exec_proc_node(node);
+        polyfill::exec_async_request_done(areq, slot);
+    } else {
+        polyfill::exec_async_request_pending(areq);
+    }
+}
+
+#[pg_guard]
+pub(super) extern "C" fn foreign_async_request<W: ForeignDataWrapper>(
+    areq: *mut pg_sys::AsyncRequest,
+) {
+    debug2!("---> foreign_async_request");
+    unsafe {
+        let node = (*areq).requestee as *mut pg_sys::ForeignScanState;
+        let mut state = PgBox::<FdwState<W>>::from_pg((*node).fdw_state as _);
+        if matches!(state.async_scan, Some(AsyncScan::NotStarted)) {
+            state.begin_async_scan(node).report_unwrap();
+        }
+        produce_async_tuple(areq, &mut state);
+    }
+}
+
+#[pg_guard]
+pub(super) extern "C" fn foreign_async_configure_wait<W: ForeignDataWrapper>(
+    areq: *mut pg_sys::AsyncRequest,
+) {
+    debug2!("---> foreign_async_configure_wait");
+    unsafe {
+        let node = (*areq).requestee as *mut pg_sys::ForeignScanState;
+        let state = PgBox::<FdwState<W>>::from_pg((*node).fdw_state as _);
+        if let Some(AsyncScan::Waiting(sock)) = &state.async_scan {
+            let requestor = (*areq).requestor as *mut pg_sys::AppendState;
+            pg_sys::AddWaitEventToSet(
+                (*requestor).as_eventset,
+                pg_sys::WL_SOCKET_READABLE,
+                sock.as_raw_fd(),
+                ptr::null_mut(),
+                areq as _,
+            );
+        }
+    }
+}
+
+#[pg_guard]
+pub(super) extern "C" fn foreign_async_notify<W: ForeignDataWrapper>(
+    areq: *mut pg_sys::AsyncRequest,
+) {
+    debug2!("---> foreign_async_notify");
+    unsafe {
+        let node = (*areq).requestee as *mut pg_sys::ForeignScanState;
+        let mut state = PgBox::<FdwState<W>>::from_pg((*node).fdw_state as _);
+        produce_async_tuple(areq, &mut state);
+    }
+}
+
+#[pg_guard]
+pub(super) extern "C" fn is_foreign_scan_parallel_safe<W: ForeignDataWrapper>(
+    _root: *mut pg_sys::PlannerInfo,
+    _rel: *mut pg_sys::RelOptInfo,
+    rte: *mut pg_sys::RangeTblEntry,
+) -> bool {
+    debug2!("---> is_foreign_scan_parallel_safe");
+    unsafe {
+        let ftable = pg_sys::GetForeignTable((*rte).relid);
+        let opts = utils::options_to_hashmap((*ftable).options);
+        W::is_parallel_safe(&opts)
+    }
+}
+
+#[pg_guard]
+pub(super) extern "C" fn estimate_dsm_foreign_scan<W: ForeignDataWrapper>(
+    _node: *mut pg_sys::ForeignScanState,
+    _pcxt: *mut pg_sys::ParallelContext,
+) -> pg_sys::Size {
+    debug2!("---> estimate_dsm_foreign_scan");
+    mem::size_of::<ParallelScanShared>()
+}
+
+#[pg_guard]
+pub(super) extern "C" fn initialize_dsm_foreign_scan<W: ForeignDataWrapper>(
+    node: *mut pg_sys::ForeignScanState,
+    _pcxt: *mut pg_sys::ParallelContext,
+    coordinate: *mut c_void,
+) {
+    debug2!("---> initialize_dsm_foreign_scan");
+    unsafe {
+        let shared = coordinate as *mut ParallelScanShared;
+        ptr::write(shared, ParallelScanShared::default());
+        let mut state = PgBox::<FdwState<W>>::from_pg((*node).fdw_state as _);
+        state.parallel_shared = shared;
+    }
+}
+
+#[pg_guard]
+pub(super) extern "C" fn re_initialize_dsm_foreign_scan<W: ForeignDataWrapper>(
+    _node: *mut pg_sys::ForeignScanState,
+    _pcxt: *mut pg_sys::ParallelContext,
+    coordinate: *mut c_void,
+) {
+    debug2!("---> re_initialize_dsm_foreign_scan");
+    unsafe {
+        let shared = coordinate as *mut ParallelScanShared;
+        (*shared).reset();
+    }
+}
+
+#[pg_guard]
+pub(super) extern "C" fn initialize_worker_foreign_scan<W: ForeignDataWrapper>(
+    node: *mut pg_sys::ForeignScanState,
+    _toc: *mut pg_sys::shm_toc,
+    coordinate: *mut c_void,
+) {
+    debug2!("---> initialize_worker_foreign_scan");
+    unsafe {
+        let mut state = PgBox::<FdwState<W>>::from_pg((*node).fdw_state as _);
+        state.parallel_shared = coordinate as _;
+    }
+}
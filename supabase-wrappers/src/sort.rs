@@ -22,55 +22,119 @@ pub(crate) unsafe fn create_sort(
     None
 }
 
+// convert a pathkey to sort on the base relation, return None if the pathkey
+// is not on a plain column of the base relation
+unsafe fn pathkey_to_sort(
+    pathkey: *mut pg_sys::PathKey,
+    baserel: *mut pg_sys::RelOptInfo,
+    baserel_id: pg_sys::Oid,
+) -> Option<Sort> {
+    let ec = (*pathkey).pk_eclass;
+
+    if (*ec).ec_has_volatile {
+        return None;
+    }
+
+    let ems: PgList<pg_sys::EquivalenceMember> = PgList::from_pg((*ec).ec_members);
+    let em = ems
+        .iter_ptr()
+        .find(|em| pg_sys::bms_equal((*(*em)).em_relids, (*baserel).relids))?;
+    let expr = (*em).em_expr as *mut pg_sys::Node;
+
+    if is_a(expr, pg_sys::NodeTag_T_Var) {
+        let var = expr as *mut pg_sys::Var;
+        create_sort(pathkey, var, baserel_id)
+    } else if is_a(expr, pg_sys::NodeTag_T_RelabelType) {
+        // ORDER BY clauses having a COLLATE option will be RelabelType
+        let expr = expr as *mut pg_sys::RelabelType;
+        let var = (*expr).arg as *mut pg_sys::Var;
+        if !is_a(var as *mut pg_sys::Node, pg_sys::NodeTag_T_Var) {
+            return None;
+        }
+        let mut sort = create_sort(pathkey, var, baserel_id)?;
+        let coll_id = (*expr).resultcollid;
+        sort.collate = Some(
+            CStr::from_ptr(pg_sys::get_collation_name(coll_id))
+                .to_str()
+                .unwrap()
+                .to_owned(),
+        );
+        Some(sort)
+    } else {
+        None
+    }
+}
+
 // extract sorts
 pub(crate) unsafe fn extract_sorts(
     root: *mut pg_sys::PlannerInfo,
     baserel: *mut pg_sys::RelOptInfo,
     baserel_id: pg_sys::Oid,
 ) -> Vec<Sort> {
-    let mut ret = Vec::new();
     let pathkeys: PgList<pg_sys::PathKey> = PgList::from_pg((*root).query_pathkeys);
+    pathkeys
+        .iter_ptr()
+        .filter_map(|pathkey| pathkey_to_sort(pathkey, baserel, baserel_id))
+        .collect()
+}
 
-    for pathkey in pathkeys.iter_ptr() {
-        let ec = (*pathkey).pk_eclass;
+// convert all pathkeys to sorts, return None if any of them cannot be converted
+pub(crate) unsafe fn pathkeys_to_sorts(
+    pathkeys: *mut pg_sys::List,
+    baserel: *mut pg_sys::RelOptInfo,
+    baserel_id: pg_sys::Oid,
+) -> Option<Vec<Sort>> {
+    let pathkeys: PgList<pg_sys::PathKey> = PgList::from_pg(pathkeys);
+    pathkeys
+        .iter_ptr()
+        .map(|pathkey| pathkey_to_sort(pathkey, baserel, baserel_id))
+        .collect()
+}
+
+// get the pathkeys worth to be pushed down for a base relation, which are the
+// query's ORDER BY and the single column merge join keys
+pub(crate) unsafe fn get_useful_pathkeys(
+    root: *mut pg_sys::PlannerInfo,
+    baserel: *mut pg_sys::RelOptInfo,
+) -> Vec<*mut pg_sys::List> {
+    let mut ret = Vec::new();
 
-        if (*ec).ec_has_volatile {
+    let query_pathkeys: PgList<pg_sys::PathKey> = PgList::from_pg((*root).query_pathkeys);
+    if !query_pathkeys.is_empty() {
+        ret.push((*root).query_pathkeys);
+    }
+
+    if !(*baserel).has_eclass_joins {
+        return ret;
+    }
+
+    let ecs: PgList<pg_sys::EquivalenceClass> = PgList::from_pg((*root).eq_classes);
+    for ec in ecs.iter_ptr() {
+        if (*ec).ec_has_volatile
+            || (*ec).ec_opfamilies.is_null()
+            || !pg_sys::eclass_useful_for_merging(root, ec, baserel)
+        {
             continue;
         }
 
-        let ems: PgList<pg_sys::EquivalenceMember> = PgList::from_pg((*ec).ec_members);
-        ems.iter_ptr()
-            .find(|em| pg_sys::bms_equal((*(*em)).em_relids, (*baserel).relids))
-            .and_then(|em| {
-                let expr = (*em).em_expr as *mut pg_sys::Node;
+        // use the first btree operator family, same as what merge join uses
+        let opfamily = (*(*(*ec).ec_opfamilies).elements).oid_value;
+        let pathkey = pg_sys::make_canonical_pathkey(
+            root,
+            ec,
+            opfamily,
+            pg_sys::BTLessStrategyNumber as _,
+            false,
+        );
 
-                if is_a(expr, pg_sys::NodeTag_T_Var) {
-                    let var = expr as *mut pg_sys::Var;
-                    let sort = create_sort(pathkey, var, baserel_id);
-                    if let Some(sort) = sort {
-                        ret.push(sort);
-                    }
-                } else if is_a(expr, pg_sys::NodeTag_T_RelabelType) {
-                    // ORDER BY clauses having a COLLATE option will be RelabelType
-                    let expr = expr as *mut pg_sys::RelabelType;
-                    let var = (*expr).arg as *mut pg_sys::Var;
-                    if is_a(var as *mut pg_sys::Node, pg_sys::NodeTag_T_Var) {
-                        let sort = create_sort(pathkey, var, baserel_id);
-                        if let Some(mut sort) = sort {
-                            let coll_id = (*expr).resultcollid;
-                            sort.collate = Some(
-                                CStr::from_ptr(pg_sys::get_collation_name(coll_id))
-                                    .to_str()
-                                    .unwrap()
-                                    .to_owned(),
-                            );
-                            ret.push(sort);
-                        }
-                    }
-                }
+        // canonical pathkeys can be compared by pointer
+        if query_pathkeys.len() == 1 && query_pathkeys.head() == Some(pathkey) {
+            continue;
+        }
 
-                None::<()>
-            });
+        let mut pathkeys = PgList::<pg_sys::PathKey>::new();
+        pathkeys.push(pathkey);
+        ret.push(pathkeys.into_pg());
     }
 
     ret
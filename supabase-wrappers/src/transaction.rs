@@ -0,0 +1,171 @@
+use pgx::{debug2, prelude::*, PgSqlErrorCode};
+use std::cell::{Cell, RefCell};
+use std::os::raw::c_void;
+use std::ptr;
+use std::rc::Rc;
+
+use crate::prelude::{FdwError, FdwResult, ForeignDataWrapper};
+use crate::utils::{report_warning, ReportUnwrap};
+
+// object-safe subset of the FDW transaction hooks, so instances of different
+// FDWs can be kept in the same list
+trait XactInstance {
+    fn on_pre_commit(&mut self) -> FdwResult<()>;
+    fn on_commit(&mut self) -> FdwResult<()>;
+    fn on_abort(&mut self) -> FdwResult<()>;
+    fn on_release_savepoint(&mut self) -> FdwResult<()>;
+    fn on_rollback_savepoint(&mut self) -> FdwResult<()>;
+}
+
+impl<W: ForeignDataWrapper> XactInstance for W {
+    fn on_pre_commit(&mut self) -> FdwResult<()> {
+        ForeignDataWrapper::on_pre_commit(self)
+    }
+
+    fn on_commit(&mut self) -> FdwResult<()> {
+        ForeignDataWrapper::on_commit(self)
+    }
+
+    fn on_abort(&mut self) -> FdwResult<()> {
+        ForeignDataWrapper::on_abort(self)
+    }
+
+    fn on_release_savepoint(&mut self) -> FdwResult<()> {
+        ForeignDataWrapper::on_release_savepoint(self)
+    }
+
+    fn on_rollback_savepoint(&mut self) -> FdwResult<()> {
+        ForeignDataWrapper::on_rollback_savepoint(self)
+    }
+}
+
+// FDW instance which modified foreign tables in the current transaction,
+// along with the subtransaction it did that in
+struct XactEntry {
+    subid: pg_sys::SubTransactionId,
+    instance: Rc<RefCell<dyn XactInstance>>,
+}
+
+thread_local! {
+    // true if the transaction callbacks are registered in this backend
+    static CALLBACKS_REGISTERED: Cell<bool> = const { Cell::new(false) };
+
+    // FDW instances to be notified when the current transaction ends
+    static XACT_ENTRIES: RefCell<Vec<XactEntry>> = const { RefCell::new(Vec::new()) };
+}
+
+// add a FDW instance to the current transaction, its transaction hooks will
+// be called when the transaction or current subtransaction ends
+pub(crate) unsafe fn register_instance<W: ForeignDataWrapper + 'static>(instance: &Rc<RefCell<W>>) {
+    if !CALLBACKS_REGISTERED.with(|r| r.replace(true)) {
+        pg_sys::RegisterXactCallback(Some(xact_callback), ptr::null_mut());
+        pg_sys::RegisterSubXactCallback(Some(sub_xact_callback), ptr::null_mut());
+    }
+
+    let instance: Rc<RefCell<dyn XactInstance>> = instance.clone();
+    let subid = pg_sys::GetCurrentSubTransactionId();
+    XACT_ENTRIES.with(|entries| {
+        let mut entries = entries.borrow_mut();
+
+        // the same modify state can be executed more than once in a transaction
+        if !entries
+            .iter()
+            .any(|entry| Rc::ptr_eq(&entry.instance, &instance))
+        {
+            entries.push(XactEntry { subid, instance });
+        }
+    });
+}
+
+// call a hook on each instance, errors are reported as warnings because the
+// transaction cannot be aborted anymore
+fn call_and_warn(entries: &[XactEntry], hook: impl Fn(&mut dyn XactInstance) -> FdwResult<()>) {
+    for entry in entries {
+        if let Err(err) = hook(&mut *entry.instance.borrow_mut()) {
+            report_warning(&err.message);
+        }
+    }
+}
+
+// get instances registered in the specified subtransaction, or all the
+// instances if it is not specified
+fn instances_in(subid: Option<pg_sys::SubTransactionId>) -> Vec<Rc<RefCell<dyn XactInstance>>> {
+    XACT_ENTRIES.with(|entries| {
+        entries
+            .borrow()
+            .iter()
+            .filter(|entry| subid.is_none() || subid == Some(entry.subid))
+            .map(|entry| entry.instance.clone())
+            .collect()
+    })
+}
+
+#[pg_guard]
+extern "C" fn xact_callback(event: pg_sys::XactEvent, _arg: *mut c_void) {
+    debug2!("---> xact_callback");
+    match event {
+        pg_sys::XactEvent_XACT_EVENT_PRE_COMMIT => {
+            // the instances are kept, so they are still aborted if any of
+            // them failed
+            for instance in instances_in(None) {
+                instance.borrow_mut().on_pre_commit().report_unwrap();
+            }
+        }
+        pg_sys::XactEvent_XACT_EVENT_PRE_PREPARE => {
+            let has_entries = XACT_ENTRIES.with(|entries| !entries.borrow().is_empty());
+            if has_entries {
+                FdwError::new(
+                    PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+                    "cannot PREPARE a transaction that has modified foreign tables",
+                )
+                .report();
+            }
+        }
+        pg_sys::XactEvent_XACT_EVENT_COMMIT => {
+            let entries = XACT_ENTRIES.with(|entries| entries.replace(Vec::new()));
+            call_and_warn(&entries, |instance| instance.on_commit());
+        }
+        pg_sys::XactEvent_XACT_EVENT_ABORT => {
+            let entries = XACT_ENTRIES.with(|entries| entries.replace(Vec::new()));
+            call_and_warn(&entries, |instance| instance.on_abort());
+        }
+        _ => {}
+    }
+}
+
+#[pg_guard]
+extern "C" fn sub_xact_callback(
+    event: pg_sys::SubXactEvent,
+    my_subid: pg_sys::SubTransactionId,
+    parent_subid: pg_sys::SubTransactionId,
+    _arg: *mut c_void,
+) {
+    debug2!("---> sub_xact_callback");
+    match event {
+        pg_sys::SubXactEvent_SUBXACT_EVENT_PRE_COMMIT_SUB => {
+            for instance in instances_in(Some(my_subid)) {
+                instance.borrow_mut().on_release_savepoint().report_unwrap();
+            }
+        }
+        pg_sys::SubXactEvent_SUBXACT_EVENT_COMMIT_SUB => {
+            // instances in the released savepoint now belong to its parent
+            XACT_ENTRIES.with(|entries| {
+                for entry in entries.borrow_mut().iter_mut() {
+                    if entry.subid == my_subid {
+                        entry.subid = parent_subid;
+                    }
+                }
+            });
+        }
+        pg_sys::SubXactEvent_SUBXACT_EVENT_ABORT_SUB => {
+            let entries = XACT_ENTRIES.with(|entries| {
+                let mut entries = entries.borrow_mut();
+                let (aborted, kept) = entries.drain(..).partition(|entry| entry.subid == my_subid);
+                *entries = kept;
+                aborted
+            });
+            call_and_warn(&entries, |instance| instance.on_rollback_savepoint());
+        }
+        _ => {}
+    }
+}
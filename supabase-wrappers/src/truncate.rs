@@ -0,0 +1,32 @@
+use pgx::{debug2, prelude::*, PgList};
+
+use crate::instance;
+use crate::prelude::ForeignDataWrapper;
+use crate::utils::{self, ReportUnwrap};
+
+#[pg_guard]
+pub(super) extern "C" fn exec_foreign_truncate<W: ForeignDataWrapper>(
+    rels: *mut pg_sys::List,
+    behavior: pg_sys::DropBehavior,
+    restart_seqs: bool,
+) {
+    debug2!("---> exec_foreign_truncate");
+    unsafe {
+        let cascade = behavior == pg_sys::DropBehavior_DROP_CASCADE;
+
+        // all the foreign tables are on the same foreign server, so they are
+        // truncated by one FDW instance
+        let rels = PgList::<pg_sys::RelationData>::from_pg(rels);
+        let mut instance: Option<W> = None;
+        for rel in rels.iter_ptr() {
+            let ftable = pg_sys::GetForeignTable((*rel).rd_id);
+            let opts = utils::options_to_hashmap((*ftable).options);
+            let instance = instance.get_or_insert_with(|| {
+                instance::create_fdw_instance_from_server_id((*ftable).serverid)
+            });
+            instance
+                .truncate(&opts, restart_seqs, cascade)
+                .report_unwrap();
+        }
+    }
+}
@@ -1,13 +1,13 @@
 //! Helper functions for working with Wrappers
 //!
 
-use crate::interface::{Cell, Column, Row};
+use crate::interface::{Cell, Column, FdwError, FdwResult, Row};
 use pgx::prelude::PgBuiltInOids;
 use pgx::spi::Spi;
 use pgx::IntoDatum;
 use pgx::*;
 use std::collections::HashMap;
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 use std::num::NonZeroUsize;
 use std::ptr;
 use tokio::runtime::{Builder, Runtime};
@@ -126,21 +126,20 @@ pub fn create_async_runtime() -> Runtime {
 
 /// Get required option value from the `options` map
 ///
-/// Get the required option's value from `options` map, return None and report
-/// error and stop current transaction if it does not exist.
+/// Get the required option's value from `options` map, return an [`FdwError`]
+/// if it does not exist.
 ///
 /// For example,
 ///
 /// ```rust,no_run
-/// require_option("my_option", options);
+/// let my_option = require_option("my_option", options)?;
 /// ```
-pub fn require_option(opt_name: &str, options: &HashMap<String, String>) -> Option<String> {
-    options.get(opt_name).map(|t| t.to_owned()).or_else(|| {
-        report_error(
+pub fn require_option(opt_name: &str, options: &HashMap<String, String>) -> FdwResult<String> {
+    options.get(opt_name).map(|t| t.to_owned()).ok_or_else(|| {
+        FdwError::new(
             PgSqlErrorCode::ERRCODE_FDW_OPTION_NAME_NOT_FOUND,
-            &format!("required option \"{}\" is not specified", opt_name),
-        );
-        None
+            format!("required option \"{}\" is not specified", opt_name),
+        )
     })
 }
 
@@ -168,25 +167,53 @@ pub fn require_option_or(
 ///
 /// Get decrypted secret as string from Vault. Vault is an extension for storing
 /// encrypted secrets, [see more details](https://github.com/supabase/vault).
-pub fn get_vault_secret(secret_id: &str) -> Option<String> {
-    match Uuid::try_parse(secret_id) {
-        Ok(sid) => {
-            let sid = sid.into_bytes();
-            Spi::get_one_with_args::<String>(
-                "select decrypted_secret from vault.decrypted_secrets where key_id = $1",
-                vec![(
-                    PgBuiltInOids::UUIDOID.oid(),
-                    pgx::Uuid::from_bytes(sid).into_datum(),
-                )],
-            )
-        }
-        Err(err) => {
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                &format!("invalid secret id \"{}\": {}", secret_id, err),
-            );
-            None
-        }
+///
+/// Return an [`FdwError`] if the secret id is invalid or the secret is not found.
+pub fn get_vault_secret(secret_id: &str) -> FdwResult<String> {
+    let sid = Uuid::try_parse(secret_id)
+        .map_err(|err| format!("invalid secret id \"{}\": {}", secret_id, err))?
+        .into_bytes();
+    Spi::get_one_with_args::<String>(
+        "select decrypted_secret from vault.decrypted_secrets where key_id = $1",
+        vec![(
+            PgBuiltInOids::UUIDOID.oid(),
+            pgx::Uuid::from_bytes(sid).into_datum(),
+        )],
+    )
+    .ok_or_else(|| format!("secret \"{}\" is not found", secret_id).into())
+}
+
+/// Quote an identifier for use in Postgres SQL
+///
+/// Quotes are added only if necessary, for example the generated `CREATE
+/// FOREIGN TABLE` commands in [import_foreign_schema](crate::interface::ForeignDataWrapper::import_foreign_schema).
+///
+/// ```rust,no_run
+/// assert_eq!(quote_identifier("my_table"), "my_table");
+/// assert_eq!(quote_identifier("My Table"), "\"My Table\"");
+/// ```
+pub fn quote_identifier(ident: &str) -> String {
+    let ident = CString::new(ident).unwrap();
+    unsafe {
+        CStr::from_ptr(pg_sys::quote_identifier(ident.as_ptr()))
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+}
+
+/// Quote a string literal for use in Postgres SQL
+///
+/// ```rust,no_run
+/// assert_eq!(quote_literal("it's"), "'it''s'");
+/// ```
+pub fn quote_literal(s: &str) -> String {
+    let s = CString::new(s).unwrap();
+    unsafe {
+        CStr::from_ptr(pg_sys::quote_literal_cstr(s.as_ptr()))
+            .to_str()
+            .unwrap()
+            .to_owned()
     }
 }
 
@@ -274,6 +301,7 @@ pub(super) unsafe fn extract_target_columns(
                 name: CStr::from_ptr(attname).to_str().unwrap().to_owned(),
                 num: attno as usize,
                 type_oid,
+                typmod: (*var).vartypmod,
             });
         }
     }
@@ -282,7 +310,7 @@ pub(super) unsafe fn extract_target_columns(
 }
 
 /// Check if the option list contains a specific option, used in [validator](crate::interface::ForeignDataWrapper::validator)
-pub fn check_options_contain(opt_list: &[Option<String>], tgt: &str) {
+pub fn check_options_contain(opt_list: &[Option<String>], tgt: &str) -> FdwResult<()> {
     let search_key = tgt.to_owned() + "=";
     if !opt_list.iter().any(|opt| {
         if let Some(s) = opt {
@@ -291,10 +319,26 @@ pub fn check_options_contain(opt_list: &[Option<String>], tgt: &str) {
             false
         }
     }) {
-        report_error(
+        return Err(FdwError::new(
             PgSqlErrorCode::ERRCODE_FDW_OPTION_NAME_NOT_FOUND,
-            &format!("required option \"{}\" is not specified", tgt),
-        );
+            format!("required option \"{}\" is not specified", tgt),
+        ));
+    }
+    Ok(())
+}
+
+// trait for reporting a FdwError returned by FDW callbacks to Postgres
+pub(super) trait ReportUnwrap<T> {
+    fn report_unwrap(self) -> T;
+}
+
+impl<T> ReportUnwrap<T> for FdwResult<T> {
+    #[inline]
+    fn report_unwrap(self) -> T {
+        match self {
+            Ok(value) => value,
+            Err(err) => err.report(),
+        }
     }
 }
 
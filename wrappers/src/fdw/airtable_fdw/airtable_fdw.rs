@@ -1,5 +1,4 @@
 use pgx::pg_sys;
-use pgx::prelude::PgSqlErrorCode;
 use reqwest::{self, header};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
@@ -18,8 +17,9 @@ use super::result::AirtableResponse;
 pub(crate) struct AirtableFdw {
     rt: Runtime,
     base_url: String,
-    client: Option<ClientWithMiddleware>,
+    client: ClientWithMiddleware,
     scan_result: Option<Vec<Row>>,
+    requests: usize,
 }
 
 impl AirtableFdw {
@@ -59,18 +59,9 @@ impl AirtableFdw {
     }
 }
 
-macro_rules! report_fetch_error {
-    ($url:ident, $err:ident) => {
-        report_error(
-            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-            &format!("fetch {} failed: {}", $url, $err),
-        )
-    };
-}
-
 // TODO Add support for INSERT, UPDATE, DELETE
 impl ForeignDataWrapper for AirtableFdw {
-    fn new(options: &HashMap<String, String>) -> Self {
+    fn new(options: &HashMap<String, String>) -> FdwResult<Self> {
         let base_url = options
             .get("api_url")
             .map(|t| t.to_owned())
@@ -78,28 +69,37 @@ impl ForeignDataWrapper for AirtableFdw {
             .trim_end_matches('/')
             .to_owned();
 
-        let client = require_option("api_key", options).map(|api_key| {
-            let mut headers = header::HeaderMap::new();
-            let value = format!("Bearer {}", api_key);
-            let mut auth_value = header::HeaderValue::from_str(&value).unwrap();
-            auth_value.set_sensitive(true);
-            headers.insert(header::AUTHORIZATION, auth_value);
-            let client = reqwest::Client::builder()
-                .default_headers(headers)
-                .build()
-                .unwrap();
-            let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
-            ClientBuilder::new(client)
-                .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-                .build()
-        });
-
-        Self {
+        let api_key = require_option("api_key", options)?;
+        let mut headers = header::HeaderMap::new();
+        let value = format!("Bearer {}", api_key);
+        let mut auth_value = header::HeaderValue::from_str(&value).unwrap();
+        auth_value.set_sensitive(true);
+        headers.insert(header::AUTHORIZATION, auth_value);
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap();
+        let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
+        let client = ClientBuilder::new(client)
+            .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+            .build();
+
+        Ok(Self {
             rt: create_async_runtime(),
             base_url,
             client,
             scan_result: None,
-        }
+            requests: 0,
+        })
+    }
+
+    fn get_rel_cost(&mut self, _options: &HashMap<String, String>) -> FdwResult<ScanCost> {
+        // each API call is a HTTP request returning one page of 100 records
+        Ok(ScanCost {
+            request_cost: 100.0,
+            request_size: 100,
+            ..Default::default()
+        })
     }
 
     fn begin_scan(
@@ -109,87 +109,94 @@ impl ForeignDataWrapper for AirtableFdw {
         _sorts: &[Sort],        // TODO: Propagate sort
         _limit: &Option<Limit>, // TODO: maxRecords
         options: &HashMap<String, String>,
-    ) {
+    ) -> FdwResult<()> {
         // TODO: Support specifying other options (view)
-        let url = if let Some(url) = require_option("base_id", options).and_then(|base_id| {
-            require_option("table", options).map(|table| self.build_url(&base_id, &table))
-        }) {
-            url
-        } else {
-            return;
-        };
+        let base_id = require_option("base_id", options)?;
+        let table = require_option("table", options)?;
+        let url = self.build_url(&base_id, &table);
 
         let mut rows = Vec::new();
-        if let Some(client) = &self.client {
-            let mut offset: Option<String> = None;
-
-            loop {
-                // Fetch all of the rows upfront. Arguably, this could be done in batches (and invoked each
-                // time iter_scan() runs out of rows) to pipeline the I/O, but we'd have to manage more
-                // state so starting with the simpler solution.
-                let url = match self.set_limit_offset(&url, None, offset.as_deref()) {
-                    Ok(url) => url,
-                    Err(err) => {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!("internal error: {}", err),
-                        );
-                        return;
-                    }
-                };
-
-                match self.rt.block_on(client.get(&url).send()) {
-                    Ok(resp) => match resp.error_for_status() {
-                        Ok(resp) => {
-                            let body = self.rt.block_on(resp.text()).unwrap();
-                            let (new_rows, new_offset) = self.parse_resp(&body, columns);
-                            rows.extend(new_rows.into_iter());
-
-                            if let Some(new_offset) = new_offset {
-                                offset = Some(new_offset);
-                            } else {
-                                break;
-                            }
-                        }
-                        Err(err) => report_fetch_error!(url, err),
-                    },
-                    Err(err) => report_fetch_error!(url, err),
-                }
+        let mut offset: Option<String> = None;
+
+        loop {
+            // Fetch all of the rows upfront. Arguably, this could be done in batches (and invoked each
+            // time iter_scan() runs out of rows) to pipeline the I/O, but we'd have to manage more
+            // state so starting with the simpler solution.
+            let url = self
+                .set_limit_offset(&url, None, offset.as_deref())
+                .map_err(|err| format!("internal error: {}", err))?;
+
+            self.requests += 1;
+            let body = self
+                .rt
+                .block_on(self.client.get(&url).send())
+                .and_then(|resp| resp.error_for_status().map_err(From::from))
+                .and_then(|resp| self.rt.block_on(resp.text()).map_err(From::from))
+                .map_err(|err| format!("fetch {} failed: {}", url, err))?;
+            let (new_rows, new_offset) = self.parse_resp(&body, columns);
+            rows.extend(new_rows);
+
+            if let Some(new_offset) = new_offset {
+                offset = Some(new_offset);
+            } else {
+                break;
             }
         }
 
         self.scan_result = Some(rows);
+
+        Ok(())
     }
 
-    fn iter_scan(&mut self, row: &mut Row) -> Option<()> {
+    fn iter_scan(&mut self, row: &mut Row) -> FdwResult<Option<()>> {
         if let Some(ref mut result) = self.scan_result {
             if !result.is_empty() {
-                return result
+                return Ok(result
                     .drain(0..1)
                     .last()
-                    .map(|src_row| row.replace_with(src_row));
+                    .map(|src_row| row.replace_with(src_row)));
             }
         }
-        None
+        Ok(None)
     }
 
-    fn end_scan(&mut self) {
+    fn end_scan(&mut self) -> FdwResult<()> {
         self.scan_result.take();
+        Ok(())
     }
 
-    fn validator(options: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) {
+    fn explain_scan(
+        &self,
+        _quals: &[Qual],
+        _columns: &[Column],
+        _sorts: &[Sort],
+        _limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+        _verbose: bool,
+    ) -> FdwResult<Vec<(String, String)>> {
+        let base_id = require_option("base_id", options)?;
+        let table = require_option("table", options)?;
+        let mut ret = vec![("Remote URL".to_string(), self.build_url(&base_id, &table))];
+        if self.requests > 0 {
+            ret.push(("Remote Requests".to_string(), self.requests.to_string()));
+        }
+        Ok(ret)
+    }
+
+    fn validator(options: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) -> FdwResult<()> {
         if let Some(oid) = catalog {
             match oid {
-                FOREIGN_DATA_WRAPPER_RELATION_ID => {}
-                FOREIGN_SERVER_RELATION_ID => {
-                    check_options_contain(&options, "api_key");
-                }
+                // api_key can be defined in either foreign server or user mapping
+                FOREIGN_DATA_WRAPPER_RELATION_ID
+                | FOREIGN_SERVER_RELATION_ID
+                | USER_MAPPING_RELATION_ID => {}
                 FOREIGN_TABLE_RELATION_ID => {
-                    check_options_contain(&options, "base_id");
-                    check_options_contain(&options, "table");
+                    check_options_contain(&options, "base_id")?;
+                    check_options_contain(&options, "table")?;
                 }
                 _ => {}
             }
         }
+        Ok(())
     }
 }
@@ -7,83 +7,243 @@ use gcp_bigquery_client::{
         table_data_insert_all_request::TableDataInsertAllRequest,
         table_field_schema::TableFieldSchema,
     },
+    table::ListOptions,
     Client,
 };
 use pgx::prelude::PgSqlErrorCode;
 use pgx::prelude::{AnyNumeric, Date, Timestamp};
-use serde_json::json;
+use serde_json::{json, Value as JsonValue};
 use std::collections::HashMap;
+use std::rc::Rc;
 use time::{format_description::well_known::Iso8601, OffsetDateTime, PrimitiveDateTime};
 
 use supabase_wrappers::prelude::*;
 
+const DEPARSER: Deparser = Deparser::new(Dialect::BigQuery);
+
+use OptionCatalog::*;
+
+const PROJECT_ID: OptionDef = OptionDef::new("project_id", &[Server])
+    .required()
+    .description("GCP project ID");
+const DATASET_ID: OptionDef = OptionDef::new("dataset_id", &[Server])
+    .required()
+    .description("BigQuery dataset ID");
+const SA_KEY: OptionDef = OptionDef::new("sa_key", &[Server, UserMapping])
+    .secret()
+    .description("GCP service account key JSON");
+const SA_KEY_ID: OptionDef = OptionDef::new("sa_key_id", &[Server, UserMapping])
+    .description("Vault secret key ID of the service account key, used if sa_key is not set");
+const MOCK_AUTH: OptionDef = OptionDef::new("mock_auth", &[Server])
+    .kind(OptionType::Bool)
+    .default("false")
+    .description("use mocked authentication, for testing only");
+const API_ENDPOINT: OptionDef = OptionDef::new("api_endpoint", &[Server])
+    .default("https://bigquery.googleapis.com/bigquery/v2")
+    .description("BigQuery API endpoint");
+const TABLE: OptionDef = OptionDef::new("table", &[Table])
+    .required()
+    .description("source table or view name, or a subquery enclosed in parentheses");
+const LOCATION: OptionDef = OptionDef::new("location", &[Table])
+    .default("US")
+    .description("source table location");
+const TIMEOUT: OptionDef = OptionDef::new("timeout", &[Table])
+    .kind(OptionType::Int)
+    .default("30000")
+    .description("query request timeout in milliseconds");
+
 macro_rules! field_type_error {
     ($field:ident, $err:ident) => {{
-        report_error(
+        FdwError::new(
             PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
-            &format!("get field {} failed: {}", &$field.name, $err),
-        );
-        None
+            format!("get field {} failed: {}", &$field.name, $err),
+        )
     }};
 }
 
 // convert BigQuery field to Cell
-fn field_to_cell(rs: &ResultSet, field: &TableFieldSchema) -> Option<Cell> {
-    match field.r#type {
+fn field_to_cell(rs: &ResultSet, field: &TableFieldSchema) -> FdwResult<Option<Cell>> {
+    let cell = match field.r#type {
         FieldType::Boolean => rs
             .get_bool_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(Cell::Bool),
         FieldType::Int64 | FieldType::Integer => rs
             .get_i64_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(Cell::I64),
         FieldType::Float64 | FieldType::Float => rs
             .get_f64_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(Cell::F64),
         FieldType::Numeric => rs
             .get_f64_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
-            .map(|v| Cell::Numeric(AnyNumeric::try_from(v).unwrap())),
+            .map_err(|err| field_type_error!(field, err))?
+            .map(|v| AnyNumeric::try_from(v).map(Cell::Numeric))
+            .transpose()
+            .map_err(|err| field_type_error!(field, err))?,
         FieldType::String => rs
             .get_string_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(Cell::String),
         FieldType::Date => rs
             .get_string_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(|v| {
-                let pg_epoch = time::Date::parse("2000-01-01", &Iso8601::DEFAULT).unwrap();
-                let dt = time::Date::parse(&v, &Iso8601::DEFAULT).unwrap();
-                let days = (dt - pg_epoch).whole_days();
-                let dt = Date::from_pg_epoch_days(days.try_into().unwrap());
-                Cell::Date(dt)
-            }),
+                let pg_epoch = time::Date::parse("2000-01-01", &Iso8601::DEFAULT)?;
+                let dt = time::Date::parse(&v, &Iso8601::DEFAULT)?;
+                let days = (dt - pg_epoch).whole_days() as i32;
+                Ok::<_, time::error::Parse>(Cell::Date(Date::from_pg_epoch_days(days)))
+            })
+            .transpose()
+            .map_err(|err| field_type_error!(field, err))?,
         FieldType::Datetime => rs
             .get_string_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(|v| {
-                let dt = PrimitiveDateTime::parse(&v, &Iso8601::DEFAULT).unwrap();
-                let ts = Timestamp::try_from(dt).unwrap();
-                Cell::Timestamp(ts)
-            }),
+                let dt = PrimitiveDateTime::parse(&v, &Iso8601::DEFAULT)
+                    .map_err(|err| err.to_string())?;
+                Timestamp::try_from(dt)
+                    .map(Cell::Timestamp)
+                    .map_err(|err| err.to_string())
+            })
+            .transpose()
+            .map_err(|err| field_type_error!(field, err))?,
         FieldType::Timestamp => rs
             .get_f64_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(|v| {
-                let dt = OffsetDateTime::from_unix_timestamp_nanos((v * 1e9) as i128).unwrap();
-                let ts = Timestamp::try_from(dt).unwrap();
-                Cell::Timestamp(ts)
-            }),
+                let dt = OffsetDateTime::from_unix_timestamp_nanos((v * 1e9) as i128)
+                    .map_err(|err| err.to_string())?;
+                Timestamp::try_from(dt)
+                    .map(Cell::Timestamp)
+                    .map_err(|err| err.to_string())
+            })
+            .transpose()
+            .map_err(|err| field_type_error!(field, err))?,
         _ => {
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                &format!("field type {:?} not supported", field.r#type),
-            );
-            None
+            return Err(FdwError::new(
+                PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+                format!("field type {:?} not supported", field.r#type),
+            ));
+        }
+    };
+    Ok(cell)
+}
+
+// convert a row to JSON object used by BigQuery insert request
+fn row_to_json(src: &Row) -> FdwResult<JsonValue> {
+    let mut row_json = json!({});
+
+    for (col_name, cell) in src.iter() {
+        if let Some(cell) = cell {
+            match cell {
+                Cell::Bool(v) => row_json[col_name] = json!(v),
+                Cell::I8(v) => row_json[col_name] = json!(v),
+                Cell::I16(v) => row_json[col_name] = json!(v),
+                Cell::I32(v) => row_json[col_name] = json!(v),
+                Cell::I64(v) => row_json[col_name] = json!(v),
+                Cell::F32(v) => row_json[col_name] = json!(v),
+                Cell::F64(v) => row_json[col_name] = json!(v),
+                Cell::Numeric(v) => row_json[col_name] = json!(v),
+                Cell::String(v) => row_json[col_name] = json!(v),
+                Cell::Date(v) => row_json[col_name] = json!(v),
+                Cell::Timestamp(v) => row_json[col_name] = json!(v),
+                Cell::Json(v) => row_json[col_name] = json!(v),
+                Cell::Uuid(v) => row_json[col_name] = json!(v.to_string()),
+                Cell::Time(v) => row_json[col_name] = json!(v),
+                Cell::TimestampTz(v) => row_json[col_name] = json!(v),
+                Cell::Inet(v) => row_json[col_name] = json!(v.0),
+                _ => {
+                    return Err(FdwError::new(
+                        PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+                        format!("field type {:?} not supported", cell),
+                    ))
+                }
+            }
         }
     }
+
+    Ok(row_json)
+}
+
+// map BigQuery field type to Postgres data type, only the types supported
+// by field_to_cell() are mapped
+fn field_type_to_pg_type(field_type: &FieldType) -> Option<&'static str> {
+    let pg_type = match field_type {
+        FieldType::Boolean => "boolean",
+        FieldType::Int64 | FieldType::Integer => "bigint",
+        FieldType::Float64 | FieldType::Float => "double precision",
+        FieldType::Numeric => "numeric",
+        FieldType::String => "text",
+        FieldType::Date => "date",
+        FieldType::Datetime | FieldType::Timestamp => "timestamp",
+        _ => return None,
+    };
+    Some(pg_type)
+}
+
+// connection to BigQuery, which is cached and shared by the FDW instances
+// in the same backend
+struct Connection {
+    rt: Runtime,
+    client: Client,
+
+    // keep the mock auth server alive as long as the client uses it
+    #[allow(dead_code)]
+    auth_mock: Option<GoogleAuthMock>,
+}
+
+fn create_connection(options: &HashMap<String, String>) -> FdwResult<Connection> {
+    let rt = create_async_runtime();
+
+    // Is authentication mocked
+    let mock_auth: bool = MOCK_AUTH.require(options)?;
+
+    let api_endpoint: String = API_ENDPOINT.require(options)?;
+
+    let mut auth_mock = None;
+    let (auth_endpoint, sa_key_json) = match mock_auth {
+        true => {
+            // Key file is not required if we're mocking auth
+            let mock = executor::block_on(GoogleAuthMock::start());
+            executor::block_on(mock.mock_token(1));
+            let auth_mock_uri = mock.uri();
+            let dummy_auth_config = dummy_configuration(&auth_mock_uri);
+            auth_mock = Some(mock);
+            let sa_key_json = serde_json::to_string_pretty(&dummy_auth_config).unwrap();
+            (auth_mock_uri, sa_key_json)
+        }
+        false => {
+            let uri = "https://www.googleapis.com/auth/bigquery".to_string();
+            match SA_KEY.get::<String>(options)? {
+                Some(sa_key) => (uri, sa_key),
+                None => {
+                    let sa_key_id: String = SA_KEY_ID.require(options)?;
+                    (uri, get_vault_secret(&sa_key_id)?)
+                }
+            }
+        }
+    };
+
+    let sa_key = yup_oauth2::parse_service_account_key(sa_key_json.as_bytes())
+        .map_err(|err| format!("parse service account key JSON failed: {}", err))?;
+
+    let client = rt
+        .block_on(
+            ClientBuilder::new()
+                .with_auth_base_url(auth_endpoint)
+                // Url of the BigQuery emulator docker image.
+                .with_v2_base_url(api_endpoint)
+                .build_from_service_account_key(sa_key, true),
+        )
+        .map_err(|err| format!("create client failed: {}", err))?;
+
+    Ok(Connection {
+        rt,
+        client,
+        auth_mock,
+    })
 }
 
 #[wrappers_fdw(
@@ -92,60 +252,87 @@ fn field_to_cell(rs: &ResultSet, field: &TableFieldSchema) -> Option<Cell> {
     website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/bigquery_fdw"
 )]
 pub(crate) struct BigQueryFdw {
-    rt: Runtime,
-    client: Option<Client>,
+    conn: Rc<Connection>,
     project_id: String,
     dataset_id: String,
     table: String,
     rowid_col: String,
     tgt_cols: Vec<Column>,
+    qual_exprs: Vec<QualExpr>,
     scan_result: Option<ResultSet>,
-    auth_mock: Option<GoogleAuthMock>,
+    requests: usize,
 }
 
 impl BigQueryFdw {
+    // fully qualified table name, or the sub query if the table is a query
+    fn table_ref(&self, table: &str) -> String {
+        if table.starts_with('(') {
+            table.to_owned()
+        } else {
+            format!("{}.{}.{}", self.project_id, self.dataset_id, table)
+        }
+    }
+
+    // deparse join to a single query, each side of the join is a sub query
+    // and the result columns are renamed to c1, c2, ... by their positions
+    fn deparse_join(&self, join: &Join) -> FdwResult<String> {
+        let outer_table = TABLE.require::<String>(&join.outer.options)?;
+        let inner_table = TABLE.require::<String>(&join.inner.options)?;
+        DEPARSER.join(
+            join,
+            &self.table_ref(&outer_table),
+            &self.table_ref(&inner_table),
+        )
+    }
+
+    // deparse aggregation to a single query, the result columns are renamed
+    // to c1, c2, ... by their positions
+    fn deparse_aggregate(&self, aggregate: &Aggregate) -> FdwResult<String> {
+        let table = TABLE.require::<String>(&aggregate.options)?;
+        DEPARSER.aggregate(aggregate, &self.table_ref(&table))
+    }
+
+    // execute query on BigQuery and save its result set for scan
+    fn execute_query(&mut self, sql: String, options: &HashMap<String, String>) -> FdwResult<()> {
+        let location: String = LOCATION.require(options)?;
+        let timeout: i32 = TIMEOUT.require(options)?;
+
+        let mut req = QueryRequest::new(sql);
+        req.location = Some(location);
+        req.timeout_ms = Some(timeout);
+
+        // execute query on BigQuery
+        self.scan_result = None;
+        self.requests += 1;
+        let rs = self
+            .conn
+            .rt
+            .block_on(self.conn.client.job().query(&self.project_id, req))
+            .map_err(|err| format!("query failed: {}", err))?;
+        if rs.query_response().job_complete == Some(false) {
+            return Err(format!("query timeout {}ms expired", timeout).into());
+        }
+        self.scan_result = Some(rs);
+
+        Ok(())
+    }
+
     fn deparse(
         &self,
+        table: &str,
         quals: &[Qual],
         columns: &[Column],
         sorts: &[Sort],
         limit: &Option<Limit>,
-    ) -> String {
-        let tgts = if columns.is_empty() {
-            "*".to_string()
-        } else {
-            columns
-                .iter()
-                .map(|c| c.name.clone())
-                .collect::<Vec<String>>()
-                .join(", ")
-        };
-        let table = if self.table.starts_with('(') {
-            self.table.clone()
-        } else {
-            format!("`{}.{}.{}`", self.project_id, self.dataset_id, self.table,)
-        };
-
-        let mut sql = if quals.is_empty() {
-            format!("select {} from {}", tgts, table)
-        } else {
-            let cond = quals
-                .iter()
-                .map(|q| q.deparse())
-                .collect::<Vec<String>>()
-                .join(" and ");
-            format!("select {} from {} where {}", tgts, table, cond)
-        };
-
-        // push down sorts
-        if !sorts.is_empty() {
-            let order_by = sorts
-                .iter()
-                .map(|sort| sort.deparse())
-                .collect::<Vec<String>>()
-                .join(", ");
-            sql.push_str(&format!(" order by {}", order_by));
-        }
+    ) -> FdwResult<String> {
+        let mut sql = DEPARSER.select(
+            &self.table_ref(table),
+            columns,
+            &DEPARSER.sendable_quals(quals),
+            &self.qual_exprs,
+            sorts,
+            &None,
+        )?;
 
         // push down limits
         // Note: Postgres will take limit and offset locally after reading rows
@@ -156,104 +343,36 @@ impl BigQueryFdw {
             sql.push_str(&format!(" limit {}", real_limit));
         }
 
-        sql
+        Ok(sql)
+    }
+
+    // EXPLAIN properties of a query sent to BigQuery
+    fn explain_sql(&self, sql: String) -> Vec<(String, String)> {
+        let mut ret = vec![("Remote SQL".to_string(), sql)];
+        if self.requests > 0 {
+            ret.push(("Remote Requests".to_string(), self.requests.to_string()));
+        }
+        ret
     }
 }
 
 impl ForeignDataWrapper for BigQueryFdw {
-    fn new(options: &HashMap<String, String>) -> Self {
-        let mut ret = BigQueryFdw {
-            rt: create_async_runtime(),
-            client: None,
-            project_id: "".to_string(),
-            dataset_id: "".to_string(),
+    fn new(options: &HashMap<String, String>) -> FdwResult<Self> {
+        let project_id: String = PROJECT_ID.require(options)?;
+        let dataset_id: String = DATASET_ID.require(options)?;
+        let conn = cached_connection(|| create_connection(options))?;
+
+        Ok(BigQueryFdw {
+            conn,
+            project_id,
+            dataset_id,
             table: "".to_string(),
             rowid_col: "".to_string(),
             tgt_cols: Vec::new(),
+            qual_exprs: Vec::new(),
             scan_result: None,
-            auth_mock: None,
-        };
-
-        let project_id = require_option("project_id", options);
-        let dataset_id = require_option("dataset_id", options);
-
-        if project_id.is_none() || dataset_id.is_none() {
-            return ret;
-        }
-        ret.project_id = project_id.unwrap();
-        ret.dataset_id = dataset_id.unwrap();
-
-        // Is authentication mocked
-        let mock_auth: bool = options
-            .get("mock_auth")
-            .map(|t| t.to_owned())
-            .unwrap_or_else(|| "false".to_string())
-            == *"true";
-
-        let api_endpoint = options
-            .get("api_endpoint")
-            .map(|t| t.to_owned())
-            .unwrap_or_else(|| "https://bigquery.googleapis.com/bigquery/v2".to_string());
-
-        let (auth_endpoint, sa_key_json) = match mock_auth {
-            true => {
-                // Key file is not required if we're mocking auth
-                let auth_mock = executor::block_on(GoogleAuthMock::start());
-                executor::block_on(auth_mock.mock_token(1));
-                let auth_mock_uri = auth_mock.uri();
-                let dummy_auth_config = dummy_configuration(&auth_mock_uri);
-                ret.auth_mock = Some(auth_mock);
-                let sa_key_json = serde_json::to_string_pretty(&dummy_auth_config).unwrap();
-                (auth_mock_uri, sa_key_json)
-            }
-            false => {
-                let uri = "https://www.googleapis.com/auth/bigquery".to_string();
-                match options.get("sa_key") {
-                    Some(sa_key) => (uri, sa_key.to_owned()),
-                    None => {
-                        let sa_key_id = match require_option("sa_key_id", options) {
-                            Some(sa_key_id) => sa_key_id,
-                            None => return ret,
-                        };
-                        let sa_key_json = match get_vault_secret(&sa_key_id) {
-                            Some(sa_key) => sa_key,
-                            None => return ret,
-                        };
-                        (uri, sa_key_json)
-                    }
-                }
-            }
-        };
-
-        let sa_key = match yup_oauth2::parse_service_account_key(sa_key_json.as_bytes()) {
-            Ok(sa_key) => sa_key,
-            Err(err) => {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("parse service account key JSON failed: {}", err),
-                );
-                return ret;
-            }
-        };
-
-        ret.client = match ret.rt.block_on(
-            ClientBuilder::new()
-                .with_auth_base_url(auth_endpoint)
-                // Url of the BigQuery emulator docker image.
-                .with_v2_base_url(api_endpoint)
-                .build_from_service_account_key(sa_key, true),
-        ) {
-            Ok(client) => Some(client),
-            Err(err) => {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("create client failed: {}", err),
-                );
-                None
-            }
-        };
-
-        ret
+            requests: 0,
+        })
     }
 
     fn get_rel_size(
@@ -263,8 +382,41 @@ impl ForeignDataWrapper for BigQueryFdw {
         _sorts: &[Sort],
         _limit: &Option<Limit>,
         _options: &HashMap<String, String>,
-    ) -> (i64, i32) {
-        (0, 0)
+    ) -> FdwResult<(i64, i32)> {
+        Ok((0, 0))
+    }
+
+    fn get_rel_cost(&mut self, _options: &HashMap<String, String>) -> FdwResult<ScanCost> {
+        // each scan runs a query job, which takes much longer than fetching
+        // the result rows
+        Ok(ScanCost {
+            request_cost: 1000.0,
+            ..Default::default()
+        })
+    }
+
+    fn can_push_down_quals(
+        &mut self,
+        quals: &[Qual],
+        _options: &HashMap<String, String>,
+    ) -> FdwResult<Vec<bool>> {
+        Ok(quals.iter().map(|q| DEPARSER.is_remote_qual(q)).collect())
+    }
+
+    fn can_push_down_qual_exprs(
+        &mut self,
+        exprs: &[QualExpr],
+        _options: &HashMap<String, String>,
+    ) -> FdwResult<Vec<bool>> {
+        Ok(exprs
+            .iter()
+            .map(|expr| DEPARSER.is_remote_qual_expr(expr))
+            .collect())
+    }
+
+    fn begin_scan_qual_exprs(&mut self, exprs: &[QualExpr]) -> FdwResult<()> {
+        self.qual_exprs = exprs.to_vec();
+        Ok(())
     }
 
     fn begin_scan(
@@ -274,90 +426,47 @@ impl ForeignDataWrapper for BigQueryFdw {
         sorts: &[Sort],
         limit: &Option<Limit>,
         options: &HashMap<String, String>,
-    ) {
-        let table = require_option("table", options);
-        if table.is_none() {
-            return;
-        }
-        self.table = table.unwrap();
+    ) -> FdwResult<()> {
+        self.table = TABLE.require::<String>(options)?;
         self.tgt_cols = columns.to_vec();
 
-        let location = options
-            .get("location")
-            .map(|t| t.to_owned())
-            .unwrap_or_else(|| "US".to_string());
-
-        let mut timeout: i32 = 30_000;
-        if let Some(timeout_str) = options.get("timeout") {
-            match timeout_str.parse::<i32>() {
-                Ok(t) => timeout = t,
-                Err(_) => report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("invalid timeout value: {}", timeout_str),
-                ),
-            }
-        }
-
-        if let Some(client) = &self.client {
-            let sql = self.deparse(quals, columns, sorts, limit);
-            let mut req = QueryRequest::new(sql);
-            req.location = Some(location);
-            req.timeout_ms = Some(timeout);
-
-            // execute query on BigQuery
-            match self.rt.block_on(client.job().query(&self.project_id, req)) {
-                Ok(rs) => {
-                    if rs.query_response().job_complete == Some(false) {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!("query timeout {}ms expired", timeout),
-                        );
-                    } else {
-                        self.scan_result = Some(rs);
-                    }
-                }
-                Err(err) => {
-                    self.scan_result = None;
-                    report_error(
-                        PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                        &format!("query failed: {}", err),
-                    );
-                }
-            }
-        }
+        let sql = self.deparse(&self.table, quals, columns, sorts, limit)?;
+        self.execute_query(sql, options)
     }
 
-    fn iter_scan(&mut self, row: &mut Row) -> Option<()> {
-        if let Some(client) = &self.client {
-            if let Some(ref mut rs) = self.scan_result {
-                let mut extract_row = |rs: &mut ResultSet| {
-                    if rs.next_row() {
-                        if let Some(schema) = &rs.query_response().schema {
-                            if let Some(fields) = &schema.fields {
-                                for tgt_col in &self.tgt_cols {
-                                    if let Some(field) =
-                                        fields.iter().find(|&f| f.name == tgt_col.name)
-                                    {
-                                        let cell = field_to_cell(rs, field);
-                                        row.push(&field.name, cell);
-                                    }
+    fn iter_scan(&mut self, row: &mut Row) -> FdwResult<Option<()>> {
+        if let Some(ref mut rs) = self.scan_result {
+            let mut extract_row = |rs: &mut ResultSet| -> FdwResult<bool> {
+                if rs.next_row() {
+                    if let Some(schema) = &rs.query_response().schema {
+                        if let Some(fields) = &schema.fields {
+                            for tgt_col in &self.tgt_cols {
+                                if let Some(field) = fields.iter().find(|&f| f.name == tgt_col.name)
+                                {
+                                    let cell = field_to_cell(rs, field)?;
+                                    row.push(&field.name, cell);
                                 }
-                                return true;
                             }
+                            return Ok(true);
                         }
                     }
-                    false
-                };
-
-                if extract_row(rs) {
-                    return Some(());
                 }
+                Ok(false)
+            };
+
+            if extract_row(rs)? {
+                return Ok(Some(()));
+            }
 
-                // deal with pagination
-                if rs.query_response().page_token.is_some() {
-                    if let Some(job_ref) = &rs.query_response().job_reference {
-                        if let Some(job_id) = &job_ref.job_id {
-                            match self.rt.block_on(client.job().get_query_results(
+            // deal with pagination
+            if rs.query_response().page_token.is_some() {
+                if let Some(job_ref) = &rs.query_response().job_reference {
+                    if let Some(job_id) = &job_ref.job_id {
+                        self.requests += 1;
+                        let resp = self
+                            .conn
+                            .rt
+                            .block_on(self.conn.client.job().get_query_results(
                                 &self.project_id,
                                 job_id,
                                 GetQueryResultsParameters {
@@ -365,144 +474,341 @@ impl ForeignDataWrapper for BigQueryFdw {
                                     page_token: rs.query_response().page_token.clone(),
                                     ..Default::default()
                                 },
-                            )) {
-                                Ok(resp) => {
-                                    // replace result set with data from the new page
-                                    *rs = ResultSet::new(QueryResponse::from(resp));
-                                    if extract_row(rs) {
-                                        return Some(());
-                                    }
-                                }
-                                Err(err) => {
-                                    self.scan_result = None;
-                                    report_error(
-                                        PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                                        &format!("fetch query result failed: {}", err),
-                                    );
-                                }
-                            }
+                            ))
+                            .map_err(|err| format!("fetch query result failed: {}", err))?;
+
+                        // replace result set with data from the new page
+                        *rs = ResultSet::new(QueryResponse::from(resp));
+                        if extract_row(rs)? {
+                            return Ok(Some(()));
                         }
                     }
                 }
             }
         }
-        None
+        Ok(None)
     }
 
-    fn end_scan(&mut self) {
+    fn end_scan(&mut self) -> FdwResult<()> {
         self.scan_result.take();
+        Ok(())
+    }
+
+    fn explain_scan(
+        &self,
+        quals: &[Qual],
+        columns: &[Column],
+        sorts: &[Sort],
+        limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+        _verbose: bool,
+    ) -> FdwResult<Vec<(String, String)>> {
+        let table = TABLE.require::<String>(options)?;
+        let sql = self.deparse(&table, quals, columns, sorts, limit)?;
+        Ok(self.explain_sql(sql))
     }
 
-    fn begin_modify(&mut self, options: &HashMap<String, String>) {
-        let table = require_option("table", options);
-        let rowid_col = require_option("rowid_column", options);
-        if table.is_none() || rowid_col.is_none() {
-            return;
+    fn can_push_down_sort(&mut self, sorts: &[Sort]) -> FdwResult<bool> {
+        // collations are not compatible with BigQuery's
+        Ok(sorts.iter().all(|sort| sort.collate.is_none()))
+    }
+
+    fn can_push_down_join(&mut self, join: &Join) -> FdwResult<bool> {
+        // only equality is supported in join conditions, as ordering of the
+        // join columns may depend on collation, and full join must have
+        // conditions
+        Ok(join.quals.iter().all(|q| q.operator == "=")
+            && (join.join_type != JoinType::Full || !join.quals.is_empty())
+            && join
+                .outer
+                .quals
+                .iter()
+                .chain(join.inner.quals.iter())
+                .all(|q| DEPARSER.is_remote_qual(q))
+            && join.outer.options.contains_key("table")
+            && join.inner.options.contains_key("table"))
+    }
+
+    fn begin_join_scan(&mut self, join: &Join) -> FdwResult<()> {
+        // result columns are renamed by their positions in the join query
+        self.tgt_cols = join
+            .outer
+            .columns
+            .iter()
+            .chain(join.inner.columns.iter())
+            .enumerate()
+            .map(|(i, col)| Column {
+                name: format!("c{}", i + 1),
+                ..col.clone()
+            })
+            .collect();
+
+        let sql = self.deparse_join(join)?;
+        self.execute_query(sql, &join.outer.options)
+    }
+
+    fn explain_join_scan(&self, join: &Join, _verbose: bool) -> FdwResult<Vec<(String, String)>> {
+        let sql = self.deparse_join(join)?;
+        Ok(self.explain_sql(sql))
+    }
+
+    fn can_push_down_aggregate(&mut self, aggregate: &Aggregate) -> FdwResult<bool> {
+        let funcs = ["count", "sum", "avg", "min", "max"];
+        Ok(aggregate
+            .funcs
+            .iter()
+            .all(|f| funcs.contains(&f.name.as_str()))
+            && aggregate.quals.iter().all(|q| DEPARSER.is_remote_qual(q))
+            && aggregate
+                .having
+                .iter()
+                .all(|q| DEPARSER.is_remote_having_qual(q))
+            && aggregate.options.contains_key("table"))
+    }
+
+    fn begin_aggregate_scan(&mut self, aggregate: &Aggregate) -> FdwResult<()> {
+        // result columns are renamed by their positions in the aggregate query
+        self.tgt_cols = aggregate
+            .group_by
+            .iter()
+            .cloned()
+            .chain(aggregate.funcs.iter().map(|f| Column {
+                type_oid: f.type_oid,
+                ..Default::default()
+            }))
+            .enumerate()
+            .map(|(i, col)| Column {
+                name: format!("c{}", i + 1),
+                ..col
+            })
+            .collect();
+
+        let sql = self.deparse_aggregate(aggregate)?;
+        self.execute_query(sql, &aggregate.options)
+    }
+
+    fn explain_aggregate_scan(
+        &self,
+        aggregate: &Aggregate,
+        _verbose: bool,
+    ) -> FdwResult<Vec<(String, String)>> {
+        let sql = self.deparse_aggregate(aggregate)?;
+        Ok(self.explain_sql(sql))
+    }
+
+    fn import_foreign_schema(&mut self, stmt: ImportForeignSchemaStmt) -> FdwResult<Vec<String>> {
+        // foreign table's 'table' option is relative to the server's dataset
+        if stmt.remote_schema != self.dataset_id {
+            return Err(FdwError::new(
+                PgSqlErrorCode::ERRCODE_FDW_SCHEMA_NOT_FOUND,
+                format!("dataset \"{}\" is not found", stmt.remote_schema),
+            )
+            .hint(format!(
+                "remote schema must be the server's dataset_id \"{}\"",
+                self.dataset_id
+            )));
         }
-        self.table = table.unwrap();
-        self.rowid_col = rowid_col.unwrap();
-    }
-
-    fn insert(&mut self, src: &Row) {
-        if let Some(ref mut client) = self.client {
-            let mut insert_request = TableDataInsertAllRequest::new();
-            let mut row_json = json!({});
-
-            for (col_name, cell) in src.iter() {
-                if let Some(cell) = cell {
-                    match cell {
-                        Cell::Bool(v) => row_json[col_name] = json!(v),
-                        Cell::I8(v) => row_json[col_name] = json!(v),
-                        Cell::I16(v) => row_json[col_name] = json!(v),
-                        Cell::I32(v) => row_json[col_name] = json!(v),
-                        Cell::I64(v) => row_json[col_name] = json!(v),
-                        Cell::F32(v) => row_json[col_name] = json!(v),
-                        Cell::F64(v) => row_json[col_name] = json!(v),
-                        Cell::Numeric(v) => row_json[col_name] = json!(v),
-                        Cell::String(v) => row_json[col_name] = json!(v),
-                        Cell::Date(v) => row_json[col_name] = json!(v),
-                        Cell::Timestamp(v) => row_json[col_name] = json!(v),
-                        Cell::Json(v) => row_json[col_name] = json!(v),
-                    }
+
+        // list all tables in the dataset
+        let mut table_ids = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut list_opts = ListOptions::default();
+            if let Some(page_token) = page_token {
+                list_opts = list_opts.page_token(page_token);
+            }
+            let tables = self
+                .conn
+                .rt
+                .block_on(self.conn.client.table().list(
+                    &self.project_id,
+                    &self.dataset_id,
+                    list_opts,
+                ))
+                .map_err(|err| format!("list tables failed: {}", err))?;
+            for table in tables.tables.unwrap_or_default() {
+                let table_id = table.table_reference.table_id;
+                if stmt.is_table_included(&table_id) {
+                    table_ids.push(table_id);
                 }
             }
+            page_token = tables.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+
+        let mut ret = Vec::new();
+        for table_id in table_ids {
+            let table = self
+                .conn
+                .rt
+                .block_on(self.conn.client.table().get(
+                    &self.project_id,
+                    &self.dataset_id,
+                    &table_id,
+                    None,
+                ))
+                .map_err(|err| format!("get table {} failed: {}", table_id, err))?;
+
+            // skip fields whose data type is not supported
+            let cols = table
+                .schema
+                .fields
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|field| {
+                    field_type_to_pg_type(&field.r#type)
+                        .map(|pg_type| format!("{} {}", quote_identifier(&field.name), pg_type))
+                })
+                .collect::<Vec<String>>();
+            if cols.is_empty() {
+                continue;
+            }
+
+            ret.push(format!(
+                "create foreign table if not exists {} ({}) server {} options (table {})",
+                quote_identifier(&table_id),
+                cols.join(", "),
+                quote_identifier(&stmt.server_name),
+                quote_literal(&table_id),
+            ));
+        }
+        Ok(ret)
+    }
+
+    fn begin_modify(&mut self, options: &HashMap<String, String>) -> FdwResult<()> {
+        self.table = TABLE.require::<String>(options)?;
+        self.rowid_col = require_option("rowid_column", options)?;
+        Ok(())
+    }
 
-            insert_request.add_row(None, row_json).unwrap();
+    fn insert(&mut self, src: &Row) -> FdwResult<Option<Row>> {
+        self.insert_batch(std::slice::from_ref(src))?;
+        Ok(None)
+    }
+
+    fn insert_batch(&mut self, rows: &[Row]) -> FdwResult<()> {
+        // add all rows to one request, so they can be inserted in one API call
+        let mut insert_request = TableDataInsertAllRequest::new();
+        for src in rows {
+            insert_request
+                .add_row(None, row_to_json(src)?)
+                .map_err(|err| FdwError::new(PgSqlErrorCode::ERRCODE_FDW_ERROR, err.to_string()))?;
+        }
 
-            // execute insert job on BigQuery
-            if let Err(err) = self.rt.block_on(client.tabledata().insert_all(
+        // execute insert job on BigQuery
+        self.requests += 1;
+        self.conn
+            .rt
+            .block_on(self.conn.client.tabledata().insert_all(
                 &self.project_id,
                 &self.dataset_id,
                 &self.table,
                 insert_request,
-            )) {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("insert failed: {}", err),
-                );
-            }
-        }
+            ))
+            .map_err(|err| format!("insert failed: {}", err))?;
+        Ok(())
     }
 
-    fn update(&mut self, rowid: &Cell, new_row: &Row) {
-        if let Some(ref mut client) = self.client {
-            let mut sets = Vec::new();
-            for (col, cell) in new_row.iter() {
-                if col == &self.rowid_col {
-                    continue;
-                }
-                if let Some(cell) = cell {
-                    sets.push(format!("{} = {}", col, cell));
-                } else {
-                    sets.push(format!("{} = null", col));
-                }
-            }
-            let sql = format!(
-                "update `{}.{}.{}` set {} where {} = {}",
-                self.project_id,
-                self.dataset_id,
-                self.table,
-                sets.join(", "),
-                self.rowid_col,
-                rowid
-            );
-
-            let query_job = client
-                .job()
-                .query(&self.project_id, QueryRequest::new(&sql));
-
-            // execute update on BigQuery
-            if let Err(err) = self.rt.block_on(query_job) {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("update failed: {}", err),
-                );
-            }
-        }
+    fn update(&mut self, rowid: &Cell, new_row: &Row) -> FdwResult<Option<Row>> {
+        let sql = DEPARSER.update(
+            &self.table_ref(&self.table),
+            new_row,
+            &self.rowid_col,
+            rowid,
+        )?;
+
+        let query_job = self
+            .conn
+            .client
+            .job()
+            .query(&self.project_id, QueryRequest::new(&sql));
+
+        // execute update on BigQuery
+        self.requests += 1;
+        self.conn
+            .rt
+            .block_on(query_job)
+            .map_err(|err| format!("update failed: {}", err))?;
+        Ok(None)
     }
 
-    fn delete(&mut self, rowid: &Cell) {
-        if let Some(ref mut client) = self.client {
-            let sql = format!(
-                "delete from `{}.{}.{}` where {} = {}",
-                self.project_id, self.dataset_id, self.table, self.rowid_col, rowid
-            );
+    fn delete(&mut self, rowid: &Cell) -> FdwResult<Option<Row>> {
+        let sql = DEPARSER.delete(&self.table_ref(&self.table), &self.rowid_col, rowid)?;
+
+        let query_job = self
+            .conn
+            .client
+            .job()
+            .query(&self.project_id, QueryRequest::new(&sql));
+
+        // execute delete on BigQuery
+        self.requests += 1;
+        self.conn
+            .rt
+            .block_on(query_job)
+            .map_err(|err| format!("delete failed: {}", err))?;
+        Ok(None)
+    }
 
-            let query_job = client
-                .job()
-                .query(&self.project_id, QueryRequest::new(&sql));
+    fn end_modify(&mut self) -> FdwResult<()> {
+        Ok(())
+    }
 
-            // execute delete on BigQuery
-            if let Err(err) = self.rt.block_on(query_job) {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("update failed: {}", err),
-                );
-            }
+    fn explain_modify(
+        &self,
+        options: &HashMap<String, String>,
+        _verbose: bool,
+    ) -> FdwResult<Vec<(String, String)>> {
+        let table = TABLE.require::<String>(options)?;
+        let mut ret = vec![(
+            "Remote Table".to_string(),
+            DEPARSER.quote_table(&self.table_ref(&table)),
+        )];
+        if self.requests > 0 {
+            ret.push(("Remote Requests".to_string(), self.requests.to_string()));
         }
+        Ok(ret)
     }
 
-    fn end_modify(&mut self) {}
+    fn truncate(
+        &mut self,
+        options: &HashMap<String, String>,
+        _restart_seqs: bool,
+        _cascade: bool,
+    ) -> FdwResult<()> {
+        let table = TABLE.require::<String>(options)?;
+        let sql = DEPARSER.truncate(&self.table_ref(&table));
+
+        let query_job = self
+            .conn
+            .client
+            .job()
+            .query(&self.project_id, QueryRequest::new(&sql));
+
+        // execute truncate on BigQuery
+        self.requests += 1;
+        self.conn
+            .rt
+            .block_on(query_job)
+            .map_err(|err| format!("truncate failed: {}", err))?;
+        Ok(())
+    }
+
+    fn options() -> &'static [OptionDef] {
+        &[
+            PROJECT_ID,
+            DATASET_ID,
+            SA_KEY,
+            SA_KEY_ID,
+            MOCK_AUTH,
+            API_ENDPOINT,
+            TABLE,
+            LOCATION,
+            TIMEOUT,
+        ]
+    }
 }
 
 use auth_mock::GoogleAuthMock;
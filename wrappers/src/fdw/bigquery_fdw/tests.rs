@@ -97,6 +97,28 @@ mod tests {
 
             assert_eq!(results, vec!["0.123", "1234.56789"]);
 
+            let results = c
+                .select(
+                    "SELECT b.name AS upper_name FROM test_table a JOIN test_table_with_subquery b ON a.id = b.id ORDER BY a.id",
+                    None,
+                    None,
+                )
+                .filter_map(|r| r.by_name("upper_name").ok().and_then(|v| v.value::<&str>()))
+                .collect::<Vec<_>>();
+
+            assert_eq!(results, vec!["FOO", "BAR"]);
+
+            let results = c
+                .select(
+                    "SELECT count(*) AS cnt FROM test_table WHERE name = 'foo'",
+                    None,
+                    None,
+                )
+                .filter_map(|r| r.by_name("cnt").ok().and_then(|v| v.value::<i64>()))
+                .collect::<Vec<_>>();
+
+            assert_eq!(results, vec![1]);
+
             // DISABLED: error: [FIXME]
             // insert failed: Request error (error: error decoding response body: missing field `status` at line 1 column 436)
 
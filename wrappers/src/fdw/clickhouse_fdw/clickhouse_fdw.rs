@@ -1,80 +1,202 @@
-use chrono::DateTime;
-use clickhouse_rs::{types, types::Block, types::SqlType, ClientHandle, Pool};
-use pgx::prelude::{PgSqlErrorCode, Timestamp};
+use chrono::{DateTime, TimeZone};
+use clickhouse_rs::{types, types::Block, types::FromSql, types::SqlType, ClientHandle, Pool};
+use pgx::pg_sys;
+use pgx::prelude::{AnyNumeric, PgSqlErrorCode, Timestamp};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 use time::OffsetDateTime;
 
 use supabase_wrappers::prelude::*;
 
-fn create_client(rt: &Runtime, conn_str: &str) -> Option<ClientHandle> {
+const DEPARSER: Deparser = Deparser::new(Dialect::ClickHouse);
+
+// number of rows returned to Postgres in one batch
+const SCAN_BATCH_SIZE: usize = 1024;
+
+use OptionCatalog::*;
+
+const CONN_STRING: OptionDef = OptionDef::new("conn_string", &[Server, UserMapping])
+    .secret()
+    .description("ClickHouse connection string");
+const CONN_STRING_ID: OptionDef = OptionDef::new("conn_string_id", &[Server, UserMapping])
+    .description("Vault secret key ID of the connection string, used if conn_string is not set");
+const TABLE: OptionDef = OptionDef::new("table", &[Table])
+    .required()
+    .description("source table name, or a subquery enclosed in parentheses");
+const PARTITION_COLUMN: OptionDef = OptionDef::new("partition_column", &[Table])
+    .description("integer column used to split the parallel scan into partitions");
+const PARALLEL_PARTITIONS: OptionDef = OptionDef::new("parallel_partitions", &[Table])
+    .kind(OptionType::Int)
+    .default("1")
+    .description("number of partitions scanned in parallel, requires partition_column");
+
+fn create_client(rt: &Runtime, conn_str: &str) -> FdwResult<ClientHandle> {
     let pool = Pool::new(conn_str);
-    rt.block_on(pool.get_handle()).map_or_else(
-        |err| {
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_UNABLE_TO_ESTABLISH_CONNECTION,
-                &format!("connection failed: {}", err),
-            );
-            None
-        },
-        Some,
+    rt.block_on(pool.get_handle()).map_err(|err| {
+        FdwError::new(
+            PgSqlErrorCode::ERRCODE_FDW_UNABLE_TO_ESTABLISH_CONNECTION,
+            format!("connection failed: {}", err),
+        )
+    })
+}
+
+// get the field at column i of a row in the block
+fn get_field<'a, T: FromSql<'a>>(
+    block: &'a Block<types::Complex>,
+    row: usize,
+    i: usize,
+) -> FdwResult<T> {
+    block.get::<T, usize>(row, i).map_err(|err| {
+        FdwError::new(
+            PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+            format!("get field {} failed: {}", block.columns()[i].name(), err),
+        )
+    })
+}
+
+// convert a ClickHouse DateTime to timestamp cell
+fn datetime_to_timestamp<Tz: TimeZone>(value: DateTime<Tz>) -> FdwResult<Cell> {
+    value
+        .timestamp_nanos_opt()
+        .ok_or_else(|| "timestamp out of range".to_string())
+        .and_then(|nanos| {
+            OffsetDateTime::from_unix_timestamp_nanos(nanos as i128).map_err(|err| err.to_string())
+        })
+        .and_then(|dt| Timestamp::try_from(dt).map_err(|err| err.to_string()))
+        .map(Cell::Timestamp)
+        .map_err(|err| {
+            FdwError::new(
+                PgSqlErrorCode::ERRCODE_FDW_ERROR,
+                format!("invalid datetime {}: {}", value.naive_utc(), err),
+            )
+        })
+}
+
+fn unsupported_type(sql_type: SqlType) -> FdwError {
+    FdwError::new(
+        PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+        format!("data type {} is not supported", sql_type.to_string()),
     )
 }
 
-fn field_to_cell(row: &types::Row<types::Complex>, i: usize) -> Option<Cell> {
-    let sql_type = row.sql_type(i).unwrap();
-    match sql_type {
+// convert the field at column i of a row in the block to Cell
+fn field_to_cell(block: &Block<types::Complex>, row: usize, i: usize) -> FdwResult<Option<Cell>> {
+    let sql_type = block.columns()[i].sql_type();
+    let cell = match sql_type {
         SqlType::UInt8 => {
             // Bool is stored as UInt8 in ClickHouse, so we treat it as bool here
-            let value = row.get::<u8, usize>(i).unwrap();
+            let value: u8 = get_field(block, row, i)?;
             Some(Cell::Bool(value != 0))
         }
-        SqlType::Int16 => {
-            let value = row.get::<i16, usize>(i).unwrap();
-            Some(Cell::I16(value))
-        }
-        SqlType::Int32 => {
-            let value = row.get::<i32, usize>(i).unwrap();
-            Some(Cell::I32(value))
-        }
+        SqlType::Int16 => Some(Cell::I16(get_field(block, row, i)?)),
+        SqlType::Int32 => Some(Cell::I32(get_field(block, row, i)?)),
         SqlType::UInt32 => {
-            let value = row.get::<u32, usize>(i).unwrap();
+            let value: u32 = get_field(block, row, i)?;
             Some(Cell::I64(value as i64))
         }
-        SqlType::Float32 => {
-            let value = row.get::<f32, usize>(i).unwrap();
-            Some(Cell::F32(value))
-        }
-        SqlType::Float64 => {
-            let value = row.get::<f64, usize>(i).unwrap();
-            Some(Cell::F64(value))
-        }
+        SqlType::Float32 => Some(Cell::F32(get_field(block, row, i)?)),
+        SqlType::Float64 => Some(Cell::F64(get_field(block, row, i)?)),
         SqlType::UInt64 => {
-            let value = row.get::<u64, usize>(i).unwrap();
+            let value: u64 = get_field(block, row, i)?;
             Some(Cell::I64(value as i64))
         }
-        SqlType::Int64 => {
-            let value = row.get::<i64, usize>(i).unwrap();
-            Some(Cell::I64(value))
-        }
-        SqlType::String => {
-            let value = row.get::<String, usize>(i).unwrap();
-            Some(Cell::String(value))
-        }
+        SqlType::Int64 => Some(Cell::I64(get_field(block, row, i)?)),
+        SqlType::String => Some(Cell::String(get_field(block, row, i)?)),
         SqlType::DateTime(_) => {
-            let value = row.get::<DateTime<_>, usize>(i).unwrap();
-            let dt = OffsetDateTime::from_unix_timestamp_nanos((value.timestamp_nanos()) as i128)
-                .unwrap();
-            let ts = Timestamp::try_from(dt).unwrap();
-            Some(Cell::Timestamp(ts))
+            let value: DateTime<_> = get_field(block, row, i)?;
+            Some(datetime_to_timestamp(value)?)
         }
-        _ => {
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
-                &format!("data type {} is not supported", sql_type.to_string()),
-            );
-            None
+        SqlType::Array(elem_type) => {
+            let cells = match elem_type {
+                SqlType::Int16 => to_array_cells(get_field(block, row, i)?, Cell::I16),
+                SqlType::Int32 => to_array_cells(get_field(block, row, i)?, Cell::I32),
+                SqlType::Int64 => to_array_cells(get_field(block, row, i)?, Cell::I64),
+                SqlType::Float32 => to_array_cells(get_field(block, row, i)?, Cell::F32),
+                SqlType::Float64 => to_array_cells(get_field(block, row, i)?, Cell::F64),
+                SqlType::String => to_array_cells(get_field(block, row, i)?, Cell::String),
+                _ => return Err(unsupported_type(sql_type)),
+            };
+            Some(Cell::Array(cells))
+        }
+        _ => return Err(unsupported_type(sql_type)),
+    };
+    Ok(cell)
+}
+
+// convert values of an Array(T) field to array elements
+fn to_array_cells<T>(values: Vec<T>, f: fn(T) -> Cell) -> Vec<Option<Cell>> {
+    values.into_iter().map(|v| Some(f(v))).collect()
+}
+
+// convert cell to the type expected by Postgres, for example sum(bigint)
+// returns numeric in Postgres but Int64 in ClickHouse
+fn cast_cell(cell: Cell, type_oid: pg_sys::Oid) -> FdwResult<Cell> {
+    let cell = match (cell, type_oid) {
+        (Cell::I64(v), pg_sys::NUMERICOID) => Cell::Numeric(AnyNumeric::from(v)),
+        (Cell::F64(v), pg_sys::NUMERICOID) => Cell::Numeric(
+            AnyNumeric::try_from(v).map_err(|err| format!("invalid numeric {}: {}", v, err))?,
+        ),
+        (Cell::I64(v), pg_sys::FLOAT8OID) => Cell::F64(v as f64),
+        (Cell::F32(v), pg_sys::FLOAT8OID) => Cell::F64(v as f64),
+        (Cell::I16(v), pg_sys::INT8OID) => Cell::I64(v as i64),
+        (Cell::I32(v), pg_sys::INT8OID) => Cell::I64(v as i64),
+        (cell, _) => cell,
+    };
+    Ok(cell)
+}
+
+// convert a row to ClickHouse row which can be pushed into a block
+fn row_to_ch_row(src: &Row) -> FdwResult<Vec<(String, types::Value)>> {
+    let mut row = Vec::new();
+    for (col_name, cell) in src.iter() {
+        let col_name = col_name.to_owned();
+        if let Some(cell) = cell {
+            match cell {
+                Cell::Bool(v) => row.push((col_name, types::Value::from(*v))),
+                Cell::F64(v) => row.push((col_name, types::Value::from(*v))),
+                Cell::I64(v) => row.push((col_name, types::Value::from(*v))),
+                Cell::String(v) => row.push((col_name, types::Value::from(v.as_str()))),
+                _ => {
+                    return Err(FdwError::new(
+                        PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+                        format!("field type {:?} not supported", cell),
+                    ))
+                }
+            }
         }
     }
+    Ok(row)
+}
+
+// map ClickHouse data type to Postgres data type, only the types supported
+// by field_to_cell() are mapped
+fn ch_type_to_pg_type(ch_type: &str) -> Option<&'static str> {
+    let pg_type = match ch_type {
+        "UInt8" => "boolean",
+        "Int16" => "smallint",
+        "Int32" => "integer",
+        "UInt32" | "Int64" | "UInt64" => "bigint",
+        "Float32" => "real",
+        "Float64" => "double precision",
+        "String" => "text",
+        _ if ch_type.starts_with("DateTime") => "timestamp",
+        "Array(Int16)" => "smallint[]",
+        "Array(Int32)" => "integer[]",
+        "Array(Int64)" => "bigint[]",
+        "Array(Float32)" => "real[]",
+        "Array(Float64)" => "double precision[]",
+        "Array(String)" => "text[]",
+        _ => return None,
+    };
+    Some(pg_type)
+}
+
+// connection to ClickHouse, which is cached and shared by the FDW instances
+// in the same backend
+struct Connection {
+    rt: Runtime,
+    client: RefCell<ClientHandle>,
 }
 
 #[wrappers_fdw(
@@ -83,59 +205,126 @@ fn field_to_cell(row: &types::Row<types::Complex>, i: usize) -> Option<Cell> {
     website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/clickhouse_fdw"
 )]
 pub(crate) struct ClickHouseFdw {
-    rt: Runtime,
-    client: Option<ClientHandle>,
+    conn: Rc<Connection>,
     table: String,
     rowid_col: String,
     tgt_cols: Vec<Column>,
+    qual_exprs: Vec<QualExpr>,
     scan_blk: Option<Block<types::Complex>>,
     row_idx: usize,
+    requests: usize,
 }
 
 impl ClickHouseFdw {
-    fn deparse(&self, quals: &[Qual], columns: &[Column]) -> String {
-        let tgts = if columns.is_empty() {
-            "*".to_string()
-        } else {
-            columns
-                .iter()
-                .map(|c| c.name.clone())
-                .collect::<Vec<String>>()
-                .join(", ")
-        };
-        let sql = if quals.is_empty() {
-            format!("select {} from {}", tgts, &self.table)
-        } else {
-            let cond = quals
-                .iter()
-                .map(|q| q.deparse())
-                .collect::<Vec<String>>()
-                .join(" and ");
-            format!("select {} from {} where {}", tgts, &self.table, cond)
-        };
-        sql
+    // deparse join to a single query, each side of the join is a sub query
+    fn deparse_join(join: &Join) -> FdwResult<String> {
+        let outer_table = TABLE.require::<String>(&join.outer.options)?;
+        let inner_table = TABLE.require::<String>(&join.inner.options)?;
+        DEPARSER.join(join, &outer_table, &inner_table)
+    }
+
+    // deparse aggregation to a single query
+    fn deparse_aggregate(aggregate: &Aggregate) -> FdwResult<String> {
+        let table = TABLE.require::<String>(&aggregate.options)?;
+        DEPARSER.aggregate(aggregate, &table)
+    }
+
+    // deparse a scan to a single query
+    fn deparse_scan(
+        &self,
+        table: &str,
+        quals: &[Qual],
+        columns: &[Column],
+        sorts: &[Sort],
+    ) -> FdwResult<String> {
+        DEPARSER.select(
+            table,
+            columns,
+            &DEPARSER.sendable_quals(quals),
+            &self.qual_exprs,
+            sorts,
+            &None,
+        )
+    }
+
+    // deparse the query of a partition in parallel scan, rows are split into
+    // partitions by the hash of the partition column
+    fn deparse_partition_scan(
+        &self,
+        table: &str,
+        quals: &[Qual],
+        columns: &[Column],
+        partition_col: &str,
+        partition: usize,
+        partitions: usize,
+    ) -> FdwResult<String> {
+        let cond = format!(
+            "modulo(sipHash64(ifNull(toString({}), '')), {}) = {}",
+            DEPARSER.quote_ident(partition_col),
+            partitions,
+            partition
+        );
+        DEPARSER.select_with_conds(
+            table,
+            columns,
+            &DEPARSER.sendable_quals(quals),
+            &self.qual_exprs,
+            &[cond],
+        )
+    }
+
+    // EXPLAIN properties of a query sent to ClickHouse
+    fn explain_sql(&self, sql: String) -> Vec<(String, String)> {
+        let mut ret = vec![("Remote SQL".to_string(), sql)];
+        if self.requests > 0 {
+            ret.push(("Remote Requests".to_string(), self.requests.to_string()));
+        }
+        ret
+    }
+
+    // fetch whole query result to local
+    fn fetch(&mut self, sql: &str) -> FdwResult<()> {
+        self.requests += 1;
+        let block = self
+            .conn
+            .rt
+            .block_on(self.conn.client.borrow_mut().query(sql).fetch_all())
+            .map_err(|err| format!("query failed: {}", err))?;
+        self.scan_blk = Some(block);
+        self.row_idx = 0;
+        Ok(())
     }
 }
 
 impl ForeignDataWrapper for ClickHouseFdw {
-    fn new(options: &HashMap<String, String>) -> Self {
-        let rt = create_async_runtime();
-        let client = match options.get("conn_string") {
-            Some(conn_str) => create_client(&rt, conn_str),
-            None => require_option("conn_string_id", options)
-                .and_then(|conn_str_id| get_vault_secret(&conn_str_id))
-                .and_then(|conn_str| create_client(&rt, &conn_str)),
-        };
+    fn new(options: &HashMap<String, String>) -> FdwResult<Self> {
+        let conn = cached_connection(|| {
+            let rt = create_async_runtime();
+            let conn_str = match CONN_STRING.get::<String>(options)? {
+                Some(conn_str) => conn_str,
+                None => {
+                    let conn_str_id: String = CONN_STRING_ID.require(options)?;
+                    get_vault_secret(&conn_str_id)?
+                }
+            };
+            let client = RefCell::new(create_client(&rt, &conn_str)?);
+            Ok(Connection { rt, client })
+        })?;
 
-        Self {
-            rt,
-            client,
+        Ok(Self {
+            conn,
             table: "".to_string(),
             rowid_col: "".to_string(),
             tgt_cols: Vec::new(),
+            qual_exprs: Vec::new(),
             scan_blk: None,
             row_idx: 0,
-        }
+            requests: 0,
+        })
+    }
+
+    fn can_estimate_rel_size(_options: &HashMap<String, String>) -> bool {
+        true
     }
 
     fn get_rel_size(
@@ -145,164 +334,465 @@ impl ForeignDataWrapper for ClickHouseFdw {
         _sorts: &[Sort],
         _limit: &Option<Limit>,
         options: &HashMap<String, String>,
-    ) -> (i64, i32) {
-        let table = require_option("table", options);
-        let rowid_col = require_option("rowid_column", options);
-        if table.is_none() || rowid_col.is_none() {
-            return (0, 0);
-        }
-        self.table = table.unwrap();
-        self.rowid_col = rowid_col.unwrap();
-        self.tgt_cols = columns.to_vec();
+    ) -> FdwResult<(i64, i32)> {
+        let table = TABLE.require::<String>(options)?;
 
-        let sql = self.deparse(quals, columns);
-
-        if let Some(ref mut client) = self.client {
-            // for simplicity purpose, we fetch whole query result to local,
-            // may need optimization in the future.
-            match self.rt.block_on(client.query(&sql).fetch_all()) {
-                Ok(block) => {
-                    let rows = block.row_count();
-                    let width = block.column_count() * 8;
-                    self.scan_blk = Some(block);
-                    return (rows as i64, width as i32);
-                }
-                Err(err) => report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("query failed: {}", err),
-                ),
-            }
-        }
-        (0, 0)
+        // only count the rows when planning, so that the whole query result
+        // isn't fetched if the query is pushed down as an aggregation.
+        // Parameter values are not known yet, so their quals are skipped.
+        let quals: Vec<Qual> = DEPARSER
+            .sendable_quals(quals)
+            .into_iter()
+            .filter(|qual| qual.param.is_none())
+            .collect();
+        let sql = format!(
+            "select count() from ({})",
+            DEPARSER.select(&table, columns, &quals, &[], &[], &None)?
+        );
+        let block = self
+            .conn
+            .rt
+            .block_on(self.conn.client.borrow_mut().query(&sql).fetch_all())
+            .map_err(|err| format!("query failed: {}", err))?;
+        let rows = block
+            .get::<u64, usize>(0, 0)
+            .map_err(|err| format!("query failed: {}", err))?;
+        let width = columns.len() * 8;
+        Ok((rows as i64, width as i32))
+    }
+
+    fn can_push_down_quals(
+        &mut self,
+        quals: &[Qual],
+        _options: &HashMap<String, String>,
+    ) -> FdwResult<Vec<bool>> {
+        Ok(quals.iter().map(|q| DEPARSER.is_remote_qual(q)).collect())
+    }
+
+    fn can_push_down_qual_exprs(
+        &mut self,
+        exprs: &[QualExpr],
+        _options: &HashMap<String, String>,
+    ) -> FdwResult<Vec<bool>> {
+        Ok(exprs
+            .iter()
+            .map(|expr| DEPARSER.is_remote_qual_expr(expr))
+            .collect())
+    }
+
+    fn begin_scan_qual_exprs(&mut self, exprs: &[QualExpr]) -> FdwResult<()> {
+        self.qual_exprs = exprs.to_vec();
+        Ok(())
     }
 
     fn begin_scan(
         &mut self,
-        _quals: &[Qual],
-        _columns: &[Column],
-        _sorts: &[Sort],
+        quals: &[Qual],
+        columns: &[Column],
+        sorts: &[Sort],
         _limit: &Option<Limit>,
-        _options: &HashMap<String, String>,
-    ) {
-        self.row_idx = 0;
+        options: &HashMap<String, String>,
+    ) -> FdwResult<()> {
+        self.table = TABLE.require::<String>(options)?;
+        self.tgt_cols = columns.to_vec();
+
+        // for simplicity purpose, we fetch whole query result to local,
+        // may need optimization in the future.
+        let sql = self.deparse_scan(&self.table, quals, columns, sorts)?;
+
+        self.fetch(&sql)
     }
 
-    fn iter_scan(&mut self, row: &mut Row) -> Option<()> {
+    fn iter_scan(&mut self, row: &mut Row) -> FdwResult<Option<()>> {
         if let Some(block) = &self.scan_blk {
-            let mut rows = block.rows();
-
-            if let Some(src_row) = rows.nth(self.row_idx) {
-                for tgt_col in &self.tgt_cols {
-                    let (i, _) = block
-                        .columns()
-                        .iter()
-                        .enumerate()
-                        .find(|(_, c)| c.name() == tgt_col.name)
-                        .unwrap();
-                    let col_name = src_row.name(i).unwrap();
-                    let cell = field_to_cell(&src_row, i);
-                    cell.as_ref()?;
+            if self.row_idx < block.row_count() {
+                // query result columns are in the same order as target columns
+                for (i, tgt_col) in self.tgt_cols.iter().enumerate() {
+                    let col_name = block.columns()[i].name();
+                    let cell = field_to_cell(block, self.row_idx, i)?
+                        .map(|cell| cast_cell(cell, tgt_col.type_oid))
+                        .transpose()?;
                     row.push(col_name, cell);
                 }
                 self.row_idx += 1;
-                return Some(());
+                return Ok(Some(()));
             }
         }
-        None
+        Ok(None)
     }
 
-    fn end_scan(&mut self) {
-        self.scan_blk.take();
+    fn scan_batch_size(_options: &HashMap<String, String>) -> Option<usize> {
+        Some(SCAN_BATCH_SIZE)
     }
 
-    fn begin_modify(&mut self, options: &HashMap<String, String>) {
-        let table = require_option("table", options);
-        let rowid_col = require_option("rowid_column", options);
-        if table.is_none() || rowid_col.is_none() {
-            return;
-        }
-        self.table = table.unwrap();
-        self.rowid_col = rowid_col.unwrap();
-    }
-
-    fn insert(&mut self, src: &Row) {
-        if let Some(ref mut client) = self.client {
-            let mut row = Vec::new();
-            for (col_name, cell) in src.iter() {
-                let col_name = col_name.to_owned();
-                if let Some(cell) = cell {
-                    match cell {
-                        Cell::Bool(v) => row.push((col_name, types::Value::from(*v))),
-                        Cell::F64(v) => row.push((col_name, types::Value::from(*v))),
-                        Cell::I64(v) => row.push((col_name, types::Value::from(*v))),
-                        Cell::String(v) => row.push((col_name, types::Value::from(v.as_str()))),
-                        _ => report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
-                            &format!("field type {:?} not supported", cell),
-                        ),
-                    }
+    fn iter_scan_batch(&mut self, batch: &mut RowBatch) -> FdwResult<usize> {
+        if let Some(block) = &self.scan_blk {
+            let end = block.row_count().min(self.row_idx + batch.capacity());
+            for row_idx in self.row_idx..end {
+                // query result columns are in the same order as target columns
+                let cells = batch.push_row();
+                for (i, tgt_col) in self.tgt_cols.iter().enumerate() {
+                    cells[i] = field_to_cell(block, row_idx, i)?
+                        .map(|cell| cast_cell(cell, tgt_col.type_oid))
+                        .transpose()?;
                 }
             }
-            let mut block = Block::new();
-            block.push(row).unwrap();
-
-            // execute query on ClickHouse
-            if let Err(err) = self.rt.block_on(client.insert(&self.table, block)) {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("insert failed: {}", err),
-                );
+            self.row_idx = end;
+        }
+        Ok(batch.len())
+    }
+
+    fn end_scan(&mut self) -> FdwResult<()> {
+        self.scan_blk.take();
+        Ok(())
+    }
+
+    fn explain_scan(
+        &self,
+        quals: &[Qual],
+        columns: &[Column],
+        sorts: &[Sort],
+        _limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+        _verbose: bool,
+    ) -> FdwResult<Vec<(String, String)>> {
+        let table = TABLE.require::<String>(options)?;
+        let sql = self.deparse_scan(&table, quals, columns, sorts)?;
+        Ok(self.explain_sql(sql))
+    }
+
+    fn is_parallel_safe(_options: &HashMap<String, String>) -> bool {
+        true
+    }
+
+    fn get_parallel_partitions(
+        &mut self,
+        _quals: &[Qual],
+        options: &HashMap<String, String>,
+    ) -> FdwResult<usize> {
+        if !options.contains_key(PARTITION_COLUMN.name) {
+            return Ok(1);
+        }
+        match PARALLEL_PARTITIONS.require::<usize>(options)? {
+            0 => Err(FdwError::new(
+                PgSqlErrorCode::ERRCODE_FDW_INVALID_OPTION_NAME,
+                "invalid parallel_partitions value: 0",
+            )),
+            partitions => Ok(partitions),
+        }
+    }
+
+    fn begin_parallel_scan(
+        &mut self,
+        quals: &[Qual],
+        columns: &[Column],
+        options: &HashMap<String, String>,
+        partition: usize,
+        partitions: usize,
+    ) -> FdwResult<()> {
+        self.table = TABLE.require::<String>(options)?;
+        self.tgt_cols = columns.to_vec();
+        let partition_col: String = PARTITION_COLUMN.require(options)?;
+        let sql = self.deparse_partition_scan(
+            &self.table,
+            quals,
+            columns,
+            &partition_col,
+            partition,
+            partitions,
+        )?;
+        self.fetch(&sql)
+    }
+
+    fn can_push_down_sort(&mut self, sorts: &[Sort]) -> FdwResult<bool> {
+        // collations are not compatible with ClickHouse's
+        Ok(sorts.iter().all(|sort| sort.collate.is_none()))
+    }
+
+    fn can_push_down_join(&mut self, join: &Join) -> FdwResult<bool> {
+        // ClickHouse fills unmatched rows of outer joins with default values
+        // rather than nulls, so only inner equi-join is pushed down
+        Ok(join.join_type == JoinType::Inner
+            && join.quals.iter().all(|q| q.operator == "=")
+            && join
+                .outer
+                .quals
+                .iter()
+                .chain(join.inner.quals.iter())
+                .all(|q| DEPARSER.is_remote_qual(q))
+            && join.outer.options.contains_key("table")
+            && join.inner.options.contains_key("table"))
+    }
+
+    fn begin_join_scan(&mut self, join: &Join) -> FdwResult<()> {
+        self.tgt_cols = join
+            .outer
+            .columns
+            .iter()
+            .chain(join.inner.columns.iter())
+            .cloned()
+            .collect();
+        let sql = Self::deparse_join(join)?;
+        self.fetch(&sql)
+    }
+
+    fn explain_join_scan(&self, join: &Join, _verbose: bool) -> FdwResult<Vec<(String, String)>> {
+        let sql = Self::deparse_join(join)?;
+        Ok(self.explain_sql(sql))
+    }
+
+    fn can_push_down_aggregate(&mut self, aggregate: &Aggregate) -> FdwResult<bool> {
+        // ClickHouse returns 0 or nan rather than null for the aggregates of
+        // empty input, so only count is pushed down without grouping columns,
+        // as there is no empty group otherwise
+        let funcs: &[&str] = if aggregate.group_by.is_empty() {
+            &["count"]
+        } else {
+            &["count", "sum", "avg", "min", "max"]
+        };
+        Ok(aggregate
+            .funcs
+            .iter()
+            .all(|f| funcs.contains(&f.name.as_str()))
+            && aggregate.quals.iter().all(|q| DEPARSER.is_remote_qual(q))
+            && aggregate
+                .having
+                .iter()
+                .all(|q| DEPARSER.is_remote_having_qual(q))
+            && aggregate.options.contains_key("table"))
+    }
+
+    fn begin_aggregate_scan(&mut self, aggregate: &Aggregate) -> FdwResult<()> {
+        self.tgt_cols = aggregate.group_by.clone();
+        self.tgt_cols.extend(aggregate.funcs.iter().map(|f| Column {
+            name: DEPARSER.aggregate_func(f),
+            type_oid: f.type_oid,
+            ..Default::default()
+        }));
+        let sql = Self::deparse_aggregate(aggregate)?;
+        self.fetch(&sql)
+    }
+
+    fn explain_aggregate_scan(
+        &self,
+        aggregate: &Aggregate,
+        _verbose: bool,
+    ) -> FdwResult<Vec<(String, String)>> {
+        let sql = Self::deparse_aggregate(aggregate)?;
+        Ok(self.explain_sql(sql))
+    }
+
+    fn can_analyze(_options: &HashMap<String, String>) -> bool {
+        true
+    }
+
+    fn analyze(
+        &mut self,
+        columns: &[Column],
+        sample_size: usize,
+        options: &HashMap<String, String>,
+    ) -> FdwResult<(i64, Vec<Row>)> {
+        let table = TABLE.require::<String>(options)?;
+
+        let sql = format!("select count() from {}", DEPARSER.quote_table(&table));
+        let block = self
+            .conn
+            .rt
+            .block_on(self.conn.client.borrow_mut().query(&sql).fetch_all())
+            .map_err(|err| format!("query failed: {}", err))?;
+        let total = block
+            .get::<u64, usize>(0, 0)
+            .map_err(|err| format!("query failed: {}", err))?;
+
+        // let ClickHouse pick the random sample rows
+        let sql = format!(
+            "{} order by rand() limit {}",
+            DEPARSER.select(&table, columns, &[], &[], &[], &None)?,
+            sample_size
+        );
+        let block = self
+            .conn
+            .rt
+            .block_on(self.conn.client.borrow_mut().query(&sql).fetch_all())
+            .map_err(|err| format!("query failed: {}", err))?;
+        let mut rows = Vec::new();
+        for row_idx in 0..block.row_count() {
+            let mut row = Row::new();
+            for (i, col) in columns.iter().enumerate() {
+                let cell = field_to_cell(&block, row_idx, i)?
+                    .map(|cell| cast_cell(cell, col.type_oid))
+                    .transpose()?;
+                row.push(&col.name, cell);
             }
+            rows.push(row);
         }
+
+        Ok((total as i64, rows))
     }
 
-    fn update(&mut self, rowid: &Cell, new_row: &Row) {
-        if let Some(ref mut client) = self.client {
-            let mut sets = Vec::new();
-            for (col, cell) in new_row.iter() {
-                if col == &self.rowid_col {
-                    continue;
-                }
-                if let Some(cell) = cell {
-                    sets.push(format!("{} = {}", col, cell));
-                } else {
-                    sets.push(format!("{} = null", col));
-                }
+    fn import_foreign_schema(&mut self, stmt: ImportForeignSchemaStmt) -> FdwResult<Vec<String>> {
+        let sql = format!(
+            "select table, name, type, is_in_primary_key from system.columns
+             where database = {} order by table, position",
+            DEPARSER.quote_string(&stmt.remote_schema)
+        );
+        let block = self
+            .conn
+            .rt
+            .block_on(self.conn.client.borrow_mut().query(&sql).fetch_all())
+            .map_err(|err| format!("query failed: {}", err))?;
+
+        // group columns by table, the result is already ordered by table name
+        let mut tables: Vec<(String, Vec<String>, Option<String>)> = Vec::new();
+        let invalid_column =
+            |err: clickhouse_rs::errors::Error| format!("invalid system.columns result: {}", err);
+        for row in block.rows() {
+            let table: String = row.get("table").map_err(invalid_column)?;
+            let col_name: String = row.get("name").map_err(invalid_column)?;
+            let col_type: String = row.get("type").map_err(invalid_column)?;
+            let is_pk: u8 = row.get("is_in_primary_key").map_err(invalid_column)?;
+
+            if !stmt.is_table_included(&table) {
+                continue;
             }
-            let sql = format!(
-                "alter table {} update {} where {} = {}",
-                self.table,
-                sets.join(", "),
-                self.rowid_col,
-                rowid
-            );
-
-            // execute query on ClickHouse
-            if let Err(err) = self.rt.block_on(client.execute(&sql)) {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("update failed: {}", err),
-                );
+
+            // skip columns whose data type is not supported
+            let pg_type = match ch_type_to_pg_type(&col_type) {
+                Some(pg_type) => pg_type,
+                None => continue,
+            };
+
+            if tables.last().map(|(t, _, _)| t != &table).unwrap_or(true) {
+                tables.push((table.clone(), Vec::new(), None));
+            }
+            let (_, cols, rowid_col) = tables.last_mut().unwrap();
+            cols.push(format!("{} {}", quote_identifier(&col_name), pg_type));
+            if is_pk != 0 && rowid_col.is_none() {
+                *rowid_col = Some(col_name);
             }
         }
+
+        let ret = tables
+            .iter()
+            .map(|(table, cols, rowid_col)| {
+                // quote the remote name here, so database and table names
+                // containing dots or other special chars are kept intact
+                let remote_table = format!(
+                    "{}.{}",
+                    DEPARSER.quote_ident(&stmt.remote_schema),
+                    DEPARSER.quote_ident(table)
+                );
+                let mut opts = vec![format!("table {}", quote_literal(&remote_table))];
+                if let Some(rowid_col) = rowid_col {
+                    opts.push(format!("rowid_column {}", quote_literal(rowid_col)));
+                }
+                format!(
+                    "create foreign table if not exists {} ({}) server {} options ({})",
+                    quote_identifier(table),
+                    cols.join(", "),
+                    quote_identifier(&stmt.server_name),
+                    opts.join(", ")
+                )
+            })
+            .collect();
+        Ok(ret)
     }
 
-    fn delete(&mut self, rowid: &Cell) {
-        if let Some(ref mut client) = self.client {
-            let sql = format!(
-                "alter table {} delete where {} = {}",
-                self.table, self.rowid_col, rowid
-            );
+    fn begin_modify(&mut self, options: &HashMap<String, String>) -> FdwResult<()> {
+        self.table = TABLE.require::<String>(options)?;
+        self.rowid_col = require_option("rowid_column", options)?;
+        Ok(())
+    }
 
-            // execute query on ClickHouse
-            if let Err(err) = self.rt.block_on(client.execute(&sql)) {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("delete failed: {}", err),
-                );
-            }
+    fn insert(&mut self, src: &Row) -> FdwResult<Option<Row>> {
+        self.insert_batch(std::slice::from_ref(src))?;
+        Ok(None)
+    }
+
+    fn insert_batch(&mut self, rows: &[Row]) -> FdwResult<()> {
+        // put all rows in one block, so they can be inserted in one request
+        let mut block = Block::new();
+        for src in rows {
+            block
+                .push(row_to_ch_row(src)?)
+                .map_err(|err| format!("build insert block failed: {}", err))?;
+        }
+
+        // execute query on ClickHouse
+        self.requests += 1;
+        self.conn
+            .rt
+            .block_on(self.conn.client.borrow_mut().insert(&self.table, block))
+            .map_err(|err| format!("insert failed: {}", err))?;
+        Ok(())
+    }
+
+    fn update(&mut self, rowid: &Cell, new_row: &Row) -> FdwResult<Option<Row>> {
+        let sql = DEPARSER.update(&self.table, new_row, &self.rowid_col, rowid)?;
+
+        // execute query on ClickHouse
+        self.requests += 1;
+        self.conn
+            .rt
+            .block_on(self.conn.client.borrow_mut().execute(&sql))
+            .map_err(|err| format!("update failed: {}", err))?;
+        Ok(None)
+    }
+
+    fn delete(&mut self, rowid: &Cell) -> FdwResult<Option<Row>> {
+        let sql = DEPARSER.delete(&self.table, &self.rowid_col, rowid)?;
+
+        // execute query on ClickHouse
+        self.requests += 1;
+        self.conn
+            .rt
+            .block_on(self.conn.client.borrow_mut().execute(&sql))
+            .map_err(|err| format!("delete failed: {}", err))?;
+        Ok(None)
+    }
+
+    fn end_modify(&mut self) -> FdwResult<()> {
+        Ok(())
+    }
+
+    fn explain_modify(
+        &self,
+        options: &HashMap<String, String>,
+        _verbose: bool,
+    ) -> FdwResult<Vec<(String, String)>> {
+        let table = TABLE.require::<String>(options)?;
+        let mut ret = vec![("Remote Table".to_string(), DEPARSER.quote_table(&table))];
+        if self.requests > 0 {
+            ret.push(("Remote Requests".to_string(), self.requests.to_string()));
         }
+        Ok(ret)
     }
 
-    fn end_modify(&mut self) {}
+    fn truncate(
+        &mut self,
+        options: &HashMap<String, String>,
+        _restart_seqs: bool,
+        _cascade: bool,
+    ) -> FdwResult<()> {
+        let table = TABLE.require::<String>(options)?;
+        let sql = DEPARSER.truncate(&table);
+
+        // execute query on ClickHouse
+        self.requests += 1;
+        self.conn
+            .rt
+            .block_on(self.conn.client.borrow_mut().execute(&sql))
+            .map_err(|err| format!("truncate failed: {}", err))?;
+        Ok(())
+    }
+
+    fn options() -> &'static [OptionDef] {
+        &[
+            CONN_STRING,
+            CONN_STRING_ID,
+            TABLE,
+            PARTITION_COLUMN,
+            PARALLEL_PARTITIONS,
+        ]
+    }
 }
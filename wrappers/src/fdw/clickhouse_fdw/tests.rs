@@ -3,53 +3,119 @@
 mod tests {
     use clickhouse_rs as ch;
     use pgx::prelude::*;
+    use pgx::spi::SpiClient;
     use pgx::{pg_test, IntoDatum};
     use supabase_wrappers::prelude::create_async_runtime;
 
-    #[pg_test]
-    fn clickhouse_smoketest() {
-        Spi::execute(|c| {
-            let clickhouse_pool = ch::Pool::new("tcp://default:@localhost:9000/supa");
+    const CONN_STRING: &str = "tcp://default:@localhost:9000/supa";
 
-            let rt = create_async_runtime();
-            let mut handle = rt
-                .block_on(async { clickhouse_pool.get_handle().await })
-                .expect("handle");
-
-            rt.block_on(async {
+    // create a table in ClickHouse, replacing the existing one, and fill it
+    // with `rows`, which is a VALUES or SELECT clause
+    fn create_remote_table(table: &str, columns: &str, rows: &str) {
+        let clickhouse_pool = ch::Pool::new(CONN_STRING);
+        let rt = create_async_runtime();
+        rt.block_on(async {
+            let mut handle = clickhouse_pool.get_handle().await?;
+            handle
+                .execute(format!("DROP TABLE IF EXISTS supa.{}", table))
+                .await?;
+            handle
+                .execute(format!(
+                    "CREATE TABLE supa.{} ({}) engine = Memory",
+                    table, columns
+                ))
+                .await?;
+            if !rows.is_empty() {
                 handle
-                    .execute("DROP TABLE IF EXISTS supa.test_table")
+                    .execute(format!("INSERT INTO supa.{} {}", table, rows))
                     .await?;
-                handle
-                    .execute("CREATE TABLE supa.test_table (id INT, name TEXT) engine = Memory")
-                    .await
-            })
-            .expect("test_table in ClickHouse");
+            }
+            Ok::<_, ch::errors::Error>(())
+        })
+        .expect("table in ClickHouse");
+    }
 
-            c.update(
-                r#"CREATE FOREIGN DATA WRAPPER clickhouse_wrapper
-                         HANDLER click_house_fdw_handler VALIDATOR click_house_fdw_validator"#,
-                None,
-                None,
-            );
-            c.update(
+    // create the foreign data wrapper and a server of the local ClickHouse
+    fn create_server(c: &SpiClient) {
+        c.update(
+            r#"CREATE FOREIGN DATA WRAPPER clickhouse_wrapper
+                     HANDLER click_house_fdw_handler VALIDATOR click_house_fdw_validator"#,
+            None,
+            None,
+        );
+        c.update(
+            &format!(
                 r#"CREATE SERVER my_clickhouse_server
-                         FOREIGN DATA WRAPPER clickhouse_wrapper
-                         OPTIONS (
-                           conn_string 'tcp://default:@localhost:9000/supa'
-                         )"#,
-                None,
-                None,
-            );
+                     FOREIGN DATA WRAPPER clickhouse_wrapper
+                     OPTIONS (
+                       conn_string '{}'
+                     )"#,
+                CONN_STRING
+            ),
+            None,
+            None,
+        );
+    }
+
+    // create a ClickHouse table of `id` and `name` columns, which has a row
+    // named 'test' and 25 rows named 'batch', and its foreign table
+    fn create_test_table(c: &SpiClient, table: &str) {
+        create_remote_table(
+            table,
+            "id Int64, name String",
+            "SELECT 1, 'test' UNION ALL SELECT number + 2, 'batch' FROM numbers(25)",
+        );
+        create_server(c);
+        c.update(
+            &format!(
+                r#"
+                  CREATE FOREIGN TABLE {} (
+                    id bigint,
+                    name text
+                  )
+                  SERVER my_clickhouse_server
+                  OPTIONS (
+                    table '{}',
+                    rowid_column 'id'
+                  )
+                "#,
+                table, table
+            ),
+            None,
+            None,
+        );
+    }
+
+    // get the EXPLAIN output lines of a query
+    fn explain(c: &SpiClient, sql: &str) -> Vec<String> {
+        c.select(&format!("EXPLAIN {}", sql), None, None)
+            .filter_map(|r| r.by_ordinal(1).ok().and_then(|v| v.value::<String>()))
+            .collect()
+    }
+
+    // get the query sent to ClickHouse from the EXPLAIN output of a query
+    fn remote_sql(c: &SpiClient, sql: &str) -> String {
+        explain(c, sql)
+            .iter()
+            .find_map(|line| line.trim_start().strip_prefix("Remote SQL: "))
+            .map(|sql| sql.to_owned())
+            .expect("Remote SQL in EXPLAIN")
+    }
+
+    #[pg_test]
+    fn clickhouse_insert() {
+        Spi::execute(|c| {
+            create_remote_table("test_insert", "id INT, name TEXT", "");
+            create_server(&c);
             c.update(
                 r#"
-                  CREATE FOREIGN TABLE test_table (
+                  CREATE FOREIGN TABLE test_insert (
                     id bigint,
                     name text
                   )
                   SERVER my_clickhouse_server
                   OPTIONS (
-                    table 'test_table',
+                    table 'test_insert',
                     rowid_column 'id'
                   )
              "#,
@@ -57,9 +123,9 @@ mod tests {
                 None,
             );
 
-            assert_eq!(c.select("SELECT * FROM test_table", None, None).len(), 0);
+            assert_eq!(c.select("SELECT * FROM test_insert", None, None).len(), 0);
             c.update(
-                "INSERT INTO test_table (name) VALUES ($1)",
+                "INSERT INTO test_insert (name) VALUES ($1)",
                 None,
                 Some(vec![(
                     PgOid::BuiltIn(PgBuiltInOids::TEXTOID),
@@ -67,17 +133,21 @@ mod tests {
                 )]),
             );
             assert_eq!(
-                c.select("SELECT name FROM test_table", None, None)
+                c.select("SELECT name FROM test_insert", None, None)
                     .first()
                     .get_one::<&str>()
                     .unwrap(),
                 "test"
             );
 
+            let clickhouse_pool = ch::Pool::new(CONN_STRING);
+            let rt = create_async_runtime();
             let remote_value: String = rt
                 .block_on(async {
-                    handle
-                        .query("SELECT name FROM supa.test_table")
+                    clickhouse_pool
+                        .get_handle()
+                        .await?
+                        .query("SELECT name FROM supa.test_insert")
                         .fetch_all()
                         .await?
                         .rows()
@@ -87,6 +157,477 @@ mod tests {
                 })
                 .expect("value");
             assert_eq!(remote_value, "test");
+
+            // test batch insert
+            c.update(
+                "ALTER FOREIGN TABLE test_insert OPTIONS (ADD batch_size '10')",
+                None,
+                None,
+            );
+            c.update(
+                "INSERT INTO test_insert (name) SELECT 'batch' FROM generate_series(1, 25)",
+                None,
+                None,
+            );
+            assert_eq!(
+                c.select("SELECT * FROM test_insert WHERE name = 'batch'", None, None)
+                    .len(),
+                25
+            );
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_remote_quals() {
+        Spi::execute(|c| {
+            create_test_table(&c, "test_quals");
+
+            // test quals evaluated remotely are not rechecked locally
+            let sql = "SELECT * FROM test_quals WHERE name = 'batch'";
+            assert_eq!(
+                remote_sql(&c, sql),
+                "select `id`, `name` from `test_quals` where `name` = 'batch'"
+            );
+            assert!(!explain(&c, sql).iter().any(|line| line.contains("Filter:")));
+            assert_eq!(c.select(sql, None, None).len(), 25);
+
+            let plan = explain(&c, &format!("ANALYZE {}", sql));
+            assert!(plan.iter().any(|line| line.contains("Remote Requests: 1")));
+            assert!(plan
+                .iter()
+                .any(|line| line.contains("Wrappers: elapsed = ")));
+
+            // test literals are escaped
+            c.update(
+                "INSERT INTO test_quals (id, name) VALUES (100, $1)",
+                None,
+                Some(vec![(
+                    PgOid::BuiltIn(PgBuiltInOids::TEXTOID),
+                    "O'Brien\\".into_datum(),
+                )]),
+            );
+            let sql = r#"SELECT name FROM test_quals WHERE name = 'O''Brien\'"#;
+            assert_eq!(
+                c.select(sql, None, None).first().get_one::<&str>().unwrap(),
+                "O'Brien\\"
+            );
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_qual_exprs() {
+        Spi::execute(|c| {
+            create_test_table(&c, "test_qual_exprs");
+
+            // test boolean condition push down, Postgres turns the NOT into
+            // the negator of its operator
+            let sql =
+                "SELECT name FROM test_qual_exprs WHERE name = 'test' OR NOT (name <> 'none')";
+            assert_eq!(
+                remote_sql(&c, sql),
+                "select `name` from `test_qual_exprs` where ((`name` = 'test') or (`name` = 'none'))"
+            );
+            assert!(!explain(&c, sql).iter().any(|line| line.contains("Filter:")));
+            assert_eq!(
+                c.select(sql, None, None).first().get_one::<&str>().unwrap(),
+                "test"
+            );
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_cost_estimate() {
+        Spi::execute(|c| {
+            create_test_table(&c, "test_estimate");
+
+            // test cost options and row count of a table not analyzed yet,
+            // which is counted on ClickHouse
+            c.update(
+                "ALTER FOREIGN TABLE test_estimate OPTIONS (ADD fdw_startup_cost '42')",
+                None,
+                None,
+            );
+            let plan = explain(&c, "SELECT * FROM test_estimate");
+            assert!(plan[0].contains("(cost=42.00.."));
+            assert!(plan[0].contains(" rows=26 "));
+
+            // test local row count estimate after analyze
+            create_remote_table(
+                "test_estimate",
+                "id Int64, name String",
+                "SELECT number, 'batch' FROM numbers(10)",
+            );
+            c.update("ANALYZE test_estimate", None, None);
+            c.update(
+                "INSERT INTO test_estimate (id, name) VALUES (100, 'test')",
+                None,
+                None,
+            );
+            let plan = explain(&c, "SELECT * FROM test_estimate");
+            assert!(plan[0].contains(" rows=10 "));
+
+            // test remote row count estimate
+            c.update(
+                "ALTER FOREIGN TABLE test_estimate OPTIONS (ADD use_remote_estimate 'true')",
+                None,
+                None,
+            );
+            let plan = explain(&c, "SELECT * FROM test_estimate");
+            assert!(plan[0].contains(" rows=11 "));
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_parallel_scan() {
+        Spi::execute(|c| {
+            create_test_table(&c, "test_partitions");
+
+            // test parallel scan split into partitions
+            c.update(
+                "ALTER FOREIGN TABLE test_partitions OPTIONS (ADD partition_column 'id', ADD parallel_partitions '4')",
+                None,
+                None,
+            );
+            c.update("SET parallel_setup_cost = 0", None, None);
+            c.update("SET parallel_tuple_cost = 0", None, None);
+            let sql = "SELECT name FROM test_partitions WHERE name = 'batch'";
+            let plan = explain(&c, sql);
+            assert!(plan[0].starts_with("Gather"));
+            assert!(plan
+                .iter()
+                .any(|line| line.contains("parallel partitions = 4")));
+            assert_eq!(c.select(sql, None, None).len(), 25);
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_param_quals() {
+        Spi::execute(|c| {
+            create_test_table(&c, "test_params");
+
+            // test quals with parameters of a prepared statement
+            c.update("SET plan_cache_mode = force_generic_plan", None, None);
+            c.update(
+                "PREPARE get_by_name(text) AS SELECT name FROM test_params WHERE name = $1",
+                None,
+                None,
+            );
+            assert_eq!(
+                c.select("EXECUTE get_by_name('test')", None, None)
+                    .first()
+                    .get_one::<&str>()
+                    .unwrap(),
+                "test"
+            );
+            assert_eq!(
+                remote_sql(&c, "ANALYZE EXECUTE get_by_name('test')"),
+                "select `name` from `test_params` where `name` = 'test'"
+            );
+
+            // test quals with parameters from the outer relation of a nested
+            // loop join, the scan is sent once for each outer row
+            c.update("SET enable_hashjoin = off", None, None);
+            c.update("SET enable_mergejoin = off", None, None);
+            let sql = "SELECT t.name FROM (VALUES ('test'), ('none')) AS v(name) JOIN test_params t ON t.name = v.name";
+            let plan = explain(&c, &format!("ANALYZE {}", sql));
+            assert!(plan.iter().any(|line| line.contains("Remote Requests: 2")));
+            assert!(plan.iter().any(|line| line
+                .contains("Remote SQL: select `name` from `test_params` where `name` = '")));
+            assert_eq!(
+                c.select(sql, None, None).first().get_one::<&str>().unwrap(),
+                "test"
+            );
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_join_push_down() {
+        Spi::execute(|c| {
+            create_test_table(&c, "test_join");
+
+            let sql = "SELECT a.name, b.name FROM test_join a JOIN test_join b ON a.name = b.name";
+            assert_eq!(
+                remote_sql(&c, sql),
+                "select r1.`name`, r2.`name` from (select `name` from `test_join`) as r1 \
+                 inner join (select `name` from `test_join`) as r2 on r1.`name` = r2.`name`"
+            );
+            // 'test' matches itself and each 'batch' matches all of them
+            assert_eq!(c.select(sql, None, None).len(), 1 + 25 * 25);
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_aggregate_push_down() {
+        Spi::execute(|c| {
+            create_test_table(&c, "test_aggregate");
+
+            let sql =
+                "SELECT name, count(*) AS cnt FROM test_aggregate GROUP BY name HAVING count(*) > 1";
+            assert_eq!(
+                remote_sql(&c, sql),
+                "select `name`, count(*) from `test_aggregate` group by `name` having count(*) > 1"
+            );
+            let results = c
+                .select(sql, None, None)
+                .filter_map(|r| {
+                    r.by_name("name")
+                        .ok()
+                        .and_then(|v| v.value::<&str>())
+                        .zip(r.by_name("cnt").ok().and_then(|v| v.value::<i64>()))
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![("batch", 25)]);
+
+            // test aggregates of an empty table are null, same as Postgres
+            c.update("TRUNCATE test_aggregate", None, None);
+            let sql = "SELECT sum(id) IS NULL AND avg(id) IS NULL AND max(id) IS NULL AND count(*) = 0 FROM test_aggregate";
+            assert!(c.select(sql, None, None).first().get_one::<bool>().unwrap());
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_sort_push_down() {
+        Spi::execute(|c| {
+            create_test_table(&c, "test_sort");
+
+            let sql = "SELECT name FROM test_sort ORDER BY name DESC";
+            assert!(explain(&c, sql)[0].starts_with("Foreign Scan"));
+            assert_eq!(
+                remote_sql(&c, sql),
+                "select `name` from `test_sort` order by `name` desc nulls first"
+            );
+            assert_eq!(
+                c.select(sql, None, None).first().get_one::<&str>().unwrap(),
+                "test"
+            );
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_arrays() {
+        Spi::execute(|c| {
+            create_remote_table(
+                "test_arrays",
+                "id Int64, nums Array(Int64), tags Array(String)",
+                "VALUES (1, [1, 2], ['a', 'b']), (2, [], [])",
+            );
+            create_server(&c);
+            c.update(
+                r#"
+                  CREATE FOREIGN TABLE test_arrays (
+                    id bigint,
+                    nums bigint[],
+                    tags text[]
+                  )
+                  SERVER my_clickhouse_server
+                  OPTIONS (
+                    table 'test_arrays'
+                  )
+             "#,
+                None,
+                None,
+            );
+            let sql = "SELECT nums = ARRAY[1, 2]::bigint[] AND tags = ARRAY['a', 'b'] FROM test_arrays WHERE id = 1";
+            assert!(c.select(sql, None, None).first().get_one::<bool>().unwrap());
+            let sql = "SELECT cardinality(nums) FROM test_arrays WHERE id = 2";
+            assert_eq!(
+                c.select(sql, None, None).first().get_one::<i32>().unwrap(),
+                0
+            );
+
+            // test analyze, row count and column stats are collected
+            c.update("ANALYZE test_arrays", None, None);
+            let sql = "SELECT reltuples::bigint FROM pg_class WHERE relname = 'test_arrays'";
+            assert_eq!(
+                c.select(sql, None, None).first().get_one::<i64>().unwrap(),
+                2
+            );
+            let sql = "SELECT count(*) FROM pg_stats WHERE tablename = 'test_arrays'";
+            assert_eq!(
+                c.select(sql, None, None).first().get_one::<i64>().unwrap(),
+                3
+            );
+
+            // test truncate
+            c.update("TRUNCATE test_arrays", None, None);
+            assert_eq!(c.select("SELECT * FROM test_arrays", None, None).len(), 0);
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_connection_cache() {
+        Spi::execute(|c| {
+            create_server(&c);
+            c.update(
+                r#"
+                  CREATE FOREIGN TABLE test_connection (
+                    id bigint
+                  )
+                  SERVER my_clickhouse_server
+                  OPTIONS (
+                    table '(select toInt64(1) as id)'
+                  )
+             "#,
+                None,
+                None,
+            );
+            let sql = "SELECT * FROM test_connection";
+            assert_eq!(c.select(sql, None, None).len(), 1);
+
+            // test connection is cached and can be dropped
+            let conn_sql = "SELECT server_name FROM click_house_fdw_get_connections()";
+            assert_eq!(
+                c.select(conn_sql, None, None)
+                    .first()
+                    .get_one::<&str>()
+                    .unwrap(),
+                "my_clickhouse_server"
+            );
+            assert!(c
+                .select(
+                    "SELECT click_house_fdw_disconnect('my_clickhouse_server')",
+                    None,
+                    None
+                )
+                .first()
+                .get_one::<bool>()
+                .unwrap());
+            assert_eq!(c.select(conn_sql, None, None).len(), 0);
+
+            // test cached connection is dropped when server is altered
+            assert_eq!(c.select(sql, None, None).len(), 1);
+            assert_eq!(c.select(conn_sql, None, None).len(), 1);
+            c.update(
+                "ALTER SERVER my_clickhouse_server OPTIONS (ADD batch_size '10')",
+                None,
+                None,
+            );
+            assert_eq!(c.select(conn_sql, None, None).len(), 0);
+
+            // test user mapping options take precedence over server options
+            c.update(
+                "ALTER SERVER my_clickhouse_server OPTIONS (SET conn_string 'tcp://default:@localhost:9999/supa')",
+                None,
+                None,
+            );
+            c.update(
+                &format!(
+                    r#"CREATE USER MAPPING FOR CURRENT_USER SERVER my_clickhouse_server
+                         OPTIONS (
+                           conn_string '{}'
+                         )"#,
+                    CONN_STRING
+                ),
+                None,
+                None,
+            );
+            assert_eq!(c.select(sql, None, None).len(), 1);
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_options() {
+        Spi::execute(|c| {
+            // test declared options are listed
+            let results = c
+                .select(
+                    "SELECT name FROM click_house_fdw_options() WHERE secret ORDER BY name",
+                    None,
+                    None,
+                )
+                .filter_map(|r| r.by_ordinal(1).ok().and_then(|v| v.value::<String>()))
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec!["conn_string"]);
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_integral_float() {
+        Spi::execute(|c| {
+            create_server(&c);
+
+            // test integral float is converted to integer column
+            c.update(
+                r#"
+                  CREATE FOREIGN TABLE test_floats (
+                    v bigint
+                  )
+                  SERVER my_clickhouse_server
+                  OPTIONS (
+                    table '(select toFloat64(42) as v)',
+                    rowid_column 'v'
+                  )
+             "#,
+                None,
+                None,
+            );
+            assert_eq!(
+                c.select("SELECT v FROM test_floats", None, None)
+                    .first()
+                    .get_one::<i64>()
+                    .unwrap(),
+                42
+            );
+        });
+    }
+
+    #[pg_test(
+        error = "cannot convert value for column 'v': non-integral value 4.2 for type bigint"
+    )]
+    fn clickhouse_non_integral_float() {
+        Spi::execute(|c| {
+            create_server(&c);
+            c.update(
+                r#"
+                  CREATE FOREIGN TABLE test_floats (
+                    v bigint
+                  )
+                  SERVER my_clickhouse_server
+                  OPTIONS (
+                    table '(select toFloat64(4.2) as v)',
+                    rowid_column 'v'
+                  )
+             "#,
+                None,
+                None,
+            );
+            c.select("SELECT v FROM test_floats", None, None);
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_date_time_literals() {
+        Spi::execute(|c| {
+            create_server(&c);
+            c.update(
+                r#"
+                  CREATE FOREIGN TABLE test_datetime (
+                    id bigint,
+                    ts timestamp
+                  )
+                  SERVER my_clickhouse_server
+                  OPTIONS (
+                    table '(select toInt64(1) as id, toDateTime(''2023-01-02 03:04:05'', ''UTC'') as ts)'
+                  )
+             "#,
+                None,
+                None,
+            );
+
+            // test date and time literals are sent in ISO format and UTC,
+            // regardless of the session settings
+            c.update("SET DateStyle = 'SQL, DMY'", None, None);
+            c.update("SET TimeZone = 'Asia/Tokyo'", None, None);
+
+            let sql = "SELECT id FROM test_datetime WHERE ts = '2023-01-02 03:04:05'";
+            assert!(remote_sql(&c, sql).ends_with("where `ts` = '2023-01-02 03:04:05'"));
+            assert_eq!(c.select(sql, None, None).len(), 1);
+
+            let sql =
+                "SELECT id FROM test_datetime WHERE ts < '2023-01-02 12:04:06+09'::timestamptz";
+            assert!(remote_sql(&c, sql)
+                .ends_with("where `ts` < toDateTime64('2023-01-02 03:04:06', 6, 'UTC')"));
+            assert_eq!(c.select(sql, None, None).len(), 1);
         });
     }
 }
@@ -13,36 +13,12 @@ use yup_oauth2::ServiceAccountAuthenticator;
 
 use supabase_wrappers::prelude::*;
 
-macro_rules! report_request_error {
-    ($url:ident, $err:ident) => {
-        report_error(
-            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-            &format!("fetch {} failed: {}", $url, $err),
-        )
-    };
-}
-
-fn get_oauth2_token(sa_key: &str, rt: &Runtime) -> Option<AccessToken> {
-    let creds = match yup_oauth2::parse_service_account_key(sa_key.as_bytes()) {
-        Ok(creds) => creds,
-        Err(err) => {
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                &format!("parse service account key JSON failed: {}", err),
-            );
-            return None;
-        }
-    };
-    let sa = match rt.block_on(ServiceAccountAuthenticator::builder(creds).build()) {
-        Ok(sa) => sa,
-        Err(err) => {
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                &format!("invalid service account key: {}", err),
-            );
-            return None;
-        }
-    };
+fn get_oauth2_token(sa_key: &str, rt: &Runtime) -> FdwResult<AccessToken> {
+    let creds = yup_oauth2::parse_service_account_key(sa_key.as_bytes())
+        .map_err(|err| format!("parse service account key JSON failed: {}", err))?;
+    let sa = rt
+        .block_on(ServiceAccountAuthenticator::builder(creds).build())
+        .map_err(|err| format!("invalid service account key: {}", err))?;
     let scopes = &[
         "https://www.googleapis.com/auth/cloud-platform",
         "https://www.googleapis.com/auth/firebase.database",
@@ -50,16 +26,36 @@ fn get_oauth2_token(sa_key: &str, rt: &Runtime) -> Option<AccessToken> {
         "https://www.googleapis.com/auth/identitytoolkit",
         "https://www.googleapis.com/auth/userinfo.email",
     ];
-    match rt.block_on(sa.token(scopes)) {
-        Ok(token) => Some(token),
-        Err(err) => {
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                &format!("get token failed: {}", err),
-            );
-            None
-        }
-    }
+    rt.block_on(sa.token(scopes))
+        .map_err(|err| format!("get token failed: {}", err).into())
+}
+
+fn invalid_timestamp(value: &str, err: impl std::fmt::Display) -> FdwError {
+    FdwError::new(
+        PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+        format!("invalid timestamp {}: {}", value, err),
+    )
+}
+
+// convert unix timestamp string in milliseconds to timestamp cell
+fn millis_to_timestamp(value: &str) -> FdwResult<Cell> {
+    let millis = value
+        .parse::<i64>()
+        .map_err(|err| invalid_timestamp(value, err))?;
+    let dt = OffsetDateTime::from_unix_timestamp(millis / 1000)
+        .map_err(|err| invalid_timestamp(value, err))?;
+    Timestamp::try_from(dt)
+        .map(Cell::Timestamp)
+        .map_err(|err| invalid_timestamp(value, err))
+}
+
+// convert ISO 8601 timestamp string to timestamp cell
+fn iso_to_timestamp(value: &str) -> FdwResult<Cell> {
+    let dt = PrimitiveDateTime::parse(value, &Iso8601::DEFAULT)
+        .map_err(|err| invalid_timestamp(value, err))?;
+    Timestamp::try_from(dt)
+        .map(Cell::Timestamp)
+        .map_err(|err| invalid_timestamp(value, err))
 }
 
 fn body_to_rows(
@@ -67,7 +63,7 @@ fn body_to_rows(
     obj_key: &str,
     normal_cols: Vec<(&str, &str, &str)>,
     tgt_cols: &[Column],
-) -> Vec<Row> {
+) -> FdwResult<Vec<Row>> {
     let mut result = Vec::new();
 
     let objs = match resp
@@ -76,7 +72,7 @@ fn body_to_rows(
         .and_then(|v| v.as_array())
     {
         Some(objs) => objs,
-        None => return result,
+        None => return Ok(result),
     };
 
     for obj in objs {
@@ -90,61 +86,49 @@ fn body_to_rows(
                 let cell = obj
                     .as_object()
                     .and_then(|v| v.get(*src_name))
-                    .and_then(|v| match *col_type {
-                        "bool" => v.as_bool().map(Cell::Bool),
-                        "i64" => v.as_i64().map(Cell::I64),
-                        "string" => v.as_str().map(|a| Cell::String(a.to_owned())),
-                        "timestamp" => v.as_str().map(|a| {
-                            let secs = a.parse::<i64>().unwrap() / 1000;
-                            let dt = OffsetDateTime::from_unix_timestamp(secs).unwrap();
-                            let ts = Timestamp::try_from(dt).unwrap();
-                            Cell::Timestamp(ts)
-                        }),
-                        "timestamp_iso" => v.as_str().map(|a| {
-                            let dt = PrimitiveDateTime::parse(a, &Iso8601::DEFAULT).unwrap();
-                            let ts = Timestamp::try_from(dt).unwrap();
-                            Cell::Timestamp(ts)
-                        }),
-                        "json" => Some(Cell::Json(JsonB(v.clone()))),
-                        _ => None,
-                    });
+                    .map(|v| match *col_type {
+                        "bool" => Ok(v.as_bool().map(Cell::Bool)),
+                        "i64" => Ok(v.as_i64().map(Cell::I64)),
+                        "string" => Ok(v.as_str().map(|a| Cell::String(a.to_owned()))),
+                        "timestamp" => v.as_str().map(millis_to_timestamp).transpose(),
+                        "timestamp_iso" => v.as_str().map(iso_to_timestamp).transpose(),
+                        "json" => Ok(Some(Cell::Json(JsonB(v.clone())))),
+                        _ => Ok(None),
+                    })
+                    .transpose()?
+                    .flatten();
                 row.push(col_name, cell);
             }
         }
 
         // put all properties into 'attrs' JSON column
         if tgt_cols.iter().any(|c| &c.name == "attrs") {
-            let attrs = serde_json::from_str(&obj.to_string()).unwrap();
-            row.push("attrs", Some(Cell::Json(JsonB(attrs))));
+            row.push("attrs", Some(Cell::Json(JsonB(obj.clone()))));
         }
 
         result.push(row);
     }
 
-    result
+    Ok(result)
 }
 
 // convert response body text to rows
-fn resp_to_rows(obj: &str, resp: &JsonValue, tgt_cols: &[Column]) -> Vec<Row> {
-    let mut result = Vec::new();
-
+fn resp_to_rows(obj: &str, resp: &JsonValue, tgt_cols: &[Column]) -> FdwResult<Vec<Row>> {
     match obj {
-        "auth/users" => {
-            result = body_to_rows(
-                resp,
-                "users",
-                vec![
-                    ("localId", "uid", "string"),
-                    ("email", "email", "string"),
-                    ("createdAt", "created_at", "timestamp"),
-                ],
-                tgt_cols,
-            );
-        }
+        "auth/users" => body_to_rows(
+            resp,
+            "users",
+            vec![
+                ("localId", "uid", "string"),
+                ("email", "email", "string"),
+                ("createdAt", "created_at", "timestamp"),
+            ],
+            tgt_cols,
+        ),
         _ => {
             // match firestore documents
             if obj.starts_with("firestore/") {
-                result = body_to_rows(
+                body_to_rows(
                     resp,
                     "documents",
                     vec![
@@ -154,17 +138,15 @@ fn resp_to_rows(obj: &str, resp: &JsonValue, tgt_cols: &[Column]) -> Vec<Row> {
                         ("updateTime", "updated_at", "timestamp_iso"),
                     ],
                     tgt_cols,
-                );
+                )
             } else {
-                report_error(
+                Err(FdwError::new(
                     PgSqlErrorCode::ERRCODE_FDW_TABLE_NOT_FOUND,
-                    &format!("'{}' object is not implemented", obj),
-                );
+                    format!("'{}' object is not implemented", obj),
+                ))
             }
         }
     }
-
-    result
 }
 
 #[wrappers_fdw(
@@ -175,8 +157,9 @@ fn resp_to_rows(obj: &str, resp: &JsonValue, tgt_cols: &[Column]) -> Vec<Row> {
 pub(crate) struct FirebaseFdw {
     rt: Runtime,
     project_id: String,
-    client: Option<ClientWithMiddleware>,
+    client: ClientWithMiddleware,
     scan_result: Option<Vec<Row>>,
+    requests: usize,
 }
 
 impl FirebaseFdw {
@@ -247,18 +230,9 @@ impl FirebaseFdw {
 }
 
 impl ForeignDataWrapper for FirebaseFdw {
-    fn new(options: &HashMap<String, String>) -> Self {
-        let mut ret = Self {
-            rt: create_async_runtime(),
-            project_id: "".to_string(),
-            client: None,
-            scan_result: None,
-        };
-
-        ret.project_id = match require_option("project_id", options) {
-            Some(project_id) => project_id,
-            None => return ret,
-        };
+    fn new(options: &HashMap<String, String>) -> FdwResult<Self> {
+        let rt = create_async_runtime();
+        let project_id = require_option("project_id", options)?;
 
         // get oauth2 access token if it is directly defined in options
         let token = if let Some(access_token) = options.get("access_token") {
@@ -268,21 +242,12 @@ impl ForeignDataWrapper for FirebaseFdw {
             let sa_key = match options.get("sa_key") {
                 Some(sa_key) => sa_key.to_owned(),
                 None => {
-                    let sa_key_id = match require_option("sa_key_id", options) {
-                        Some(sa_key_id) => sa_key_id,
-                        None => return ret,
-                    };
-                    match get_vault_secret(&sa_key_id) {
-                        Some(sa_key) => sa_key,
-                        None => return ret,
-                    }
+                    let sa_key_id = require_option("sa_key_id", options)?;
+                    get_vault_secret(&sa_key_id)?
                 }
             };
-            if let Some(access_token) = get_oauth2_token(&sa_key, &ret.rt) {
-                access_token.token().map(|t| t.to_owned()).unwrap()
-            } else {
-                return ret;
-            }
+            let access_token = get_oauth2_token(&sa_key, &rt)?;
+            access_token.token().map(|t| t.to_owned()).unwrap()
         };
 
         // create client
@@ -299,9 +264,23 @@ impl ForeignDataWrapper for FirebaseFdw {
         let client = ClientBuilder::new(client)
             .with(RetryTransientMiddleware::new_with_policy(retry_policy))
             .build();
-        ret.client = Some(client);
 
-        ret
+        Ok(Self {
+            rt,
+            project_id,
+            client,
+            scan_result: None,
+            requests: 0,
+        })
+    }
+
+    fn get_rel_cost(&mut self, _options: &HashMap<String, String>) -> FdwResult<ScanCost> {
+        // each API call is a HTTP request returning one page of records
+        Ok(ScanCost {
+            request_cost: 100.0,
+            request_size: Self::PAGE_SIZE,
+            ..Default::default()
+        })
     }
 
     fn begin_scan(
@@ -311,82 +290,103 @@ impl ForeignDataWrapper for FirebaseFdw {
         _sorts: &[Sort],
         _limit: &Option<Limit>,
         options: &HashMap<String, String>,
-    ) {
-        let obj = match require_option("object", options) {
-            Some(obj) => obj,
-            None => return,
+    ) -> FdwResult<()> {
+        let obj = require_option("object", options)?;
+        let row_cnt_limit = match options.get("limit") {
+            Some(n) => n.parse::<usize>().map_err(|_| {
+                FdwError::new(
+                    PgSqlErrorCode::ERRCODE_FDW_INVALID_OPTION_NAME,
+                    format!("invalid limit value: {}", n),
+                )
+            })?,
+            None => Self::DEFAULT_ROWS_LIMIT,
         };
-        let row_cnt_limit = options
-            .get("limit")
-            .map(|n| n.parse::<usize>().unwrap())
-            .unwrap_or(Self::DEFAULT_ROWS_LIMIT);
 
         self.scan_result = None;
 
-        if let Some(client) = &self.client {
-            let mut next_page: Option<String> = None;
-            let mut result = Vec::new();
-
-            loop {
-                let url = self.build_url(&obj, &next_page, options);
-
-                match self.rt.block_on(client.get(&url).send()) {
-                    Ok(resp) => match resp.error_for_status() {
-                        Ok(resp) => {
-                            let body = self.rt.block_on(resp.text()).unwrap();
-                            let json: JsonValue = serde_json::from_str(&body).unwrap();
-                            let mut rows = resp_to_rows(&obj, &json, columns);
-                            result.append(&mut rows);
-                            if result.len() >= row_cnt_limit {
-                                break;
-                            }
-
-                            // get next page token, stop fetching if no more pages
-                            next_page = json
-                                .get("nextPageToken")
-                                .and_then(|v| v.as_str())
-                                .map(|v| v.to_owned());
-                            if next_page.is_none() {
-                                break;
-                            }
-                        }
-                        Err(err) => {
-                            report_request_error!(url, err);
-                            break;
-                        }
-                    },
-                    Err(err) => {
-                        report_request_error!(url, err);
-                        break;
-                    }
-                }
+        let mut next_page: Option<String> = None;
+        let mut result = Vec::new();
+
+        loop {
+            let url = self.build_url(&obj, &next_page, options);
+
+            self.requests += 1;
+            let body = self
+                .rt
+                .block_on(self.client.get(&url).send())
+                .and_then(|resp| resp.error_for_status().map_err(From::from))
+                .and_then(|resp| self.rt.block_on(resp.text()).map_err(From::from))
+                .map_err(|err| format!("fetch {} failed: {}", url, err))?;
+            let json: JsonValue = serde_json::from_str(&body).map_err(|err| {
+                FdwError::new(
+                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
+                    format!("parse response body failed: {}", err),
+                )
+            })?;
+            let mut rows = resp_to_rows(&obj, &json, columns)?;
+            result.append(&mut rows);
+            if result.len() >= row_cnt_limit {
+                break;
             }
 
-            self.scan_result = Some(result);
+            // get next page token, stop fetching if no more pages
+            next_page = json
+                .get("nextPageToken")
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_owned());
+            if next_page.is_none() {
+                break;
+            }
         }
+
+        self.scan_result = Some(result);
+
+        Ok(())
     }
 
-    fn iter_scan(&mut self, row: &mut Row) -> Option<()> {
+    fn iter_scan(&mut self, row: &mut Row) -> FdwResult<Option<()>> {
         if let Some(ref mut result) = self.scan_result {
             if !result.is_empty() {
-                return result
+                return Ok(result
                     .drain(0..1)
                     .last()
-                    .map(|src_row| row.replace_with(src_row));
+                    .map(|src_row| row.replace_with(src_row)));
             }
         }
-        None
+        Ok(None)
     }
 
-    fn end_scan(&mut self) {
+    fn end_scan(&mut self) -> FdwResult<()> {
         self.scan_result.take();
+        Ok(())
+    }
+
+    fn explain_scan(
+        &self,
+        _quals: &[Qual],
+        _columns: &[Column],
+        _sorts: &[Sort],
+        _limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+        _verbose: bool,
+    ) -> FdwResult<Vec<(String, String)>> {
+        let obj = require_option("object", options)?;
+        let mut ret = vec![(
+            "Remote URL".to_string(),
+            self.build_url(&obj, &None, options),
+        )];
+        if self.requests > 0 {
+            ret.push(("Remote Requests".to_string(), self.requests.to_string()));
+        }
+        Ok(ret)
     }
 
-    fn validator(options: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) {
+    fn validator(options: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) -> FdwResult<()> {
         if let Some(oid) = catalog {
             if oid == FOREIGN_TABLE_RELATION_ID {
-                check_options_contain(&options, "object");
+                check_options_contain(&options, "object")?;
             }
         }
+        Ok(())
     }
 }
@@ -2,12 +2,12 @@ use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, XzDecoder, ZlibD
 use aws_sdk_s3 as s3;
 use http::Uri;
 use pgx::pg_sys;
-use pgx::prelude::PgSqlErrorCode;
 use serde_json::{self, Value as JsonValue};
 use std::collections::{HashMap, VecDeque};
 use std::env;
 use std::io::Cursor;
 use std::pin::Pin;
+use std::rc::Rc;
 use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 
 use supabase_wrappers::prelude::*;
@@ -19,14 +19,108 @@ enum Parser {
     JsonLine(VecDeque<JsonValue>),
 }
 
+// record parsed from S3 text file
+enum Record {
+    Csv(csv::StringRecord),
+    JsonLine(JsonValue),
+}
+
+impl Record {
+    // get cell of the column, csv record is indexed by column number and json
+    // record is indexed by column name
+    fn cell(&self, col: &Column) -> Option<Cell> {
+        match self {
+            Record::Csv(record) => record.get(col.num - 1).map(|s| Cell::String(s.to_owned())),
+            Record::JsonLine(record) => record
+                .as_object()
+                .and_then(|obj| obj.get(&col.name))
+                .and_then(|val| match val {
+                    JsonValue::Null => None,
+                    JsonValue::Bool(v) => Some(Cell::String(v.to_string())),
+                    JsonValue::Number(v) => Some(Cell::String(v.to_string())),
+                    JsonValue::String(v) => Some(Cell::String(v.to_owned())),
+                    JsonValue::Array(v) => Some(Cell::String(format!("{:?}", v))),
+                    JsonValue::Object(v) => Some(Cell::String(format!("{:?}", v))),
+                }),
+        }
+    }
+}
+
+// connection to S3, which is cached and shared by the FDW instances in the
+// same backend
+struct Connection {
+    rt: Runtime,
+    client: s3::Client,
+}
+
+fn create_connection(options: &HashMap<String, String>) -> FdwResult<Connection> {
+    let rt = create_async_runtime();
+
+    // get is_mock flag
+    let is_mock: bool = options.get("is_mock") == Some(&"true".to_string());
+
+    // get credentials
+    let creds = if is_mock {
+        // LocalStack uses hardcoded credentials
+        ("test".to_string(), "test".to_string())
+    } else {
+        match options.get("vault_access_key_id") {
+            Some(vault_access_key_id) => {
+                // if using credentials stored in Vault
+                let vault_secret_access_key = require_option("vault_secret_access_key", options)?;
+                (
+                    get_vault_secret(vault_access_key_id)?,
+                    get_vault_secret(&vault_secret_access_key)?,
+                )
+            }
+            None => {
+                // if using credentials directly specified
+                (
+                    require_option("aws_access_key_id", options)?,
+                    require_option("aws_secret_access_key", options)?,
+                )
+            }
+        }
+    };
+
+    // get region
+    let default_region = "us-east-1".to_string();
+    let region = if is_mock {
+        default_region
+    } else {
+        options
+            .get("aws_region")
+            .map(|t| t.to_owned())
+            .unwrap_or(default_region)
+    };
+
+    // set AWS environment variables and create shared config from them
+    env::set_var("AWS_ACCESS_KEY_ID", creds.0);
+    env::set_var("AWS_SECRET_ACCESS_KEY", creds.1);
+    env::set_var("AWS_REGION", region);
+    let config = rt.block_on(aws_config::load_from_env());
+
+    // create S3 client
+    let client = if is_mock {
+        let mut s3_config_builder = s3::config::Builder::from(&config);
+        s3_config_builder = s3_config_builder
+            .endpoint_url("http://localhost:4566/")
+            .force_path_style(true);
+        s3::Client::from_conf(s3_config_builder.build())
+    } else {
+        s3::Client::new(&config)
+    };
+
+    Ok(Connection { rt, client })
+}
+
 #[wrappers_fdw(
     version = "0.1.0",
     author = "Supabase",
     website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/s3_fdw"
 )]
 pub(crate) struct S3Fdw {
-    rt: Runtime,
-    client: Option<s3::Client>,
+    conn: Rc<Connection>,
     rdr: Option<BufReader<Pin<Box<dyn AsyncRead>>>>,
     parser: Parser,
     tgt_cols: Vec<Column>,
@@ -38,38 +132,35 @@ impl S3Fdw {
     // Note: this is not a hard limit, just an indication of full buffer
     const BUF_SIZE: usize = 256 * 1024;
 
+    // number of rows returned to Postgres in one batch
+    const SCAN_BATCH_SIZE: usize = 1024;
+
     // fetch remote data to local string line buffer when it is empty and set
     // up record parser.
     // Returns:
     //   Some - still have records to read
     //   None - no more records
-    fn refill(&mut self) -> Option<()> {
+    fn refill(&mut self) -> FdwResult<Option<()>> {
         if !self.buf.is_empty() {
-            return Some(());
+            return Ok(Some(()));
         }
 
         if let Some(ref mut rdr) = self.rdr {
             // fetch remote data by lines and fill in local buffer
             loop {
-                match self.rt.block_on(rdr.read_line(&mut self.buf)) {
-                    Ok(num_bytes) => {
-                        if num_bytes == 0 || self.buf.len() > Self::BUF_SIZE {
-                            break;
-                        }
-                    }
-                    Err(err) => {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!("fetch query result failed: {}", err),
-                        );
-                        return None;
-                    }
+                let num_bytes = self
+                    .conn
+                    .rt
+                    .block_on(rdr.read_line(&mut self.buf))
+                    .map_err(|err| format!("fetch query result failed: {}", err))?;
+                if num_bytes == 0 || self.buf.len() > Self::BUF_SIZE {
+                    break;
                 }
             }
         }
 
         if self.buf.is_empty() {
-            return None;
+            return Ok(None);
         }
 
         match &mut self.parser {
@@ -89,97 +180,59 @@ impl S3Fdw {
                     .collect::<Vec<&str>>()
                     .join(",");
                 let json_str = format!("{{ \"rows\": [{}] }}", s.trim_end_matches(','));
-                match serde_json::from_str::<JsonValue>(&json_str) {
-                    Ok(rows) => {
-                        *records =
-                            VecDeque::from(rows.get("rows").unwrap().as_array().unwrap().to_vec());
+                let rows = serde_json::from_str::<JsonValue>(&json_str)
+                    .map_err(|err| format!("parse json line file failed: {}", err))?;
+                *records = VecDeque::from(rows.get("rows").unwrap().as_array().unwrap().to_vec());
+            }
+        }
+
+        Ok(Some(()))
+    }
+
+    // read next record, refill local buffer from remote when it is exhausted
+    fn read_record(&mut self) -> FdwResult<Option<Record>> {
+        loop {
+            if self.refill()?.is_none() {
+                break;
+            }
+
+            // parse local buffer data to records
+            match &mut self.parser {
+                Parser::Csv(rdr) => {
+                    let mut record = csv::StringRecord::new();
+                    let result = rdr
+                        .read_record(&mut record)
+                        .map_err(|err| format!("read csv record failed: {}", err))?;
+                    if result {
+                        return Ok(Some(Record::Csv(record)));
                     }
-                    Err(err) => {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!("parse json line file failed: {}", err),
-                        );
-                        return None;
+                }
+                Parser::JsonLine(records) => {
+                    if let Some(record) = records.pop_front() {
+                        return Ok(Some(Record::JsonLine(record)));
                     }
                 }
             }
+
+            // no more records left in the local buffer, refill from remote
+            self.buf.clear();
         }
 
-        Some(())
+        Ok(None)
     }
 }
 
 impl ForeignDataWrapper for S3Fdw {
-    fn new(options: &HashMap<String, String>) -> Self {
-        let mut ret = S3Fdw {
-            rt: create_async_runtime(),
-            client: None,
+    fn new(options: &HashMap<String, String>) -> FdwResult<Self> {
+        let conn = cached_connection(|| create_connection(options))?;
+
+        Ok(S3Fdw {
+            conn,
             rdr: None,
             parser: Parser::JsonLine(VecDeque::new()),
             tgt_cols: Vec::new(),
             buf: String::new(),
-        };
-
-        // get is_mock flag
-        let is_mock: bool = options.get("is_mock") == Some(&"true".to_string());
-
-        // get credentials
-        let creds = if is_mock {
-            // LocalStack uses hardcoded credentials
-            Some(("test".to_string(), "test".to_string()))
-        } else {
-            match options.get("vault_access_key_id") {
-                Some(vault_access_key_id) => {
-                    // if using credentials stored in Vault
-                    require_option("vault_secret_access_key", options).and_then(
-                        |vault_secret_access_key| {
-                            get_vault_secret(vault_access_key_id)
-                                .zip(get_vault_secret(&vault_secret_access_key))
-                        },
-                    )
-                }
-                None => {
-                    // if using credentials directly specified
-                    require_option("aws_access_key_id", options)
-                        .zip(require_option("aws_secret_access_key", options))
-                }
-            }
-        };
-        if creds.is_none() {
-            return ret;
-        }
-        let creds = creds.unwrap();
-
-        // get region
-        let default_region = "us-east-1".to_string();
-        let region = if is_mock {
-            default_region
-        } else {
-            options
-                .get("aws_region")
-                .map(|t| t.to_owned())
-                .unwrap_or(default_region)
-        };
-
-        // set AWS environment variables and create shared config from them
-        env::set_var("AWS_ACCESS_KEY_ID", creds.0);
-        env::set_var("AWS_SECRET_ACCESS_KEY", creds.1);
-        env::set_var("AWS_REGION", region);
-        let config = ret.rt.block_on(aws_config::load_from_env());
-
-        // create S3 client
-        let client = if is_mock {
-            let mut s3_config_builder = s3::config::Builder::from(&config);
-            s3_config_builder = s3_config_builder
-                .endpoint_url("http://localhost:4566/")
-                .force_path_style(true);
-            s3::Client::from_conf(s3_config_builder.build())
-        } else {
-            s3::Client::new(&config)
-        };
-        ret.client = Some(client);
-
-        ret
+        })
     }
 
     fn begin_scan(
@@ -189,200 +242,129 @@ impl ForeignDataWrapper for S3Fdw {
         _sorts: &[Sort],
         _limit: &Option<Limit>,
         options: &HashMap<String, String>,
-    ) {
+    ) -> FdwResult<()> {
         // extract s3 bucket and object path from uri option
-        let (bucket, object) = if let Some(uri) = require_option("uri", options) {
-            match uri.parse::<Uri>() {
-                Ok(uri) => {
-                    if uri.scheme_str() != Option::Some("s3")
-                        || uri.host().is_none()
-                        || uri.path().is_empty()
-                    {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!("invalid s3 uri: {}", uri),
-                        );
-                        return;
-                    }
-                    // exclude 1st "/" char in the path as s3 object path doesn't like it
-                    (uri.host().unwrap().to_owned(), uri.path()[1..].to_string())
-                }
-                Err(err) => {
-                    report_error(
-                        PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                        &format!("parse s3 uri failed: {}", err),
-                    );
-                    return;
-                }
-            }
-        } else {
-            return;
-        };
+        let uri = require_option("uri", options)?;
+        let uri = uri
+            .parse::<Uri>()
+            .map_err(|err| format!("parse s3 uri failed: {}", err))?;
+        if uri.scheme_str() != Option::Some("s3") || uri.host().is_none() || uri.path().is_empty()
+        {
+            return Err(format!("invalid s3 uri: {}", uri).into());
+        }
+        // exclude 1st "/" char in the path as s3 object path doesn't like it
+        let (bucket, object) = (uri.host().unwrap().to_owned(), uri.path()[1..].to_string());
 
         // initialise parser according to format option
-        if let Some(format) = require_option("format", options) {
-            // create dummy parser
-            match format.as_str() {
-                "csv" => self.parser = Parser::Csv(csv::Reader::from_reader(Cursor::new(vec![0]))),
-                "jsonl" => self.parser = Parser::JsonLine(VecDeque::new()),
-                _ => {
-                    report_error(
-                        PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                        &format!(
-                            "invalid format option: {}, it can only be 'csv' or 'jsonl'",
-                            format
-                        ),
-                    );
-                    return;
-                }
+        let format = require_option("format", options)?;
+        // create dummy parser
+        match format.as_str() {
+            "csv" => self.parser = Parser::Csv(csv::Reader::from_reader(Cursor::new(vec![0]))),
+            "jsonl" => self.parser = Parser::JsonLine(VecDeque::new()),
+            _ => {
+                return Err(format!(
+                    "invalid format option: {}, it can only be 'csv' or 'jsonl'",
+                    format
+                )
+                .into());
             }
-        } else {
-            return;
-        };
+        }
 
         let has_header: bool = options.get("has_header") == Some(&"true".to_string());
 
-        if let Some(client) = &self.client {
-            self.tgt_cols = columns.to_vec();
-
-            match self
-                .rt
-                .block_on(client.get_object().bucket(bucket).key(object).send())
-            {
-                Ok(resp) => {
-                    let stream = resp.body.into_async_read();
-
-                    let boxed_stream: Pin<Box<dyn AsyncRead>> =
-                        // deal with compression
-                        if let Some(compress) = options.get("compress") {
-                            let buf_rdr = BufReader::new(stream);
-                            match compress.as_str() {
-                                "bzip2" => Box::pin(BzDecoder::new(buf_rdr)),
-                                "gzip" => Box::pin(GzipDecoder::new(buf_rdr)),
-                                "xz" => Box::pin(XzDecoder::new(buf_rdr)),
-                                "zlib" => Box::pin(ZlibDecoder::new(buf_rdr)),
-                                _ => {
-                                    report_error(
-                                        PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                                        &format!("invalid compression option: {}", compress),
-                                    );
-                                    return;
-                                }
-                            }
-                        } else {
-                            Box::pin(stream)
-                        };
-
-                    let mut rdr: BufReader<Pin<Box<dyn AsyncRead>>> = BufReader::new(boxed_stream);
-
-                    // skip csv header line if needed
-                    if let Parser::Csv(_) = self.parser {
-                        if has_header {
-                            let mut header = String::new();
-                            if let Err(err) = self.rt.block_on(rdr.read_line(&mut header)) {
-                                report_error(
-                                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                                    &format!("fetch csv file failed: {}", err),
-                                );
-                                return;
-                            }
-                        }
-                    }
+        self.tgt_cols = columns.to_vec();
 
-                    self.rdr = Some(rdr);
+        let resp = self
+            .conn
+            .rt
+            .block_on(
+                self.conn
+                    .client
+                    .get_object()
+                    .bucket(bucket)
+                    .key(object)
+                    .send(),
+            )
+            .map_err(|err| format!("request s3 failed: {}", err))?;
+        let stream = resp.body.into_async_read();
+
+        let boxed_stream: Pin<Box<dyn AsyncRead>> =
+            // deal with compression
+            if let Some(compress) = options.get("compress") {
+                let buf_rdr = BufReader::new(stream);
+                match compress.as_str() {
+                    "bzip2" => Box::pin(BzDecoder::new(buf_rdr)),
+                    "gzip" => Box::pin(GzipDecoder::new(buf_rdr)),
+                    "xz" => Box::pin(XzDecoder::new(buf_rdr)),
+                    "zlib" => Box::pin(ZlibDecoder::new(buf_rdr)),
+                    _ => {
+                        return Err(format!("invalid compression option: {}", compress).into());
+                    }
                 }
-                Err(err) => report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("request s3 failed: {}", err),
-                ),
+            } else {
+                Box::pin(stream)
+            };
+
+        let mut rdr: BufReader<Pin<Box<dyn AsyncRead>>> = BufReader::new(boxed_stream);
+
+        // skip csv header line if needed
+        if let Parser::Csv(_) = self.parser {
+            if has_header {
+                let mut header = String::new();
+                self.conn
+                    .rt
+                    .block_on(rdr.read_line(&mut header))
+                    .map_err(|err| format!("fetch csv file failed: {}", err))?;
             }
         }
-    }
 
-    fn iter_scan(&mut self, row: &mut Row) -> Option<()> {
-        loop {
-            if self.refill().is_none() {
-                break;
-            }
+        self.rdr = Some(rdr);
 
-            // parse local buffer data to records
-            match &mut self.parser {
-                Parser::Csv(rdr) => {
-                    let mut record = csv::StringRecord::new();
-                    match rdr.read_record(&mut record) {
-                        Ok(result) => {
-                            if result {
-                                for col in &self.tgt_cols {
-                                    let cell =
-                                        record.get(col.num - 1).map(|s| Cell::String(s.to_owned()));
-                                    row.push(&col.name, cell);
-                                }
-                                return Some(());
-                            } else {
-                                // no more records left in the local buffer, refill from remote
-                                self.buf.clear();
-                            }
-                        }
-                        Err(err) => {
-                            report_error(
-                                PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                                &format!("read csv record failed: {}", err),
-                            );
-                            break;
-                        }
-                    }
-                }
-                Parser::JsonLine(records) => {
-                    match records.pop_front() {
-                        Some(record) => {
-                            if let Some(obj) = record.as_object() {
-                                for col in &self.tgt_cols {
-                                    let cell = obj
-                                        .get(&col.name)
-                                        .map(|val| match val {
-                                            JsonValue::Null => None,
-                                            JsonValue::Bool(v) => Some(Cell::String(v.to_string())),
-                                            JsonValue::Number(v) => {
-                                                Some(Cell::String(v.to_string()))
-                                            }
-                                            JsonValue::String(v) => {
-                                                Some(Cell::String(v.to_owned()))
-                                            }
-                                            JsonValue::Array(v) => {
-                                                Some(Cell::String(format!("{:?}", v)))
-                                            }
-                                            JsonValue::Object(v) => {
-                                                Some(Cell::String(format!("{:?}", v)))
-                                            }
-                                        })
-                                        .unwrap_or(None);
-                                    row.push(&col.name, cell);
-                                }
-                            }
-                            return Some(());
-                        }
-                        None => {
-                            // no more records left in the local buffer, refill from remote
-                            self.buf.clear();
-                        }
-                    }
+        Ok(())
+    }
+
+    fn iter_scan(&mut self, row: &mut Row) -> FdwResult<Option<()>> {
+        match self.read_record()? {
+            Some(record) => {
+                for col in &self.tgt_cols {
+                    row.push(&col.name, record.cell(col));
                 }
+                Ok(Some(()))
             }
+            None => Ok(None),
         }
+    }
 
-        None
+    fn scan_batch_size(_options: &HashMap<String, String>) -> Option<usize> {
+        Some(Self::SCAN_BATCH_SIZE)
+    }
+
+    fn iter_scan_batch(&mut self, batch: &mut RowBatch) -> FdwResult<usize> {
+        while !batch.is_full() {
+            let record = match self.read_record()? {
+                Some(record) => record,
+                None => break,
+            };
+            let cells = batch.push_row();
+            for (cell, col) in cells.iter_mut().zip(self.tgt_cols.iter()) {
+                *cell = record.cell(col);
+            }
+        }
+        Ok(batch.len())
     }
 
-    fn end_scan(&mut self) {
+    fn end_scan(&mut self) -> FdwResult<()> {
         self.rdr.take();
+        Ok(())
     }
 
-    fn validator(options: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) {
+    fn validator(options: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) -> FdwResult<()> {
         if let Some(oid) = catalog {
             if oid == FOREIGN_TABLE_RELATION_ID {
-                check_options_contain(&options, "uri");
-                check_options_contain(&options, "format");
+                check_options_contain(&options, "uri")?;
+                check_options_contain(&options, "format")?;
             }
         }
+        Ok(())
     }
 }
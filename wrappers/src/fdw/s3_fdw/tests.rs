@@ -105,6 +105,26 @@ mod tests {
                 None,
             );
 
+            c.update(
+                r#"
+                CREATE FOREIGN TABLE s3_test_table_csv_typed (
+                  name text,
+                  sex text,
+                  age int,
+                  height varchar(10),
+                  weight numeric(5, 2)
+                )
+                SERVER s3_server
+                OPTIONS (
+                    uri 's3://test/test_data.csv',
+                    format 'csv',
+                    has_header 'true'
+                  )
+             "#,
+                None,
+                None,
+            );
+
             let check_test_table = |table| {
                 let sql = format!("SELECT * FROM {} ORDER BY name LIMIT 1", table);
                 let results = c
@@ -124,6 +144,29 @@ mod tests {
             check_test_table("s3_test_table_csv_gz");
             check_test_table("s3_test_table_jsonl");
             check_test_table("s3_test_table_jsonl_bz");
+
+            // test cells are converted to the declared column types and type
+            // modifiers
+            let results = c
+                .select(
+                    "SELECT name, age, weight FROM s3_test_table_csv_typed ORDER BY name LIMIT 1",
+                    None,
+                    None,
+                )
+                .filter_map(|r| {
+                    r.by_name("name")
+                        .ok()
+                        .and_then(|v| v.value::<&str>())
+                        .zip(r.by_name("age").ok().and_then(|v| v.value::<i32>()))
+                        .zip(
+                            r.by_name("weight")
+                                .ok()
+                                .and_then(|v| v.value::<AnyNumeric>())
+                                .map(|v| v.to_string()),
+                        )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![(("Alex", 41), "170.00".to_string())]);
         });
     }
 }
@@ -2,14 +2,20 @@ use pgx::pg_sys;
 use pgx::prelude::{PgSqlErrorCode, Timestamp};
 use pgx::JsonB;
 use reqwest::{self, header, Url};
-use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, RequestBuilder};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
 use serde_json::{Map as JsonMap, Number, Value as JsonValue};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 use time::OffsetDateTime;
 
 use supabase_wrappers::prelude::*;
 
+// maximum page size limit for Stripe API
+const PAGE_SIZE: i64 = 100;
+
 fn create_client(api_key: &str) -> ClientWithMiddleware {
     let mut headers = header::HeaderMap::new();
     let value = format!("Bearer {}", api_key);
@@ -26,13 +32,247 @@ fn create_client(api_key: &str) -> ClientWithMiddleware {
         .build()
 }
 
+// all the supported Stripe objects
+const OBJECTS: &[&str] = &[
+    "accounts",
+    "balance",
+    "balance_transactions",
+    "charges",
+    "customers",
+    "disputes",
+    "events",
+    "files",
+    "file_links",
+    "invoices",
+    "mandates",
+    "payment_intents",
+    "payouts",
+    "products",
+    "refunds",
+    "setup_attempts",
+    "setup_intents",
+    "subscriptions",
+    "tokens",
+    "topups",
+    "transfers",
+];
+
+// Stripe objects which support data modify
+const MODIFIABLE_OBJECTS: &[&str] = &["customers", "products", "subscriptions"];
+
+// get the normal column definitions of a Stripe object, 'attrs' column is not included
+fn object_columns(obj: &str) -> Option<Vec<(&'static str, &'static str)>> {
+    let cols = match obj {
+        "accounts" => vec![
+            ("id", "string"),
+            ("business_type", "string"),
+            ("country", "string"),
+            ("email", "string"),
+            ("type", "string"),
+            ("created", "timestamp"),
+        ],
+        "balance" => vec![
+            ("balance_type", "string"),
+            ("amount", "i64"),
+            ("currency", "string"),
+        ],
+        "balance_transactions" => vec![
+            ("id", "string"),
+            ("amount", "i64"),
+            ("currency", "string"),
+            ("description", "string"),
+            ("fee", "i64"),
+            ("net", "i64"),
+            ("status", "string"),
+            ("type", "string"),
+            ("created", "timestamp"),
+        ],
+        "charges" => vec![
+            ("id", "string"),
+            ("amount", "i64"),
+            ("currency", "string"),
+            ("customer", "string"),
+            ("description", "string"),
+            ("invoice", "string"),
+            ("payment_intent", "string"),
+            ("status", "string"),
+            ("created", "timestamp"),
+        ],
+        "customers" => vec![
+            ("id", "string"),
+            ("email", "string"),
+            ("name", "string"),
+            ("description", "string"),
+            ("created", "timestamp"),
+        ],
+        "disputes" => vec![
+            ("id", "string"),
+            ("amount", "i64"),
+            ("currency", "string"),
+            ("charge", "string"),
+            ("payment_intent", "string"),
+            ("reason", "string"),
+            ("status", "string"),
+            ("created", "timestamp"),
+        ],
+        "events" => vec![
+            ("id", "string"),
+            ("type", "string"),
+            ("api_version", "string"),
+            ("created", "timestamp"),
+        ],
+        "files" => vec![
+            ("id", "string"),
+            ("filename", "string"),
+            ("purpose", "string"),
+            ("title", "string"),
+            ("size", "i64"),
+            ("type", "string"),
+            ("url", "string"),
+            ("created", "timestamp"),
+            ("expires_at", "timestamp"),
+        ],
+        "file_links" => vec![
+            ("id", "string"),
+            ("file", "string"),
+            ("url", "string"),
+            ("created", "timestamp"),
+            ("expired", "bool"),
+            ("expires_at", "timestamp"),
+        ],
+        "invoices" => vec![
+            ("id", "string"),
+            ("customer", "string"),
+            ("subscription", "string"),
+            ("status", "string"),
+            ("total", "i64"),
+            ("currency", "string"),
+            ("period_start", "timestamp"),
+            ("period_end", "timestamp"),
+        ],
+        "mandates" => vec![
+            ("id", "string"),
+            ("payment_method", "string"),
+            ("status", "string"),
+            ("type", "string"),
+        ],
+        "payment_intents" => vec![
+            ("id", "string"),
+            ("customer", "string"),
+            ("amount", "i64"),
+            ("currency", "string"),
+            ("payment_method", "string"),
+            ("created", "timestamp"),
+        ],
+        "payouts" => vec![
+            ("id", "string"),
+            ("amount", "i64"),
+            ("currency", "string"),
+            ("arrival_date", "timestamp"),
+            ("description", "string"),
+            ("statement_descriptor", "string"),
+            ("status", "string"),
+            ("created", "timestamp"),
+        ],
+        "products" => vec![
+            ("id", "string"),
+            ("name", "string"),
+            ("active", "bool"),
+            ("default_price", "string"),
+            ("description", "string"),
+            ("created", "timestamp"),
+            ("updated", "timestamp"),
+        ],
+        "refunds" => vec![
+            ("id", "string"),
+            ("amount", "i64"),
+            ("currency", "string"),
+            ("charge", "string"),
+            ("payment_intent", "string"),
+            ("reason", "string"),
+            ("status", "string"),
+            ("created", "timestamp"),
+        ],
+        "setup_attempts" => vec![
+            ("id", "string"),
+            ("application", "string"),
+            ("customer", "string"),
+            ("on_behalf_of", "string"),
+            ("payment_method", "string"),
+            ("setup_intent", "string"),
+            ("status", "string"),
+            ("usage", "string"),
+            ("created", "timestamp"),
+        ],
+        "setup_intents" => vec![
+            ("id", "string"),
+            ("client_secret", "string"),
+            ("customer", "string"),
+            ("description", "string"),
+            ("payment_method", "string"),
+            ("status", "string"),
+            ("usage", "string"),
+            ("created", "timestamp"),
+        ],
+        "subscriptions" => vec![
+            ("id", "string"),
+            ("customer", "string"),
+            ("currency", "string"),
+            ("current_period_start", "timestamp"),
+            ("current_period_end", "timestamp"),
+        ],
+        "tokens" => vec![
+            ("id", "string"),
+            ("type", "string"),
+            ("client_ip", "string"),
+            ("used", "bool"),
+            ("created", "timestamp"),
+        ],
+        "topups" => vec![
+            ("id", "string"),
+            ("amount", "i64"),
+            ("currency", "string"),
+            ("description", "string"),
+            ("status", "string"),
+            ("created", "timestamp"),
+        ],
+        "transfers" => vec![
+            ("id", "string"),
+            ("amount", "i64"),
+            ("currency", "string"),
+            ("description", "string"),
+            ("destination", "string"),
+            ("created", "timestamp"),
+        ],
+        _ => return None,
+    };
+    Some(cols)
+}
+
+// parse response body text to JSON
+fn parse_body(resp_body: &str) -> FdwResult<JsonValue> {
+    serde_json::from_str(resp_body).map_err(|err| {
+        FdwError::new(
+            PgSqlErrorCode::ERRCODE_FDW_ERROR,
+            format!("parse response body failed: {}", err),
+        )
+    })
+}
+
+fn invalid_response(msg: &str) -> FdwError {
+    FdwError::new(
+        PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+        format!("invalid response: {}", msg),
+    )
+}
+
 fn body_to_rows(
     resp_body: &str,
     normal_cols: Vec<(&str, &str)>,
     tgt_cols: &[Column],
-) -> (Vec<Row>, Option<String>, Option<bool>) {
+) -> FdwResult<(Vec<Row>, Option<String>, Option<bool>)> {
     let mut result = Vec::new();
-    let value: JsonValue = serde_json::from_str(resp_body).unwrap();
+    let value = parse_body(resp_body)?;
     let is_list = value
         .as_object()
         .and_then(|v| v.get("object"))
@@ -57,8 +297,10 @@ fn body_to_rows(
                 .as_object()
                 .and_then(|v| v.get(*bal_type))
                 .and_then(|v| v.as_array())
-                .map(|v| v[0].as_object().unwrap().clone())
-                .unwrap();
+                .and_then(|v| v.first())
+                .and_then(|v| v.as_object())
+                .cloned()
+                .ok_or_else(|| invalid_response(&format!("balance has no {} object", bal_type)))?;
             obj.insert(
                 "balance_type".to_string(),
                 JsonValue::String(bal_type.to_string()),
@@ -71,14 +313,14 @@ fn body_to_rows(
         value
             .as_object()
             .map(|v| vec![JsonValue::Object(v.clone())])
-            .unwrap()
+            .ok_or_else(|| invalid_response("not an object"))?
     };
     let objs = if is_list {
         value
             .as_object()
             .and_then(|v| v.get("data"))
             .and_then(|v| v.as_array())
-            .unwrap()
+            .ok_or_else(|| invalid_response("list has no data array"))?
     } else {
         &single_wrapped
     };
@@ -94,22 +336,19 @@ fn body_to_rows(
                 let cell = obj
                     .as_object()
                     .and_then(|v| v.get(*col_name))
-                    .and_then(|v| match *col_type {
-                        "bool" => v.as_bool().map(Cell::Bool),
-                        "i64" => v.as_i64().map(Cell::I64),
-                        "string" => v.as_str().map(|a| Cell::String(a.to_owned())),
-                        "timestamp" => v.as_i64().map(|a| {
-                            let dt = OffsetDateTime::from_unix_timestamp(a).unwrap();
-                            let ts = Timestamp::try_from(dt).unwrap();
-                            Cell::Timestamp(ts)
-                        }),
-                        _ => None,
-                    });
+                    .map(|v| match *col_type {
+                        "bool" => Ok(v.as_bool().map(Cell::Bool)),
+                        "i64" => Ok(v.as_i64().map(Cell::I64)),
+                        "string" => Ok(v.as_str().map(|a| Cell::String(a.to_owned()))),
+                        "timestamp" => v.as_i64().map(unix_to_timestamp).transpose(),
+                        _ => Ok(None),
+                    })
+                    .transpose()?
+                    .flatten();
                 row.push(col_name, cell);
             } else if &tgt_col.name == "attrs" {
                 // put all properties into 'attrs' JSON column
-                let attrs = serde_json::from_str(&obj.to_string()).unwrap();
-                row.push("attrs", Some(Cell::Json(JsonB(attrs))));
+                row.push("attrs", Some(Cell::Json(JsonB(obj.clone()))));
             }
         }
 
@@ -131,10 +370,32 @@ fn body_to_rows(
         .and_then(|v| v.get("has_more"))
         .and_then(|v| v.as_bool());
 
-    (result, cursor, has_more)
+    Ok((result, cursor, has_more))
+}
+
+// convert unix timestamp in seconds to timestamp cell
+fn unix_to_timestamp(secs: i64) -> FdwResult<Cell> {
+    OffsetDateTime::from_unix_timestamp(secs)
+        .map_err(|err| err.to_string())
+        .and_then(|dt| Timestamp::try_from(dt).map_err(|err| err.to_string()))
+        .map(Cell::Timestamp)
+        .map_err(|err| {
+            FdwError::new(
+                PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+                format!("invalid timestamp {}: {}", secs, err),
+            )
+        })
 }
 
-fn row_to_body(row: &Row) -> JsonValue {
+// Stripe object id is always a string
+fn invalid_rowid(rowid: &Cell) -> FdwError {
+    FdwError::new(
+        PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+        format!("invalid rowid {}, it must be a string", rowid),
+    )
+}
+
+fn row_to_body(row: &Row) -> FdwResult<JsonValue> {
     let mut map = JsonMap::new();
 
     for (col_name, cell) in row.iter() {
@@ -159,17 +420,65 @@ fn row_to_body(row: &Row) -> JsonValue {
                     }
                 }
                 _ => {
-                    report_error(
+                    return Err(FdwError::new(
                         PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
-                        &format!("field type {:?} not supported", cell),
-                    );
-                    return JsonValue::Null;
+                        format!("field type {:?} not supported", cell),
+                    ));
                 }
             }
         }
     }
 
-    JsonValue::Object(map)
+    Ok(JsonValue::Object(map))
+}
+
+// get the fields which can be pushed down for an object, other than id
+// ref: https://stripe.com/docs/api/[object]/list
+fn pushdown_fields(obj: &str) -> FdwResult<Vec<&'static str>> {
+    let fields = match obj {
+        "accounts" => vec![],
+        "balance" => vec![],
+        "balance_transactions" => vec!["type"],
+        "charges" => vec!["customer"],
+        "customers" => vec!["email"],
+        "disputes" => vec!["charge", "payment_intent"],
+        "events" => vec!["type"],
+        "files" => vec!["purpose"],
+        "file_links" => vec![],
+        "invoices" => vec!["customer", "status", "subscription"],
+        "mandates" => vec![],
+        "payment_intents" => vec!["customer"],
+        "payouts" => vec!["status"],
+        "products" => vec!["active"],
+        "refunds" => vec!["charge", "payment_intent"],
+        "setup_attempts" => vec!["setup_intent"],
+        "setup_intents" => vec!["customer", "payment_method"],
+        "subscriptions" => vec!["customer", "price", "status"],
+        "tokens" => vec![],
+        "topups" => vec!["status"],
+        "transfers" => vec!["destination"],
+        _ => {
+            return Err(FdwError::new(
+                PgSqlErrorCode::ERRCODE_FDW_TABLE_NOT_FOUND,
+                format!("'{}' object is not implemented", obj),
+            ));
+        }
+    };
+    Ok(fields)
+}
+
+// check if a qual is pushed down to Stripe API by pushdown_quals()
+fn is_pushed_down(qual: &Qual, quals: &[Qual], fields: &[&str]) -> bool {
+    if qual.operator != "=" || qual.use_or {
+        return false;
+    }
+    match &qual.value {
+        Value::Cell(Cell::String(_)) => {
+            (quals.len() == 1 && qual.field == "id") || fields.contains(&qual.field.as_str())
+        }
+        Value::Cell(Cell::Bool(_)) => fields.contains(&qual.field.as_str()),
+        _ => false,
+    }
 }
 
 fn pushdown_quals(
@@ -223,14 +532,74 @@ fn pushdown_quals(
     }
 }
 
-macro_rules! report_request_error {
-    ($err:ident) => {{
-        report_error(
-            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-            &format!("request failed: {}", $err),
-        );
-        return;
-    }};
+// send a request to Stripe API and return the response body
+fn send_request(rt: &Runtime, req: RequestBuilder) -> FdwResult<String> {
+    rt.block_on(req.send())
+        .and_then(|resp| resp.error_for_status().map_err(From::from))
+        .and_then(|resp| rt.block_on(resp.text()).map_err(From::from))
+        .map_err(|err| format!("request failed: {}", err).into())
+}
+
+// get the number of pages to fetch, None if no rows are needed
+fn page_count(limit: &Option<Limit>) -> Option<i64> {
+    match limit {
+        Some(limit) if limit.count == 0 => None,
+        Some(limit) => Some((limit.offset + limit.count) / PAGE_SIZE + 1),
+        // if no limit specified, fetch all records
+        None => Some(i64::MAX),
+    }
+}
+
+// response bodies of all pages and the number of requests sent
+type Pages = (Vec<String>, usize);
+
+// fetch the response bodies of up to `page_cnt` pages starting from `url`,
+// the fetch stops before the next page once `stop` is set
+//
+// This doesn't call any Postgres functions, so it can be run in a background
+// thread for asynchronous scan.
+fn fetch_pages(
+    rt: &Runtime,
+    client: &ClientWithMiddleware,
+    obj: &str,
+    url: Url,
+    page_cnt: i64,
+    stop: &AtomicBool,
+) -> FdwResult<Pages> {
+    let normal_cols = object_columns(obj).ok_or_else(|| {
+        FdwError::new(
+            PgSqlErrorCode::ERRCODE_FDW_TABLE_NOT_FOUND,
+            format!("'{}' object is not implemented", obj),
+        )
+    })?;
+    let mut bodies = Vec::new();
+    let mut requests = 0;
+    let mut cursor: Option<String> = None;
+
+    while (requests as i64) < page_cnt && !stop.load(Ordering::Relaxed) {
+        let mut url = url.clone();
+        if let Some(ref cursor) = cursor {
+            url.query_pairs_mut().append_pair("starting_after", cursor);
+        }
+
+        // make api call
+        let body = send_request(rt, client.get(url))?;
+        requests += 1;
+
+        // only the cursor and 'has_more' are needed here, no target columns
+        // are extracted
+        let (rows, starting_after, has_more) = body_to_rows(&body, normal_cols.clone(), &[])?;
+        if rows.is_empty() {
+            break;
+        }
+        bodies.push(body);
+        if has_more != Some(true) {
+            break;
+        }
+        cursor = starting_after;
+    }
+
+    Ok((bodies, requests))
 }
 
 #[wrappers_fdw(
@@ -241,57 +610,45 @@ macro_rules! report_request_error {
 pub(crate) struct StripeFdw {
     rt: Runtime,
     base_url: Url,
-    client: Option<ClientWithMiddleware>,
+    client: ClientWithMiddleware,
     scan_result: Option<Vec<Row>>,
+    scan_thread: Option<JoinHandle<FdwResult<Pages>>>,
+    scan_stop: Arc<AtomicBool>,
+    scan_cols: Vec<Column>,
     obj: String,
     rowid_col: String,
+    requests: usize,
 }
 
 impl StripeFdw {
+    // send a request to Stripe API and return the response body
+    fn send_request(&mut self, req: RequestBuilder) -> FdwResult<String> {
+        self.requests += 1;
+        send_request(&self.rt, req)
+    }
+
     fn build_url(
         &self,
         obj: &str,
         quals: &[Qual],
         page_size: i64,
         cursor: &Option<String>,
-    ) -> Option<Url> {
+    ) -> FdwResult<Url> {
         let mut url = self.base_url.join(obj).unwrap();
 
-        // pushdown quals other than id
-        // ref: https://stripe.com/docs/api/[object]/list
-        let fields = match obj {
-            "accounts" => vec![],
-            "balance" => vec![],
-            "balance_transactions" => vec!["type"],
-            "charges" => vec!["customer"],
-            "customers" => vec!["email"],
-            "disputes" => vec!["charge", "payment_intent"],
-            "events" => vec!["type"],
-            "files" => vec!["purpose"],
-            "file_links" => vec![],
-            "invoices" => vec!["customer", "status", "subscription"],
-            "mandates" => vec![],
-            "payment_intents" => vec!["customer"],
-            "payouts" => vec!["status"],
-            "products" => vec!["active"],
-            "refunds" => vec!["charge", "payment_intent"],
-            "setup_attempts" => vec!["setup_intent"],
-            "setup_intents" => vec!["customer", "payment_method"],
-            "subscriptions" => vec!["customer", "price", "status"],
-            "tokens" => vec![],
-            "topups" => vec!["status"],
-            "transfers" => vec!["destination"],
-            _ => {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_TABLE_NOT_FOUND,
-                    &format!("'{}' object is not implemented", obj),
-                );
-                return None;
-            }
-        };
+        let fields = pushdown_fields(obj)?;
         pushdown_quals(&mut url, obj, quals, fields, page_size, cursor);
 
-        Some(url)
+        Ok(url)
+    }
+
+    // EXPLAIN properties of the API endpoint
+    fn explain_url(&self, url: Url) -> Vec<(String, String)> {
+        let mut ret = vec![("Remote URL".to_string(), url.to_string())];
+        if self.requests > 0 {
+            ret.push(("Remote Requests".to_string(), self.requests.to_string()));
+        }
+        ret
     }
 
     // convert response body text to rows
@@ -300,286 +657,51 @@ impl StripeFdw {
         obj: &str,
         resp_body: &str,
         tgt_cols: &[Column],
-    ) -> (Vec<Row>, Option<String>, Option<bool>) {
-        match obj {
-            "accounts" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("business_type", "string"),
-                    ("country", "string"),
-                    ("email", "string"),
-                    ("type", "string"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "balance" => body_to_rows(
-                resp_body,
-                vec![
-                    ("balance_type", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                ],
-                tgt_cols,
-            ),
-            "balance_transactions" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("description", "string"),
-                    ("fee", "i64"),
-                    ("net", "i64"),
-                    ("status", "string"),
-                    ("type", "string"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "charges" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("customer", "string"),
-                    ("description", "string"),
-                    ("invoice", "string"),
-                    ("payment_intent", "string"),
-                    ("status", "string"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "customers" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("email", "string"),
-                    ("name", "string"),
-                    ("description", "string"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "disputes" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("charge", "string"),
-                    ("payment_intent", "string"),
-                    ("reason", "string"),
-                    ("status", "string"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "events" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("type", "string"),
-                    ("api_version", "string"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "files" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("filename", "string"),
-                    ("purpose", "string"),
-                    ("title", "string"),
-                    ("size", "i64"),
-                    ("type", "string"),
-                    ("url", "string"),
-                    ("created", "timestamp"),
-                    ("expires_at", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "file_links" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("file", "string"),
-                    ("url", "string"),
-                    ("created", "timestamp"),
-                    ("expired", "bool"),
-                    ("expires_at", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "invoices" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("customer", "string"),
-                    ("subscription", "string"),
-                    ("status", "string"),
-                    ("total", "i64"),
-                    ("currency", "string"),
-                    ("period_start", "timestamp"),
-                    ("period_end", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "mandates" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("payment_method", "string"),
-                    ("status", "string"),
-                    ("type", "string"),
-                ],
-                tgt_cols,
-            ),
-            "payment_intents" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("customer", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("payment_method", "string"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "payouts" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("arrival_date", "timestamp"),
-                    ("description", "string"),
-                    ("statement_descriptor", "string"),
-                    ("status", "string"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "products" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("name", "string"),
-                    ("active", "bool"),
-                    ("default_price", "string"),
-                    ("description", "string"),
-                    ("created", "timestamp"),
-                    ("updated", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "refunds" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("charge", "string"),
-                    ("payment_intent", "string"),
-                    ("reason", "string"),
-                    ("status", "string"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "setup_attempts" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("application", "string"),
-                    ("customer", "string"),
-                    ("on_behalf_of", "string"),
-                    ("payment_method", "string"),
-                    ("setup_intent", "string"),
-                    ("status", "string"),
-                    ("usage", "string"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "setup_intents" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("client_secret", "string"),
-                    ("customer", "string"),
-                    ("description", "string"),
-                    ("payment_method", "string"),
-                    ("status", "string"),
-                    ("usage", "string"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "subscriptions" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("customer", "string"),
-                    ("currency", "string"),
-                    ("current_period_start", "timestamp"),
-                    ("current_period_end", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "tokens" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("type", "string"),
-                    ("client_ip", "string"),
-                    ("used", "bool"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "topups" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("description", "string"),
-                    ("status", "string"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            "transfers" => body_to_rows(
-                resp_body,
-                vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("description", "string"),
-                    ("destination", "string"),
-                    ("created", "timestamp"),
-                ],
-                tgt_cols,
-            ),
-            _ => {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_TABLE_NOT_FOUND,
-                    &format!("'{}' object is not implemented", obj),
-                );
-                (Vec::new(), None, None)
-            }
+    ) -> FdwResult<(Vec<Row>, Option<String>, Option<bool>)> {
+        let normal_cols = object_columns(obj).ok_or_else(|| {
+            FdwError::new(
+                PgSqlErrorCode::ERRCODE_FDW_TABLE_NOT_FOUND,
+                format!("'{}' object is not implemented", obj),
+            )
+        })?;
+        body_to_rows(resp_body, normal_cols, tgt_cols)
+    }
+
+    // convert response bodies of all pages to scan result
+    fn bodies_to_rows(
+        &self,
+        obj: &str,
+        bodies: &[String],
+        tgt_cols: &[Column],
+    ) -> FdwResult<Vec<Row>> {
+        let mut result = Vec::new();
+        for body in bodies {
+            let (rows, _, _) = self.resp_to_rows(obj, body, tgt_cols)?;
+            result.extend(rows);
         }
+        Ok(result)
+    }
+
+    // convert a single object response body to a row with all the object's
+    // columns, used by RETURNING clause in data modify
+    fn resp_to_returning_row(&self, resp_body: &str) -> FdwResult<Row> {
+        let tgt_cols = object_columns(&self.obj)
+            .unwrap_or_default()
+            .iter()
+            .map(|(col_name, _)| col_name.to_string())
+            .chain(std::iter::once("attrs".to_string()))
+            .map(|name| Column {
+                name,
+                ..Default::default()
+            })
+            .collect::<Vec<Column>>();
+        let (mut rows, _, _) = self.resp_to_rows(&self.obj, resp_body, &tgt_cols)?;
+        Ok(rows.pop().unwrap_or_default())
     }
 }
 
 impl ForeignDataWrapper for StripeFdw {
-    fn new(options: &HashMap<String, String>) -> Self {
+    fn new(options: &HashMap<String, String>) -> FdwResult<Self> {
         let base_url = options
             .get("api_url")
             .map(|t| t.to_owned())
@@ -593,21 +715,54 @@ impl ForeignDataWrapper for StripeFdw {
                 }
             })
             .unwrap_or_else(|| "https://api.stripe.com/v1/".to_string());
+        let base_url = Url::parse(&base_url).map_err(|err| {
+            FdwError::new(
+                PgSqlErrorCode::ERRCODE_FDW_INVALID_OPTION_NAME,
+                format!("invalid api_url: {}", err),
+            )
+        })?;
         let client = match options.get("api_key") {
-            Some(api_key) => Some(create_client(api_key)),
-            None => require_option("api_key_id", options)
-                .and_then(|key_id| get_vault_secret(&key_id))
-                .map(|api_key| create_client(&api_key)),
+            Some(api_key) => create_client(api_key),
+            None => {
+                let key_id = require_option("api_key_id", options)?;
+                create_client(&get_vault_secret(&key_id)?)
+            }
         };
 
-        StripeFdw {
+        Ok(StripeFdw {
             rt: create_async_runtime(),
-            base_url: Url::parse(&base_url).unwrap(),
+            base_url,
             client,
             scan_result: None,
+            scan_thread: None,
+            scan_stop: Arc::new(AtomicBool::new(false)),
+            scan_cols: Vec::new(),
             obj: String::default(),
             rowid_col: String::default(),
-        }
+            requests: 0,
+        })
+    }
+
+    fn can_push_down_quals(
+        &mut self,
+        quals: &[Qual],
+        options: &HashMap<String, String>,
+    ) -> FdwResult<Vec<bool>> {
+        let obj = require_option("object", options)?;
+        let fields = pushdown_fields(&obj)?;
+        Ok(quals
+            .iter()
+            .map(|qual| is_pushed_down(qual, quals, &fields))
+            .collect())
+    }
+
+    fn get_rel_cost(&mut self, _options: &HashMap<String, String>) -> FdwResult<ScanCost> {
+        // each API call is a HTTP request returning one page of objects
+        Ok(ScanCost {
+            request_cost: 100.0,
+            request_size: 100,
+            ..Default::default()
+        })
     }
 
     fn begin_scan(
@@ -617,188 +772,243 @@ impl ForeignDataWrapper for StripeFdw {
         _sorts: &[Sort],
         limit: &Option<Limit>,
         options: &HashMap<String, String>,
-    ) {
-        let obj = if let Some(name) = require_option("object", options) {
-            name
-        } else {
-            return;
+    ) -> FdwResult<()> {
+        let obj = require_option("object", options)?;
+        let page_cnt = match page_count(limit) {
+            Some(page_cnt) => page_cnt,
+            None => return Ok(()),
         };
 
-        if let Some(client) = &self.client {
-            let page_size = 100; // maximum page size limit for Stripe API
-            let page_cnt = if let Some(limit) = limit {
-                if limit.count == 0 {
-                    return;
-                }
-                (limit.offset + limit.count) / page_size + 1
-            } else {
-                // if no limit specified, fetch all records
-                i64::MAX
-            };
-            let mut page = 0;
-            let mut result = Vec::new();
-            let mut cursor: Option<String> = None;
-
-            while page < page_cnt {
-                // build url
-                let url = self.build_url(&obj, quals, page_size, &cursor);
-                if url.is_none() {
-                    return;
-                }
-                let url = url.unwrap();
-
-                // make api call
-                match self.rt.block_on(client.get(url).send()) {
-                    Ok(resp) => match resp.error_for_status() {
-                        Ok(resp) => {
-                            let body = self.rt.block_on(resp.text()).unwrap();
-                            let (rows, starting_after, has_more) =
-                                self.resp_to_rows(&obj, &body, columns);
-                            if rows.is_empty() {
-                                break;
-                            }
-                            result.extend(rows);
-                            match has_more {
-                                Some(has_more) => {
-                                    if !has_more {
-                                        break;
-                                    }
-                                }
-                                None => break,
-                            }
-                            cursor = starting_after;
-                        }
-                        Err(err) => report_request_error!(err),
-                    },
-                    Err(err) => report_request_error!(err),
-                }
+        let url = self.build_url(&obj, quals, PAGE_SIZE, &None)?;
+        let (bodies, requests) = fetch_pages(
+            &self.rt,
+            &self.client,
+            &obj,
+            url,
+            page_cnt,
+            &AtomicBool::new(false),
+        )?;
+        self.requests += requests;
+        self.scan_result = Some(self.bodies_to_rows(&obj, &bodies, columns)?);
+
+        Ok(())
+    }
+
+    fn can_async_scan(&mut self, _options: &HashMap<String, String>) -> FdwResult<bool> {
+        Ok(true)
+    }
 
-                page += 1;
+    fn begin_async_scan(
+        &mut self,
+        quals: &[Qual],
+        columns: &[Column],
+        _sorts: &[Sort],
+        limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+        notifier: AsyncNotifier,
+    ) -> FdwResult<()> {
+        let obj = require_option("object", options)?;
+        let page_cnt = match page_count(limit) {
+            Some(page_cnt) => page_cnt,
+            None => {
+                notifier.notify();
+                return Ok(());
             }
+        };
 
-            self.scan_result = Some(result);
-        }
+        // fetch pages in a background thread with its own runtime, the
+        // responses are converted to rows later in iter_scan
+        let url = self.build_url(&obj, quals, PAGE_SIZE, &None)?;
+        let client = self.client.clone();
+        let thread_obj = obj.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        self.scan_stop = stop.clone();
+        self.scan_thread = Some(thread::spawn(move || {
+            let rt = create_async_runtime();
+            let ret = fetch_pages(&rt, &client, &thread_obj, url, page_cnt, &stop);
+            notifier.notify();
+            ret
+        }));
+        self.obj = obj;
+        self.scan_cols = columns.to_vec();
+
+        Ok(())
     }
 
-    fn iter_scan(&mut self, row: &mut Row) -> Option<()> {
+    fn iter_scan(&mut self, row: &mut Row) -> FdwResult<Option<()>> {
+        if let Some(scan_thread) = self.scan_thread.take() {
+            let (bodies, requests) = scan_thread.join().map_err(|_| {
+                FdwError::new(
+                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
+                    "asynchronous scan failed",
+                )
+            })??;
+            self.requests += requests;
+            self.scan_result = Some(self.bodies_to_rows(&self.obj, &bodies, &self.scan_cols)?);
+        }
         if let Some(ref mut result) = self.scan_result {
             if !result.is_empty() {
-                return result
+                return Ok(result
                     .drain(0..1)
                     .last()
-                    .map(|src_row| row.replace_with(src_row));
+                    .map(|src_row| row.replace_with(src_row)));
             }
         }
-        None
+        Ok(None)
     }
 
-    fn end_scan(&mut self) {
+    fn end_scan(&mut self) -> FdwResult<()> {
+        // stop a running background fetch before its next page, the thread is
+        // detached rather than joined so the backend doesn't wait for the
+        // request in flight, and its result is dropped
+        self.scan_stop.store(true, Ordering::Relaxed);
+        self.scan_thread.take();
         self.scan_result.take();
+        Ok(())
     }
 
-    fn begin_modify(&mut self, options: &HashMap<String, String>) {
-        self.obj = require_option("object", options).unwrap_or_default();
-        self.rowid_col = require_option("rowid_column", options).unwrap_or_default();
+    fn explain_scan(
+        &self,
+        quals: &[Qual],
+        _columns: &[Column],
+        _sorts: &[Sort],
+        _limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+        _verbose: bool,
+    ) -> FdwResult<Vec<(String, String)>> {
+        let obj = require_option("object", options)?;
+        let url = self.build_url(&obj, quals, PAGE_SIZE, &None)?;
+        Ok(self.explain_url(url))
     }
 
-    fn insert(&mut self, src: &Row) {
-        if let Some(ref mut client) = self.client {
-            let url = self.base_url.join(&self.obj).unwrap();
-            let body = row_to_body(src);
-            if body.is_null() {
-                return;
-            }
+    fn begin_modify(&mut self, options: &HashMap<String, String>) -> FdwResult<()> {
+        self.obj = require_option("object", options)?;
+        self.rowid_col = require_option("rowid_column", options)?;
+        Ok(())
+    }
 
-            // call Stripe API
-            match self.rt.block_on(client.post(url).form(&body).send()) {
-                Ok(resp) => match resp.error_for_status() {
-                    Ok(resp) => {
-                        let body = self.rt.block_on(resp.text()).unwrap();
-                        let json: JsonValue = serde_json::from_str(&body).unwrap();
-                        if let Some(id) = json.get("id").and_then(|v| v.as_str()) {
-                            report_info(&format!("inserted {} {}", self.obj, id));
-                        }
-                    }
-                    Err(err) => report_request_error!(err),
-                },
-                Err(err) => report_request_error!(err),
-            }
+    fn insert(&mut self, src: &Row) -> FdwResult<Option<Row>> {
+        let url = self.base_url.join(&self.obj).unwrap();
+        let body = row_to_body(src)?;
+
+        // call Stripe API
+        let body = self.send_request(self.client.post(url).form(&body))?;
+        let json = parse_body(&body)?;
+        if let Some(id) = json.get("id").and_then(|v| v.as_str()) {
+            report_info(&format!("inserted {} {}", self.obj, id));
         }
-    }
 
-    fn update(&mut self, rowid: &Cell, new_row: &Row) {
-        if let Some(ref mut client) = self.client {
-            match rowid {
-                Cell::String(rowid) => {
-                    let url = self
-                        .base_url
-                        .join(&format!("{}/", self.obj))
-                        .unwrap()
-                        .join(rowid)
-                        .unwrap();
-                    let body = row_to_body(new_row);
-                    if body.is_null() {
-                        return;
-                    }
+        // return the created object, so server-generated id can be returned
+        self.resp_to_returning_row(&body).map(Some)
+    }
 
-                    // call Stripe API
-                    match self.rt.block_on(client.post(url).form(&body).send()) {
-                        Ok(resp) => match resp.error_for_status() {
-                            Ok(resp) => {
-                                let body = self.rt.block_on(resp.text()).unwrap();
-                                let json: JsonValue = serde_json::from_str(&body).unwrap();
-                                if let Some(id) = json.get("id").and_then(|v| v.as_str()) {
-                                    report_info(&format!("updated {} {}", self.obj, id));
-                                }
-                            }
-                            Err(err) => report_request_error!(err),
-                        },
-                        Err(err) => report_request_error!(err),
-                    }
+    fn update(&mut self, rowid: &Cell, new_row: &Row) -> FdwResult<Option<Row>> {
+        match rowid {
+            Cell::String(rowid) => {
+                let url = self
+                    .base_url
+                    .join(&format!("{}/", self.obj))
+                    .unwrap()
+                    .join(rowid)
+                    .unwrap();
+                let body = row_to_body(new_row)?;
+
+                // call Stripe API
+                let body = self.send_request(self.client.post(url).form(&body))?;
+                let json = parse_body(&body)?;
+                if let Some(id) = json.get("id").and_then(|v| v.as_str()) {
+                    report_info(&format!("updated {} {}", self.obj, id));
                 }
-                _ => unreachable!(),
+                self.resp_to_returning_row(&body).map(Some)
             }
+            _ => Err(invalid_rowid(rowid)),
         }
     }
 
-    fn delete(&mut self, rowid: &Cell) {
-        if let Some(ref mut client) = self.client {
-            match rowid {
-                Cell::String(rowid) => {
-                    let url = self
-                        .base_url
-                        .join(&format!("{}/", self.obj))
-                        .unwrap()
-                        .join(rowid)
-                        .unwrap();
-
-                    // call Stripe API
-                    match self.rt.block_on(client.delete(url).send()) {
-                        Ok(resp) => match resp.error_for_status() {
-                            Ok(resp) => {
-                                let body = self.rt.block_on(resp.text()).unwrap();
-                                let json: JsonValue = serde_json::from_str(&body).unwrap();
-                                if let Some(id) = json.get("id").and_then(|v| v.as_str()) {
-                                    report_info(&format!("deleted {} {}", self.obj, id));
-                                }
-                            }
-                            Err(err) => report_request_error!(err),
-                        },
-                        Err(err) => report_request_error!(err),
-                    }
+    fn delete(&mut self, rowid: &Cell) -> FdwResult<Option<Row>> {
+        match rowid {
+            Cell::String(rowid) => {
+                let url = self
+                    .base_url
+                    .join(&format!("{}/", self.obj))
+                    .unwrap()
+                    .join(rowid)
+                    .unwrap();
+
+                // call Stripe API
+                let body = self.send_request(self.client.delete(url))?;
+                let json = parse_body(&body)?;
+                if let Some(id) = json.get("id").and_then(|v| v.as_str()) {
+                    report_info(&format!("deleted {} {}", self.obj, id));
                 }
-                _ => unreachable!(),
+
+                // deleted object only has id, so return it alone
+                let mut row = Row::new();
+                row.push("id", Some(Cell::String(rowid.to_owned())));
+                Ok(Some(row))
             }
+            _ => Err(invalid_rowid(rowid)),
         }
     }
 
-    fn end_modify(&mut self) {}
+    fn end_modify(&mut self) -> FdwResult<()> {
+        Ok(())
+    }
+
+    fn explain_modify(
+        &self,
+        options: &HashMap<String, String>,
+        _verbose: bool,
+    ) -> FdwResult<Vec<(String, String)>> {
+        let obj = require_option("object", options)?;
+        let url = self.base_url.join(&obj).unwrap();
+        Ok(self.explain_url(url))
+    }
+
+    fn import_foreign_schema(&mut self, stmt: ImportForeignSchemaStmt) -> FdwResult<Vec<String>> {
+        // Stripe objects are fixed, so remote schema name is not used here
+        let ret = OBJECTS
+            .iter()
+            .filter(|obj| stmt.is_table_included(obj))
+            .map(|obj| {
+                let mut cols = object_columns(obj)
+                    .unwrap()
+                    .iter()
+                    .map(|(col_name, col_type)| {
+                        let pg_type = match *col_type {
+                            "bool" => "boolean",
+                            "i64" => "bigint",
+                            "timestamp" => "timestamp",
+                            _ => "text",
+                        };
+                        format!("{} {}", quote_identifier(col_name), pg_type)
+                    })
+                    .collect::<Vec<String>>();
+                cols.push("attrs jsonb".to_string());
+
+                let mut opts = vec![format!("object {}", quote_literal(obj))];
+                if MODIFIABLE_OBJECTS.contains(obj) {
+                    opts.push("rowid_column 'id'".to_string());
+                }
+
+                format!(
+                    "create foreign table if not exists {} ({}) server {} options ({})",
+                    quote_identifier(obj),
+                    cols.join(", "),
+                    quote_identifier(&stmt.server_name),
+                    opts.join(", ")
+                )
+            })
+            .collect();
+        Ok(ret)
+    }
 
-    fn validator(options: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) {
+    fn validator(options: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) -> FdwResult<()> {
         if let Some(oid) = catalog {
             if oid == FOREIGN_TABLE_RELATION_ID {
-                check_options_contain(&options, "object");
+                check_options_contain(&options, "object")?;
             }
         }
+        Ok(())
     }
 }
@@ -503,6 +503,17 @@ mod tests {
                 .collect::<Vec<_>>();
             assert_eq!(results, vec!["cus_MJiBgSUgeWFN0z"]);
 
+            // test asynchronous scans under Append
+            let sql = "SELECT id FROM stripe_customers UNION ALL SELECT id FROM stripe_charges";
+            let plan = c
+                .select(&format!("EXPLAIN {}", sql), None, None)
+                .filter_map(|r| r.by_ordinal(1).ok().and_then(|v| v.value::<String>()))
+                .collect::<Vec<_>>();
+            assert!(plan
+                .iter()
+                .any(|line| line.contains("Async Foreign Scan on stripe_customers")));
+            assert_eq!(c.select(sql, None, None).len(), 2);
+
             let results = c
                 .select("SELECT * FROM stripe_disputes", None, None)
                 .filter_map(|r| {
@@ -758,6 +769,52 @@ mod tests {
                 )]
             );
 
+            // test import foreign schema
+            c.update("CREATE SCHEMA stripe_import", None, None);
+            c.update(
+                r#"
+                IMPORT FOREIGN SCHEMA stripe LIMIT TO (customers, topups)
+                FROM SERVER my_stripe_server INTO stripe_import
+                "#,
+                None,
+                None,
+            );
+            let results = c
+                .select(
+                    "SELECT foreign_table_name FROM information_schema.foreign_tables
+                     WHERE foreign_table_schema = 'stripe_import' ORDER BY 1",
+                    None,
+                    None,
+                )
+                .filter_map(|r| {
+                    r.by_name("foreign_table_name")
+                        .ok()
+                        .and_then(|v| v.value::<&str>())
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec!["customers", "topups"]);
+
+            let results = c
+                .select("SELECT * FROM stripe_import.topups", None, None)
+                .filter_map(|r| r.by_name("id").ok().and_then(|v| v.value::<&str>()))
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec!["tu_1Lb4leDciZwYG8GPbKaCK9X3"]);
+
+            // test insert with returning, the created object is returned by
+            // Stripe mock even though it is stateless
+            let id = c
+                .update(
+                    r#"
+                    INSERT INTO stripe_customers(email, name)
+                    VALUES ('test@test.com', 'test name') RETURNING id
+                    "#,
+                    None,
+                    None,
+                )
+                .first()
+                .get_one::<&str>();
+            assert!(id.map(|id| id.starts_with("cus_")).unwrap_or_default());
+
             // Stripe mock container is currently stateless, so we cannot test
             // data modify for now but will keep the code below for future use.
             //